@@ -0,0 +1,289 @@
+use std::fs;
+use std::mem::{align_of, size_of};
+
+
+/*
+* Memory layout report for the types defined in the book chapters.
+*
+* Each chapter registers its own types with the layout_of! macro, so the
+* table below always matches the real definitions. Field offsets come from
+* offset_of!, and padding is whatever is left over once every field's size
+* has been subtracted from the size of the whole struct. The macro also
+* destructures the struct with the listed fields and no `..`, so leaving a
+* field out (or adding one to the struct later) is a compile error rather
+* than bytes quietly counted as padding.
+*
+* A "niche" is a bit pattern the type can never hold, like a null reference
+* or a bool equal to 2. Option<T> uses the niche to store None, which is why
+* Option<&str> is the same size as &str.
+*/
+#[derive(Debug)]
+pub struct FieldLayout {
+    pub name: &'static str,
+    pub offset: usize,
+    pub size: usize,
+}
+
+#[derive(Debug)]
+pub struct TypeLayout {
+    pub name: String,
+    pub size: usize,
+    pub align: usize,
+    pub option_size: usize,
+    pub fields: Vec<FieldLayout>,
+}
+
+impl TypeLayout {
+
+    pub fn of<T>(name: &str) -> TypeLayout {
+        TypeLayout {
+            name: name.replace("'static ", "").replace(' ', ""),
+            size: size_of::<T>(),
+            align: align_of::<T>(),
+            option_size: size_of::<Option<T>>(),
+            fields: Vec::new(),
+        }
+    }
+
+    pub fn has_niche(&self) -> bool {
+        self.option_size == self.size
+    }
+
+    // Only structs list their fields. Enums report None here since their
+    // variants overlap and the compiler is free to arrange the tag.
+    pub fn padding(&self) -> Option<usize> {
+        if self.fields.is_empty() {
+            return None;
+        }
+        let used: usize = self.fields.iter().map(|f| f.size).sum();
+        self.size.checked_sub(used)
+    }
+
+    // The same padding worked out from the gaps between fields (which are
+    // sorted by offset) and after the last one. Fields that overlap or run
+    // past the end of the struct give None.
+    pub fn gaps(&self) -> Option<usize> {
+        let mut end: usize = 0;
+        let mut gaps: usize = 0;
+        for f in &self.fields {
+            gaps += f.offset.checked_sub(end)?;
+            end = f.offset + f.size;
+        }
+        Some(gaps + self.size.checked_sub(end)?)
+    }
+}
+
+
+// Lets the size of a field be read without needing a value of the type
+pub fn size_of_field<T, F>(_field: fn(&T) -> &F) -> usize {
+    size_of::<F>()
+}
+
+
+macro_rules! layout_of {
+    ($t:ty) => {
+        $crate::layout::TypeLayout::of::<$t>(stringify!($t))
+    };
+    ($t:ty { $($field:ident),+ $(,)? }) => {{
+        // Fails to compile unless every field is listed
+        #[allow(dead_code)]
+        type Listed = $t;
+        let _ = |v: &Listed| {
+            let Listed { $($field: _),+ } = v;
+        };
+        let mut layout = $crate::layout::TypeLayout::of::<$t>(stringify!($t));
+        $(
+            layout.fields.push($crate::layout::FieldLayout {
+                name: stringify!($field),
+                offset: std::mem::offset_of!($t, $field),
+                size: $crate::layout::size_of_field(|v: &$t| &v.$field),
+            });
+        )+
+        layout.fields.sort_by_key(|f| f.offset);
+        layout
+    }};
+}
+pub(crate) use layout_of;
+
+
+#[derive(Debug, Default)]
+pub struct Registry {
+    sections: Vec<(String, Vec<TypeLayout>)>,
+}
+
+impl Registry {
+
+    pub fn new() -> Registry {
+        Registry::default()
+    }
+
+    pub fn register(&mut self, section: &str, layout: TypeLayout) {
+        match self.sections.iter_mut().find(|(name, _)| name == section) {
+            Some((_, layouts)) => layouts.push(layout),
+            None => self.sections.push((section.to_string(), vec![layout])),
+        }
+    }
+
+    pub fn print_table(&self) {
+        println!(
+            "{:<28} {:>5} {:>6} {:>8} {:>8}  niche",
+            "type", "size", "align", "padding", "Option"
+        );
+        for (section, layouts) in &self.sections {
+            println!("-- {section} --");
+            for l in layouts {
+                let padding: String = l.padding()
+                    .map(|p| p.to_string())
+                    .unwrap_or(String::from("-"));
+                println!(
+                    "{:<28} {:>5} {:>6} {:>8} {:>8}  {}",
+                    l.name,
+                    l.size,
+                    l.align,
+                    padding,
+                    l.option_size,
+                    if l.has_niche() { "yes" } else { "no" }
+                );
+                for f in &l.fields {
+                    println!("    .{:<23} {:>5} @ {}", f.name, f.size, f.offset);
+                }
+            }
+        }
+    }
+
+    // An HTML fragment in the same style as the pages under docs/
+    pub fn to_html(&self) -> String {
+        let mut html: String = String::from("<h1>Type Layouts</h1>\n<p>\n");
+        html.push_str(
+            "  Generated by <code class=\"inline-code\">notes layout --html\
+             </code>.\n  Sizes are in bytes for the machine that ran it.\n\
+             </p>\n"
+        );
+        for (section, layouts) in &self.sections {
+            html.push_str(&format!("\n<h2>{}</h2>\n<table>\n", escape(section)));
+            html.push_str(
+                "  <tr><th>Type</th><th>Size</th><th>Align</th>\
+                 <th>Padding</th><th>Option&lt;T&gt;</th><th>Niche</th></tr>\n"
+            );
+            for l in layouts {
+                let padding: String = l.padding()
+                    .map(|p| p.to_string())
+                    .unwrap_or(String::from("-"));
+                html.push_str(&format!(
+                    "  <tr><td><code>{}</code></td><td>{}</td><td>{}</td>\
+                     <td>{}</td><td>{}</td><td>{}</td></tr>\n",
+                    escape(&l.name),
+                    l.size,
+                    l.align,
+                    padding,
+                    l.option_size,
+                    if l.has_niche() { "yes" } else { "no" }
+                ));
+                for f in &l.fields {
+                    html.push_str(&format!(
+                        "  <tr class=\"field\"><td><code>.{}</code></td>\
+                         <td>{}</td><td colspan=\"4\">at offset {}</td></tr>\n",
+                        f.name,
+                        f.size,
+                        f.offset
+                    ));
+                }
+            }
+            html.push_str("</table>\n");
+        }
+        html
+    }
+}
+
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+
+fn register_std(registry: &mut Registry) {
+    let section: &str = "Standard library";
+    registry.register(section, layout_of!(&str));
+    registry.register(section, layout_of!(Option<&str>));
+    registry.register(section, layout_of!(String));
+    registry.register(section, layout_of!(Option<String>));
+    registry.register(section, layout_of!(Box<u8>));
+    registry.register(section, layout_of!(Option<Box<u8>>));
+    registry.register(section, layout_of!(Option<u32>));
+    registry.register(section, layout_of!(bool));
+    registry.register(section, layout_of!(char));
+}
+
+
+pub fn build_registry() -> Registry {
+    let mut registry: Registry = Registry::new();
+    crate::the_book::register_layouts(&mut registry);
+    register_std(&mut registry);
+    registry
+}
+
+
+// notes layout              print the table
+// notes layout --html       print an HTML fragment
+// notes layout --html FILE  write the HTML fragment to FILE
+pub fn run(args: &[String]) {
+    let registry: Registry = build_registry();
+    match args.first().map(|s| s.as_str()) {
+        None => registry.print_table(),
+        Some("--html") => match args.get(1) {
+            Some(path) => match fs::write(path, registry.to_html()) {
+                Ok(_) => println!("Wrote {path}"),
+                Err(e) => println!("Failed to write {path}: {e}"),
+            },
+            None => print!("{}", registry.to_html()),
+        },
+        Some(other) => println!("Unknown layout option: {other}"),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Padded {
+        small: u8,
+        big: u64,
+        middle: u16,
+    }
+
+    #[test]
+    fn padding_is_the_gaps_between_fields() {
+        let layout: TypeLayout = layout_of!(Padded { small, big, middle });
+        assert_eq!(layout.size, size_of::<Padded>());
+        assert_eq!(layout.fields.len(), 3);
+        assert_eq!(layout.padding(), Some(size_of::<Padded>() - 11));
+        assert_eq!(layout.gaps(), layout.padding());
+        assert_eq!(layout_of!(u32).padding(), None);
+    }
+
+    #[test]
+    fn every_registered_struct_adds_up() {
+        let registry: Registry = build_registry();
+        for (_, layouts) in &registry.sections {
+            for l in layouts.iter().filter(|l| !l.fields.is_empty()) {
+                let used: usize = l.fields.iter().map(|f| f.size).sum();
+                assert_eq!(used + l.padding().unwrap(), l.size, "{}", l.name);
+                assert_eq!(l.gaps(), l.padding(), "{}", l.name);
+            }
+        }
+    }
+
+    #[test]
+    fn html_lists_fields() {
+        let mut registry: Registry = Registry::new();
+        registry.register("Test", layout_of!(Padded { small, big, middle }));
+        let html: String = registry.to_html();
+        assert!(html.contains("<code>Padded</code>"));
+        for field in [".small", ".big", ".middle"] {
+            assert!(html.contains(&format!("<code>{field}</code>")));
+        }
+    }
+}
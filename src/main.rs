@@ -2,11 +2,24 @@
 // learning project, there will be many unused files over time.
 #![allow(dead_code, unused_variables)]
 
+use std::env;
+
+mod layout;
+mod text;
 mod testing;
 mod the_book;
 
 
 fn main() {
-    the_book::chapter_11::run();
+    // Subcommands, e.g. `cargo run -- layout`. With no arguments, the
+    // current chapter runs like it always has.
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.first().map(|s| s.as_str()) {
+        Some("layout") => layout::run(&args[1..]),
+        Some("ip") => the_book::chapter_6::cidr::cli(&args[1..]),
+        Some("msg") => the_book::chapter_6::msg::cli(&args[1..]),
+        Some("users") => the_book::chapter_5::user_store::cli(&args[1..]),
+        Some(other) => println!("Unknown command: {other}"),
+        None => the_book::chapter_11::run(),
+    }
 }
-
@@ -0,0 +1,296 @@
+use super::{char_width, display_width, expand_tabs};
+use crate::the_book::chapter_4::wrap::{wrap, WrapOptions};
+
+
+/*
+* Renders text inside a box, like the birthday card in chapter_3::scope:
+*
+*   ┌─ TO: OUTER SCOPE ────┐
+*   │  Happy birthday      │
+*   │    -- INNER SCOPE    │
+*   └──────────────────────┘
+*
+* Everything is measured in display columns rather than bytes, so accented
+* letters, CJK text and emoji still line up with the border. Tabs are
+* expanded to spaces (stops every 4 columns) before anything is measured.
+*/
+const TAB_WIDTH: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Border {
+    Ascii,
+    Light,
+    Rounded,
+    Heavy,
+    Double,
+}
+
+// top left, top right, bottom left, bottom right, horizontal, vertical
+struct BorderChars(char, char, char, char, char, char);
+
+impl Border {
+    fn chars(&self) -> BorderChars {
+        match self {
+            Border::Ascii => BorderChars('+', '+', '+', '+', '-', '|'),
+            Border::Light => BorderChars('┌', '┐', '└', '┘', '─', '│'),
+            Border::Rounded => BorderChars('╭', '╮', '╰', '╯', '─', '│'),
+            Border::Heavy => BorderChars('┏', '┓', '┗', '┛', '━', '┃'),
+            Border::Double => BorderChars('╔', '╗', '╚', '╝', '═', '║'),
+        }
+    }
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+
+#[derive(Debug, Clone)]
+pub struct Card {
+    title: Option<String>,
+    body: String,
+    border: Border,
+    align: Align,
+    padding: usize,
+    width: Option<usize>,
+}
+
+impl Card {
+
+    pub fn new(body: &str) -> Card {
+        Card {
+            title: None,
+            body: body.to_string(),
+            border: Border::Light,
+            align: Align::Left,
+            padding: 1,
+            width: None,
+        }
+    }
+
+    pub fn title(mut self, title: &str) -> Card {
+        self.title = Some(title.to_string());
+        self
+    }
+
+    pub fn border(mut self, border: Border) -> Card {
+        self.border = border;
+        self
+    }
+
+    pub fn align(mut self, align: Align) -> Card {
+        self.align = align;
+        self
+    }
+
+    // Spaces between the border and the text on the left and right
+    pub fn padding(mut self, padding: usize) -> Card {
+        self.padding = padding;
+        self
+    }
+
+    // Width of the text area, not counting padding or borders. Lines longer
+    // than this are word wrapped (by chapter_4::wrap), and a single word
+    // that's wider still makes the card wider. Without a width the card
+    // fits its longest line.
+    pub fn width(mut self, width: usize) -> Card {
+        self.width = Some(width.max(1));
+        self
+    }
+
+    pub fn render(&self) -> String {
+        let b: BorderChars = self.border.chars();
+        let body: String = self.body
+            .lines()
+            .map(|line| expand_tabs(line, TAB_WIDTH))
+            .collect::<Vec<String>>()
+            .join("\n");
+        let lines: Vec<String> = match self.width {
+            Some(w) => wrap(&body, &WrapOptions::new(w))
+                .into_iter()
+                .map(String::from)
+                .collect(),
+            None => body.lines().map(|l| l.to_string()).collect(),
+        };
+        let widest: usize = lines.iter()
+            .map(|l| display_width(l))
+            .max()
+            .unwrap_or(0);
+
+        // The title sits in the top border as "─ title ", so it needs three
+        // more columns than its own width.
+        let title: Option<String> = self.title.as_deref()
+            .map(|t| expand_tabs(t, TAB_WIDTH));
+        let title_width: usize = title.as_deref()
+            .map(|t| display_width(t) + 3)
+            .unwrap_or(0);
+        // A char wider than the set width (a CJK char in a 1 column card)
+        // still has to fit, so the card grows to hold it
+        let inner: usize = match self.width {
+            Some(w) => w.max(widest),
+            None => widest.max(title_width.saturating_sub(self.padding * 2)),
+        };
+        let span: usize = inner + self.padding * 2;
+
+        let mut out: String = String::new();
+        out.push(b.0);
+        match &title {
+            Some(title) => {
+                let title: String = truncate(title, span.saturating_sub(3));
+                out.push(b.4);
+                out.push(' ');
+                out.push_str(&title);
+                out.push(' ');
+                let used: usize = display_width(&title) + 3;
+                out.extend(std::iter::repeat_n(b.4, span.saturating_sub(used)));
+            }
+            None => out.extend(std::iter::repeat_n(b.4, span)),
+        }
+        out.push(b.1);
+        out.push('\n');
+
+        let pad: String = " ".repeat(self.padding);
+        for line in &lines {
+            out.push(b.5);
+            out.push_str(&pad);
+            out.push_str(&align_line(line, inner, self.align));
+            out.push_str(&pad);
+            out.push(b.5);
+            out.push('\n');
+        }
+
+        out.push(b.2);
+        out.extend(std::iter::repeat_n(b.4, span));
+        out.push(b.3);
+        out
+    }
+}
+
+
+// A one line card for section headings in the lesson output
+pub fn header(title: &str) -> String {
+    Card::new(title)
+        .border(Border::Double)
+        .align(Align::Center)
+        .padding(2)
+        .render()
+}
+
+
+fn align_line(line: &str, width: usize, align: Align) -> String {
+    let gap: usize = width.saturating_sub(display_width(line));
+    let (left, right) = match align {
+        Align::Left => (0, gap),
+        Align::Center => (gap / 2, gap - gap / 2),
+        Align::Right => (gap, 0),
+    };
+    format!("{}{}{}", " ".repeat(left), line, " ".repeat(right))
+}
+
+
+// Cuts text down to a number of columns, marking the cut with an ellipsis
+fn truncate(text: &str, width: usize) -> String {
+    if display_width(text) <= width {
+        return text.to_string();
+    }
+    let mut out: String = String::new();
+    let mut used: usize = 0;
+    for ch in text.chars() {
+        if used + char_width(ch) + 1 > width {
+            break;
+        }
+        used += char_width(ch);
+        out.push(ch);
+    }
+    out.push('…');
+    out
+}
+
+
+pub fn run() {
+    println!("{}", header("TEXT CARDS"));
+    let body: &str = "Rust measures strings in bytes, but terminals measure \
+        them in columns. Zoë, 日本語 and 🦀 all line up anyway.";
+    for (border, align) in [
+        (Border::Light, Align::Left),
+        (Border::Rounded, Align::Center),
+        (Border::Ascii, Align::Right),
+    ] {
+        let card: Card = Card::new(body)
+            .title(&format!("{border:?} / {align:?}"))
+            .border(border)
+            .align(align)
+            .width(28);
+        println!("{}", card.render());
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Every line of a card has to be exactly as wide as the top border
+    fn assert_square(card: &str) {
+        let widths: Vec<usize> = card.lines().map(display_width).collect();
+        assert!(widths.iter().all(|w| *w == widths[0]), "{card}\n{widths:?}");
+    }
+
+    #[test]
+    fn renders_a_plain_card() {
+        let card: String = Card::new("hi\nthere").title("T").render();
+        assert_eq!(
+            card,
+            "┌─ T ───┐\n\
+             │ hi    │\n\
+             │ there │\n\
+             └───────┘"
+        );
+    }
+
+    #[test]
+    fn aligns_and_pads() {
+        let card: String = Card::new("ab\nabcd")
+            .border(Border::Ascii)
+            .align(Align::Right)
+            .padding(2)
+            .render();
+        assert_eq!(
+            card,
+            "+--------+\n\
+             |    ab  |\n\
+             |  abcd  |\n\
+             +--------+"
+        );
+        let centered: String = header("X");
+        assert_eq!(centered, "╔═════╗\n║  X  ║\n╚═════╝");
+    }
+
+    #[test]
+    fn wraps_to_the_width() {
+        let card: String = Card::new("one two three").width(7).render();
+        assert_eq!(card.lines().count(), 4);
+        assert!(card.contains("│ one two │"));
+        assert_square(&card);
+    }
+
+    #[test]
+    fn wide_chars_and_tabs_stay_inside_the_border() {
+        let cases: [Card; 5] = [
+            Card::new("日本語 and 🦀").width(5),
+            Card::new("日").width(1),
+            Card::new("a\tb\n\tc").title("tab\there"),
+            Card::new("e\u{301}\u{301}").title("Zoë"),
+            Card::new("").title("a long title").width(3),
+        ];
+        for card in cases {
+            assert_square(&card.render());
+        }
+        let tabbed: String = Card::new("a\tb").render();
+        assert!(tabbed.contains("│ a   b │"));
+    }
+}
@@ -0,0 +1,91 @@
+pub mod card;
+
+
+/*
+* How many terminal columns a piece of text takes up. This isn't the same as
+* its length in bytes ("é" is 2 bytes) or even in chars (a combining accent
+* takes no space, and CJK characters and most emoji take two columns).
+*
+* The ranges below are the common cases from Unicode's East Asian Width and
+* combining mark tables, not the full data files.
+*/
+pub fn char_width(ch: char) -> usize {
+    let cp: u32 = ch as u32;
+    if cp == 0 || ch.is_control() {
+        return 0;
+    }
+    if is_zero_width(cp) {
+        return 0;
+    }
+    if is_wide(cp) {
+        return 2;
+    }
+    1
+}
+
+pub fn display_width(text: &str) -> usize {
+    text.chars().map(char_width).sum()
+}
+
+// A tab is a control char, so char_width counts it as 0 even though a
+// terminal moves to the next tab stop. Expanding tabs into spaces first
+// gives text whose width can be measured.
+pub fn expand_tabs(line: &str, tab_width: usize) -> String {
+    let tab_width: usize = tab_width.max(1);
+    let mut out: String = String::new();
+    let mut column: usize = 0;
+    for ch in line.chars() {
+        if ch == '\t' {
+            let spaces: usize = tab_width - column % tab_width;
+            out.extend(std::iter::repeat_n(' ', spaces));
+            column += spaces;
+        } else {
+            out.push(ch);
+            column += char_width(ch);
+        }
+    }
+    out
+}
+
+
+fn is_zero_width(cp: u32) -> bool {
+    matches!(
+        cp,
+        0x0300..=0x036F         // combining diacritical marks
+            | 0x0483..=0x0489
+            | 0x0591..=0x05BD
+            | 0x0610..=0x061A
+            | 0x064B..=0x065F
+            | 0x1AB0..=0x1AFF
+            | 0x1DC0..=0x1DFF
+            | 0x200B..=0x200F   // zero width space, joiners, marks
+            | 0x202A..=0x202E
+            | 0x2060..=0x2064
+            | 0x20D0..=0x20FF
+            | 0xFE00..=0xFE0F   // variation selectors
+            | 0xFE20..=0xFE2F
+            | 0xFEFF
+            | 0xE0100..=0xE01EF
+    )
+}
+
+
+fn is_wide(cp: u32) -> bool {
+    matches!(
+        cp,
+        0x1100..=0x115F         // Hangul Jamo
+            | 0x2E80..=0x303E   // CJK radicals, punctuation
+            | 0x3041..=0x33FF   // Hiragana, Katakana, CJK compatibility
+            | 0x3400..=0x4DBF   // CJK extension A
+            | 0x4E00..=0x9FFF   // CJK unified ideographs
+            | 0xA000..=0xA4CF   // Yi
+            | 0xAC00..=0xD7A3   // Hangul syllables
+            | 0xF900..=0xFAFF   // CJK compatibility ideographs
+            | 0xFE30..=0xFE4F
+            | 0xFF00..=0xFF60   // fullwidth forms
+            | 0xFFE0..=0xFFE6
+            | 0x1F300..=0x1F64F // symbols, pictographs, emoticons
+            | 0x1F900..=0x1F9FF
+            | 0x20000..=0x3FFFD // CJK extensions B and up
+    )
+}
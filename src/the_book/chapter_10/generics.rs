@@ -1,3 +1,5 @@
+use crate::layout::{layout_of, Registry};
+
 
 
 #[derive(Debug)]
@@ -50,3 +52,16 @@ pub fn run() {
     };
 }
 
+
+pub fn register_layouts(registry: &mut Registry) {
+    let section: &str = "Chapter 10";
+    registry.register(section, layout_of!(Point<u8> { x, y }));
+    registry.register(section, layout_of!(Point<i32> { x, y }));
+    registry.register(section, layout_of!(Point<f64> { x, y }));
+    registry.register(section, layout_of!(Point<&'static str> { x, y }));
+    registry.register(section, layout_of!(ComboPoint<u8, f64> { x, y }));
+    registry.register(section, layout_of!(Coordinate<u8>));
+    registry.register(section, layout_of!(Coordinate<i32>));
+    registry.register(section, layout_of!(Coordinate<f64>));
+    registry.register(section, layout_of!(Coordinate<&str>));
+}
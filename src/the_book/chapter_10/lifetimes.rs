@@ -1,3 +1,5 @@
+use crate::layout::{layout_of, Registry};
+
 
 struct ImportantExcerpt<'a> {
     part: &'a str,
@@ -36,3 +38,10 @@ pub fn run() {
     };
 }
 
+
+pub fn register_layouts(registry: &mut Registry) {
+    registry.register(
+        "Chapter 10",
+        layout_of!(ImportantExcerpt<'static> { part })
+    );
+}
@@ -1,3 +1,5 @@
+use crate::layout::Registry;
+
 pub mod generics;
 pub mod traits;
 pub mod lifetimes;
@@ -9,3 +11,9 @@ pub fn run() {
     lifetimes::run();
 }
 
+
+pub fn register_layouts(registry: &mut Registry) {
+    generics::register_layouts(registry);
+    traits::register_layouts(registry);
+    lifetimes::register_layouts(registry);
+}
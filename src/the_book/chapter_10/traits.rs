@@ -1,4 +1,5 @@
 use std::fmt::{Display, Debug};
+use crate::layout::{layout_of, Registry};
 
 
 trait PersonTitle {
@@ -86,3 +87,9 @@ pub fn run() {
     some_function(&t, &u);
 }
 
+
+pub fn register_layouts(registry: &mut Registry) {
+    registry.register("Chapter 10", layout_of!(Civilian { name }));
+    registry.register("Chapter 10", layout_of!(Doctor { name }));
+    registry.register("Chapter 10", layout_of!(Soldier { name }));
+}
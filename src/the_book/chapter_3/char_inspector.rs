@@ -0,0 +1,490 @@
+use std::ops::Range;
+use crate::layout::{layout_of, Registry};
+use crate::text::card::header;
+
+
+/*
+* A Rust char is a Unicode scalar value, always 4 bytes in memory. A String
+* stores those same chars as UTF-8, which uses 1 to 4 bytes per char. That
+* mismatch is why a String can't be indexed by position, and why slicing
+* with byte ranges panics if a range cuts a char in half.
+*/
+#[derive(Debug)]
+pub struct CharInfo {
+    pub ch: char,
+    pub byte_offset: usize,
+    pub utf8: Vec<u8>,
+    pub utf16: Vec<u16>,
+    pub category: &'static str,
+}
+
+impl CharInfo {
+
+    pub fn new(ch: char, byte_offset: usize) -> CharInfo {
+        let mut utf8_buf: [u8; 4] = [0; 4];
+        let mut utf16_buf: [u16; 2] = [0; 2];
+        CharInfo {
+            ch,
+            byte_offset,
+            utf8: ch.encode_utf8(&mut utf8_buf).as_bytes().to_vec(),
+            utf16: ch.encode_utf16(&mut utf16_buf).to_vec(),
+            category: general_category(ch),
+        }
+    }
+
+    pub fn code_point(&self) -> String {
+        format!("U+{:04X}", self.ch as u32)
+    }
+
+    pub fn print(&self) {
+        let utf8: Vec<String> = self.utf8.iter()
+            .map(|b| format!("{b:02X}"))
+            .collect();
+        let utf16: Vec<String> = self.utf16.iter()
+            .map(|u| format!("{u:04X}"))
+            .collect();
+        println!(
+            "  {:>3}  {:<8} {:<12} {:<10} {}  alpha={} num={} space={}  {:?}",
+            self.byte_offset,
+            self.code_point(),
+            utf8.join(" "),
+            utf16.join(" "),
+            self.category,
+            self.ch.is_alphabetic(),
+            self.ch.is_numeric(),
+            self.ch.is_whitespace(),
+            self.ch
+        );
+    }
+}
+
+
+pub fn inspect(text: &str) -> Vec<CharInfo> {
+    text.char_indices()
+        .map(|(i, ch)| CharInfo::new(ch, i))
+        .collect()
+}
+
+
+/*
+* The standard library doesn't expose the Unicode general category, so this
+* works it out from what char does provide, plus a few well known ranges.
+* ASCII is looked up in a full table and is exact. Beyond ASCII it's a
+* best guess: controls and separators come straight from char, letters
+* are sorted by case with short lists for the titlecase (Lt) and modifier
+* (Lm) letters, and letters that are also numbers (Roman numerals) are Nl.
+* Decimal digits are recognised by the blocks of ten they come in. Marks,
+* format chars, currency and math signs, brackets, quotes and punctuation
+* come from the tables below, which cover every Sm, Ps, Pe, Pi and Pf char
+* as of Unicode 14. Noncharacters and the empty planes are unassigned (Cn),
+* but gaps inside the other planes can't be told apart from symbols
+* without the full Unicode tables, so they come out as So like whatever
+* else is left.
+*/
+pub fn general_category(ch: char) -> &'static str {
+    let cp: u32 = ch as u32;
+    if ch.is_ascii() {
+        return ascii_category(ch);
+    }
+    if ch.is_control() {
+        return "Cc";
+    }
+    if ch.is_whitespace() {
+        return match cp {
+            0x2028 => "Zl",
+            0x2029 => "Zp",
+            _ => "Zs",
+        };
+    }
+    if is_combining_mark(cp) {
+        return "Mn";
+    }
+    if ch.is_alphabetic() {
+        if ch.is_numeric() {
+            return "Nl";
+        }
+        if is_titlecase(cp) {
+            return "Lt";
+        }
+        if is_modifier_letter(cp) {
+            return "Lm";
+        }
+        if ch.is_uppercase() {
+            return "Lu";
+        }
+        if ch.is_lowercase() {
+            return "Ll";
+        }
+        return "Lo";
+    }
+    if ch.is_numeric() {
+        return if is_decimal_digit(cp) { "Nd" } else { "No" };
+    }
+    if let Some(category) = bracket_category(cp) {
+        return category;
+    }
+    match cp {
+        0x00AD | 0x200B..=0x200F | 0x202A..=0x202E | 0x2060..=0x2064
+            | 0xFEFF | 0xE0001 | 0xE0020..=0xE007F => "Cf",
+        _ if is_unassigned(cp) => "Cn",
+        0xE000..=0xF8FF | 0xF0000..=0x10FFFF => "Co",
+        0x00A2..=0x00A5 | 0x20A0..=0x20C0 => "Sc",
+        _ if is_math_symbol(cp) => "Sm",
+        0x2010..=0x2015 => "Pd",
+        _ if is_punctuation_block(cp) => "Po",
+        _ => "So",
+    }
+}
+
+
+fn ascii_category(ch: char) -> &'static str {
+    match ch {
+        '\0'..='\x1f' | '\x7f' => "Cc",
+        ' ' => "Zs",
+        'A'..='Z' => "Lu",
+        'a'..='z' => "Ll",
+        '0'..='9' => "Nd",
+        '$' => "Sc",
+        '+' | '<' | '=' | '>' | '|' | '~' => "Sm",
+        '^' | '`' => "Sk",
+        '(' | '[' | '{' => "Ps",
+        ')' | ']' | '}' => "Pe",
+        '-' => "Pd",
+        '_' => "Pc",
+        _ => "Po",
+    }
+}
+
+
+// Letters like the Dz in "Dzavo" that are neither upper nor lower case
+fn is_titlecase(cp: u32) -> bool {
+    matches!(
+        cp,
+        0x01C5 | 0x01C8 | 0x01CB | 0x01F2
+            | 0x1F88..=0x1F8F
+            | 0x1F98..=0x1F9F
+            | 0x1FA8..=0x1FAF
+            | 0x1FBC | 0x1FCC | 0x1FFC
+    )
+}
+
+
+// Spacing and superscript letters, like the raised h of an aspirated t
+fn is_modifier_letter(cp: u32) -> bool {
+    matches!(
+        cp,
+        0x02B0..=0x02C1
+            | 0x02C6..=0x02D1
+            | 0x02E0..=0x02E4
+            | 0x02EC | 0x02EE | 0x0374 | 0x037A | 0x0559 | 0x0640
+            | 0x1D2C..=0x1D6A
+            | 0x1D78
+            | 0x1D9B..=0x1DBF
+            | 0x2071 | 0x207F
+            | 0x2090..=0x209C
+            | 0x2C7C | 0x2C7D | 0x3005
+            | 0x3031..=0x3035
+            | 0x309D | 0x309E
+            | 0x30FC..=0x30FE
+            | 0xA717..=0xA71F
+            | 0xFF70 | 0xFF9E | 0xFF9F
+    )
+}
+
+
+// Decimal digits always come as a run of ten, 0 through 9. These are where
+// the runs start, apart from the mathematical digits which are five runs
+// back to back.
+const DIGIT_ZEROS: [u32; 37] = [
+    0x0660, 0x06F0, 0x07C0, 0x0966, 0x09E6, 0x0A66, 0x0AE6, 0x0B66, 0x0BE6,
+    0x0C66, 0x0CE6, 0x0D66, 0x0DE6, 0x0E50, 0x0ED0, 0x0F20, 0x1040, 0x1090,
+    0x17E0, 0x1810, 0x1946, 0x19D0, 0x1A80, 0x1A90, 0x1B50, 0x1BB0, 0x1C40,
+    0x1C50, 0xA620, 0xA8D0, 0xA900, 0xA9D0, 0xA9F0, 0xAA50, 0xABF0, 0xFF10,
+    0x104A0,
+];
+
+fn is_decimal_digit(cp: u32) -> bool {
+    (0x1D7CE..=0x1D7FF).contains(&cp)
+        || DIGIT_ZEROS.iter().any(|zero| (*zero..*zero + 10).contains(&cp))
+}
+
+
+fn is_combining_mark(cp: u32) -> bool {
+    matches!(
+        cp,
+        0x0300..=0x036F
+            | 0x1AB0..=0x1AFF
+            | 0x1DC0..=0x1DFF
+            | 0x20D0..=0x20FF
+            | 0xFE00..=0xFE0F
+            | 0xFE20..=0xFE2F
+            | 0xE0100..=0xE01EF
+    )
+}
+
+
+// Operators, relations and the arrows that are used as them
+fn is_math_symbol(cp: u32) -> bool {
+    matches!(
+        cp,
+        0x00AC | 0x00B1 | 0x00D7 | 0x00F7 | 0x03F6
+            | 0x0606..=0x0608
+            | 0x2044 | 0x2052
+            | 0x207A..=0x207C
+            | 0x208A..=0x208C
+            | 0x2118
+            | 0x2140..=0x2144
+            | 0x214B
+            | 0x2190..=0x2194
+            | 0x219A | 0x219B | 0x21A0 | 0x21A3 | 0x21A6 | 0x21AE
+            | 0x21CE | 0x21CF | 0x21D2 | 0x21D4
+            | 0x21F4..=0x22FF
+            | 0x2320 | 0x2321 | 0x237C
+            | 0x239B..=0x23B3
+            | 0x23DC..=0x23E1
+            | 0x25B7 | 0x25C1
+            | 0x25F8..=0x25FF
+            | 0x266F
+            | 0x27C0..=0x27C4
+            | 0x27C7..=0x27E5
+            | 0x27F0..=0x27FF
+            | 0x2900..=0x2982
+            | 0x2999..=0x29D7
+            | 0x29DC..=0x29FB
+            | 0x29FE..=0x2AFF
+            | 0x2B30..=0x2B44
+            | 0x2B47..=0x2B4C
+            | 0xFB29 | 0xFE62
+            | 0xFE64..=0xFE66
+            | 0xFF0B
+            | 0xFF1C..=0xFF1E
+            | 0xFF5C | 0xFF5E | 0xFFE2
+            | 0xFFE9..=0xFFEC
+            | 0x1D6C1 | 0x1D6DB | 0x1D6FB | 0x1D715 | 0x1D735 | 0x1D74F
+            | 0x1D76F | 0x1D789 | 0x1D7A9 | 0x1D7C3
+            | 0x1EEF0 | 0x1EEF1
+    )
+}
+
+
+// Most brackets come in runs of open, close, open, close... These are
+// the runs, first and last char. The rest are odd ones out, like the low
+// quotes that open (Ps) and the Arabic ornate parentheses, which are
+// mirrored.
+const BRACKET_RUNS: [(u32, u32); 24] = [
+    (0x0F3A, 0x0F3D), (0x169B, 0x169C), (0x2045, 0x2046), (0x207D, 0x207E),
+    (0x208D, 0x208E), (0x2308, 0x230B), (0x2329, 0x232A), (0x2768, 0x2775),
+    (0x27C5, 0x27C6), (0x27E6, 0x27EF), (0x2983, 0x2998), (0x29D8, 0x29DB),
+    (0x29FC, 0x29FD), (0x2E22, 0x2E29), (0x2E55, 0x2E5C), (0x3008, 0x3011),
+    (0x3014, 0x301B), (0xFE17, 0xFE18), (0xFE35, 0xFE44), (0xFE47, 0xFE48),
+    (0xFE59, 0xFE5E), (0xFF08, 0xFF09), (0xFF5F, 0xFF60), (0xFF62, 0xFF63),
+];
+
+// Opening (Ps) and closing (Pe) brackets, and the initial (Pi) and final
+// (Pf) quotes
+fn bracket_category(cp: u32) -> Option<&'static str> {
+    if let Some((first, _)) = BRACKET_RUNS.iter()
+        .find(|(first, last)| (*first..=*last).contains(&cp))
+    {
+        return Some(if (cp - first).is_multiple_of(2) { "Ps" } else { "Pe" });
+    }
+    match cp {
+        0x201A | 0x201E | 0x2E42 | 0x301D | 0xFD3F | 0xFF3B | 0xFF5B => {
+            Some("Ps")
+        }
+        0x301E | 0x301F | 0xFD3E | 0xFF3D | 0xFF5D => Some("Pe"),
+        0x00AB | 0x2018 | 0x201B | 0x201C | 0x201F | 0x2039 | 0x2E02
+            | 0x2E04 | 0x2E09 | 0x2E0C | 0x2E1C | 0x2E20 => Some("Pi"),
+        0x00BB | 0x2019 | 0x201D | 0x203A | 0x2E03 | 0x2E05 | 0x2E0A
+            | 0x2E0D | 0x2E1D | 0x2E21 => Some("Pf"),
+        _ => None,
+    }
+}
+
+
+// The noncharacters (the last two code points of every plane and a block
+// in Arabic Presentation Forms-A), planes 4 to 13, and plane 14 outside
+// its tags and variation selectors
+fn is_unassigned(cp: u32) -> bool {
+    cp & 0xFFFE == 0xFFFE
+        || (0xFDD0..=0xFDEF).contains(&cp)
+        || (0x40000..=0xDFFFF).contains(&cp)
+        || ((0xE0000..=0xEFFFF).contains(&cp)
+            && !matches!(cp, 0xE0001 | 0xE0020..=0xE007F | 0xE0100..=0xE01EF))
+}
+
+
+fn is_punctuation_block(cp: u32) -> bool {
+    matches!(
+        cp,
+        0x00A1 | 0x00A7 | 0x00AB | 0x00B6 | 0x00B7 | 0x00BB | 0x00BF
+            | 0x2010..=0x2027
+            | 0x2030..=0x205E
+            | 0x3000..=0x303F
+    )
+}
+
+
+// Every byte offset inside the string that falls in the middle of a char.
+// Using any of these as a slice bound will panic.
+pub fn unsafe_byte_indices(text: &str) -> Vec<usize> {
+    (0..text.len())
+        .filter(|i| !text.is_char_boundary(*i))
+        .collect()
+}
+
+
+// Checks a byte range the same way &text[range] would, but reports the
+// problem instead of panicking.
+pub fn check_slice(text: &str, range: Range<usize>) -> Result<&str, String> {
+    if range.start > range.end {
+        return Err(format!(
+            "range start {} is past its end {}", range.start, range.end
+        ));
+    }
+    if range.end > text.len() {
+        return Err(format!(
+            "byte {} is out of bounds, {:?} is {} bytes long",
+            range.end, text, text.len()
+        ));
+    }
+    for bound in [range.start, range.end] {
+        if !text.is_char_boundary(bound) {
+            let (start, ch) = text.char_indices()
+                .take_while(|(i, _)| *i < bound)
+                .last()
+                .unwrap();
+            return Err(format!(
+                "byte {} is inside {:?} (bytes {}..{}) of {:?}",
+                bound, ch, start, start + ch.len_utf8(), text
+            ));
+        }
+    }
+    Ok(&text[range])
+}
+
+
+pub fn print_report(text: &str) {
+    println!("\n  {:?}: {} bytes, {} chars", text, text.len(),
+        text.chars().count());
+    println!("   at  code     utf-8        utf-16     cat");
+    for info in inspect(text) {
+        info.print();
+    }
+    let bad: Vec<usize> = unsafe_byte_indices(text);
+    if !bad.is_empty() {
+        println!("  slicing at these byte indices would panic: {bad:?}");
+    }
+}
+
+
+pub fn run() {
+    println!("\n{}", header("CHAR INSPECTOR"));
+    print_report("D-Pad 29");
+    print_report("Zoë\t№5 €");
+    print_report("e\u{301} 日本 🦀");
+
+    // The same slice from chapter_8::string_demo, on a name that isn't ASCII
+    for name in ["dpad_29", "日本_29"] {
+        match check_slice(name, 0..4) {
+            Ok(s) => println!("  &{name:?}[0..4] = {s:?}"),
+            Err(e) => println!("  &{name:?}[0..4] would panic: {e}"),
+        }
+    }
+}
+
+
+pub fn register_layouts(registry: &mut Registry) {
+    registry.register("Chapter 3", layout_of!(CharInfo {
+        ch, byte_offset, utf8, utf16, category
+    }));
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ascii_categories() {
+        let expected: [(&str, &str); 12] = [
+            ("Cc", "\0\t\n\x7f"),
+            ("Zs", " "),
+            ("Lu", "AZ"),
+            ("Ll", "az"),
+            ("Nd", "09"),
+            ("Sc", "$"),
+            ("Sm", "+<=>|~"),
+            ("Sk", "^`"),
+            ("Ps", "([{"),
+            ("Pe", ")]}"),
+            ("Pd", "-"),
+            ("Pc", "_"),
+        ];
+        for (category, chars) in expected {
+            for ch in chars.chars() {
+                assert_eq!(general_category(ch), category, "{ch:?}");
+            }
+        }
+        for ch in "!\"#%&'*,./:;?@\\".chars() {
+            assert_eq!(general_category(ch), "Po", "{ch:?}");
+        }
+    }
+
+    #[test]
+    fn beyond_ascii() {
+        let expected: [(char, &str); 16] = [
+            ('\u{e9}', "Ll"),
+            ('\u{c9}', "Lu"),
+            ('\u{65e5}', "Lo"),
+            ('\u{1c5}', "Lt"),
+            ('\u{2b0}', "Lm"),
+            ('\u{216b}', "Nl"),
+            ('\u{663}', "Nd"),
+            ('\u{ff15}', "Nd"),
+            ('\u{1d7d8}', "Nd"),
+            ('\u{b2}', "No"),
+            ('\u{2116}', "So"),
+            ('\u{20ac}', "Sc"),
+            ('\u{2014}', "Pd"),
+            ('\u{301}', "Mn"),
+            ('\u{a0}', "Zs"),
+            ('\u{200b}', "Cf"),
+        ];
+        for (ch, category) in expected {
+            assert_eq!(general_category(ch), category, "{ch:?}");
+        }
+    }
+
+    #[test]
+    fn math_brackets_and_quotes() {
+        let expected: [(&str, &str); 5] = [
+            ("Sm", "\u{b1}\u{d7}\u{f7}\u{ac}\u{2211}\u{2264}\u{2192}\u{ff0b}"),
+            ("Ps", "\u{3008}\u{300c}\u{2983}\u{201a}\u{ff08}\u{fd3f}"),
+            ("Pe", "\u{3009}\u{300d}\u{2984}\u{301f}\u{ff09}\u{fd3e}"),
+            ("Pi", "\u{ab}\u{2018}\u{201c}\u{2039}"),
+            ("Pf", "\u{bb}\u{2019}\u{201d}\u{203a}"),
+        ];
+        for (category, chars) in expected {
+            for ch in chars.chars() {
+                assert_eq!(general_category(ch), category, "{ch:?}");
+            }
+        }
+        // Arrows that aren't used as operators stay plain symbols
+        assert_eq!(general_category('\u{2195}'), "So");
+        assert_eq!(general_category('\u{3001}'), "Po");
+    }
+
+    #[test]
+    fn unassigned() {
+        for ch in ['\u{fffe}', '\u{ffff}', '\u{fdd0}', '\u{1fffe}',
+            '\u{50000}', '\u{e0000}', '\u{e0200}']
+        {
+            assert_eq!(general_category(ch), "Cn", "{ch:?}");
+        }
+        assert_eq!(general_category('\u{e0041}'), "Cf");
+        assert_eq!(general_category('\u{e0100}'), "Mn");
+        assert_eq!(general_category('\u{10fffd}'), "Co");
+        assert_eq!(general_category('\u{10ffff}'), "Cn");
+    }
+}
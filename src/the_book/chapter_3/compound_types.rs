@@ -1,3 +1,6 @@
+use super::ticker::{FuturesContract, YearMonth};
+use crate::text::card::header;
+
 
 
 fn arrays() {
@@ -36,6 +39,9 @@ fn tuples() {
         [13, 21, 34]
     );
     println!("  2D structure: {:?}", two_dee);
+
+    // When every row has the same type, a nested array [[T; C]; R] works
+    // too. matrix.rs builds a Matrix<T, R, C> type on top of that idea.
 }
 
 
@@ -90,6 +96,13 @@ fn strings() {
     println!("  Ticker symbol: {}", ticker_slice);
     let asset_id: &str = &ticker_slice[0..2];
     println!("  Asset ID: {}", asset_id);
+
+    // Slicing off the first two characters only works for two letter roots.
+    // ticker.rs parses the whole symbol from the end instead.
+    match FuturesContract::parse(ticker_slice, YearMonth::now()) {
+        Ok(contract) => println!("  Contract: {}", contract.describe()),
+        Err(e) => println!("  Bad ticker symbol: {}", e),
+    }
 }
 
 
@@ -97,7 +110,7 @@ pub fn run() {
     /* 
     * Compound types include arrays, tuples, slices, and strings
     */
-    println!("\n~~~ COMPOUND_TYPES ~~~");
+    println!("\n{}", header("COMPOUND_TYPES"));
     arrays();
     tuples();
     slices();
@@ -0,0 +1,349 @@
+use std::num::FpCategory;
+use crate::layout::{layout_of, Registry};
+use crate::text::card::header;
+
+
+/*
+* IEEE-754 floats are stored as three bit fields:
+*   sign     | exponent | mantissa (fraction)
+*   f32: 1   | 8        | 23
+*   f64: 1   | 11       | 52
+*
+* For normal numbers the value is (-1)^sign * 1.mantissa * 2^(exponent - bias)
+* where the bias is 127 for f32 and 1023 for f64. When the exponent bits are
+* all zero the number is subnormal, and the implicit leading 1 becomes a 0.
+* When they are all ones the number is either infinity or NaN.
+*/
+#[derive(Debug)]
+pub struct FloatBits {
+    pub width: u32,
+    pub sign: u64,
+    pub exponent: u64,
+    pub mantissa: u64,
+    pub category: FpCategory,
+    pub value: f64,
+    pub next: f64,
+    exponent_width: u32,
+    mantissa_width: u32,
+}
+
+impl FloatBits {
+
+    pub fn from_f32(x: f32) -> FloatBits {
+        let bits: u64 = x.to_bits() as u64;
+        FloatBits {
+            width: 32,
+            sign: bits >> 31,
+            exponent: (bits >> 23) & 0xff,
+            mantissa: bits & 0x7f_ffff,
+            category: x.classify(),
+            value: x as f64,
+            next: x.next_up() as f64,
+            exponent_width: 8,
+            mantissa_width: 23,
+        }
+    }
+
+    pub fn from_f64(x: f64) -> FloatBits {
+        let bits: u64 = x.to_bits();
+        FloatBits {
+            width: 64,
+            sign: bits >> 63,
+            exponent: (bits >> 52) & 0x7ff,
+            mantissa: bits & 0xf_ffff_ffff_ffff,
+            category: x.classify(),
+            value: x,
+            next: x.next_up(),
+            exponent_width: 11,
+            mantissa_width: 52,
+        }
+    }
+
+    fn bias(&self) -> i32 {
+        (1 << (self.exponent_width - 1)) - 1
+    }
+
+    // The exponent with the bias removed. Subnormals share the exponent of
+    // the smallest normal number, they just lose the implicit leading 1.
+    pub fn unbiased_exponent(&self) -> i32 {
+        (self.exponent.max(1) as i32) - self.bias()
+    }
+
+    // The value as a whole number significand times a power of two. Every
+    // finite float can be written exactly this way.
+    fn significand_and_power(&self) -> (u64, i32) {
+        let significand: u64 = if self.exponent == 0 {
+            self.mantissa
+        } else {
+            self.mantissa | (1 << self.mantissa_width)
+        };
+        let power: i32 = self.unbiased_exponent() - self.mantissa_width as i32;
+        (significand, power)
+    }
+
+    // The exact decimal value that the bits represent, with no rounding.
+    // This is why 0.1 is really 0.1000000000000000055511151231257827...
+    pub fn exact_decimal(&self) -> String {
+        match self.category {
+            FpCategory::Nan => return String::from("NaN"),
+            FpCategory::Infinite => {
+                return String::from(if self.sign == 1 { "-inf" } else { "inf" })
+            }
+            _ => ()
+        }
+        let (significand, power) = self.significand_and_power();
+        let mut text: String = exact_decimal(significand, power);
+        if self.sign == 1 {
+            text.insert(0, '-');
+        }
+        text
+    }
+
+    // The gap to the next float away from zero. The gap doubles every
+    // time the exponent goes up by one.
+    pub fn ulp(&self) -> f64 {
+        match self.category {
+            FpCategory::Nan | FpCategory::Infinite => f64::NAN,
+            _ => {
+                let (_, power) = self.significand_and_power();
+                power_of_two(power)
+            }
+        }
+    }
+
+    pub fn bit_string(&self) -> String {
+        let man_w: usize = self.mantissa_width as usize;
+        let exp_w: usize = self.exponent_width as usize;
+        format!(
+            "{} {:0exp_w$b} {:0man_w$b}",
+            self.sign, self.exponent, self.mantissa
+        )
+    }
+
+    pub fn print(&self) {
+        println!("  f{} {:?}", self.width, self.value);
+        println!("    bits:     {}", self.bit_string());
+        println!("    sign:     {}", self.sign);
+        println!(
+            "    exponent: {} (unbiased {})",
+            self.exponent,
+            self.unbiased_exponent()
+        );
+        println!("    mantissa: {:#x}", self.mantissa);
+        println!("    class:    {}", category_name(self.category));
+        println!("    exact:    {}", self.exact_decimal());
+        println!("    ulp:      {:e}", self.ulp());
+        println!("    next up:  {:?}", self.next);
+    }
+}
+
+
+pub fn category_name(category: FpCategory) -> &'static str {
+    match category {
+        FpCategory::Nan => "NaN",
+        FpCategory::Infinite => "infinite",
+        FpCategory::Zero => "zero",
+        FpCategory::Subnormal => "subnormal",
+        FpCategory::Normal => "normal",
+    }
+}
+
+
+// powi() goes through 2^1074 on the way to 2^-1074 and underflows, so the
+// bits are built directly instead. Subnormal powers set a single mantissa bit.
+fn power_of_two(power: i32) -> f64 {
+    if power >= -1022 {
+        f64::from_bits(((power + 1023) as u64) << 52)
+    } else {
+        f64::from_bits(1 << (power + 1074))
+    }
+}
+
+
+// Builds the decimal expansion of significand * 2^power by hand. Digits are
+// stored least significant first so carrying is easy. A negative power is
+// turned into significand * 5^k / 10^k, which only needs a decimal point.
+fn exact_decimal(significand: u64, power: i32) -> String {
+    let mut digits: Vec<u8> = Vec::new();
+    let mut n: u64 = significand;
+    while n > 0 {
+        digits.push((n % 10) as u8);
+        n /= 10;
+    }
+    if digits.is_empty() {
+        return String::from("0");
+    }
+
+    let multiplier: u8 = if power >= 0 { 2 } else { 5 };
+    for _ in 0..power.unsigned_abs() {
+        let mut carry: u8 = 0;
+        for d in digits.iter_mut() {
+            let product: u8 = *d * multiplier + carry;
+            *d = product % 10;
+            carry = product / 10;
+        }
+        if carry > 0 {
+            digits.push(carry);
+        }
+    }
+
+    let point: usize = if power < 0 { power.unsigned_abs() as usize } else { 0 };
+    while digits.len() <= point {
+        digits.push(0);
+    }
+    let mut text: String = String::new();
+    for (i, d) in digits.iter().enumerate().rev() {
+        text.push((b'0' + d) as char);
+        if i == point && point > 0 {
+            text.push('.');
+        }
+    }
+    if point > 0 {
+        let trimmed: &str = text.trim_end_matches('0').trim_end_matches('.');
+        text.truncate(trimmed.len());
+    }
+    text
+}
+
+
+// Plain tolerance check. Good near zero, useless for very large numbers.
+pub fn approx_eq_abs(a: f64, b: f64, tolerance: f64) -> bool {
+    a == b || (a - b).abs() <= tolerance
+}
+
+
+// Tolerance scaled by the size of the inputs, so it works at any magnitude.
+// An infinity is only close to the same infinity (caught by a == b), since
+// scaling by it would make the tolerance infinite too. NaN is close to
+// nothing.
+pub fn approx_eq_rel(a: f64, b: f64, tolerance: f64) -> bool {
+    if a == b {
+        return true;
+    }
+    if !a.is_finite() || !b.is_finite() {
+        return false;
+    }
+    let largest: f64 = a.abs().max(b.abs());
+    (a - b).abs() <= largest * tolerance
+}
+
+
+// Counts how many representable floats sit between a and b. Mapping the bits
+// onto a single number line lets negative values be compared too.
+pub fn ulps_between(a: f64, b: f64) -> Option<u64> {
+    if a.is_nan() || b.is_nan() {
+        return None;
+    }
+    fn ordered(x: f64) -> i64 {
+        let bits: i64 = x.to_bits() as i64;
+        if bits < 0 { i64::MIN - bits } else { bits }
+    }
+    Some(ordered(a).abs_diff(ordered(b)))
+}
+
+
+pub fn approx_eq_ulps(a: f64, b: f64, max_ulps: u64) -> bool {
+    match ulps_between(a, b) {
+        Some(distance) => distance <= max_ulps,
+        None => false
+    }
+}
+
+
+fn point_one_plus_point_two() {
+    let sum: f64 = 0.1 + 0.2;
+    println!("\n  0.1 + 0.2 == 0.3 -> {}", sum == 0.3);
+    println!("    0.1       = {}", FloatBits::from_f64(0.1).exact_decimal());
+    println!("    0.2       = {}", FloatBits::from_f64(0.2).exact_decimal());
+    println!("    0.1 + 0.2 = {}", FloatBits::from_f64(sum).exact_decimal());
+    println!("    0.3       = {}", FloatBits::from_f64(0.3).exact_decimal());
+    println!("    ulps apart: {:?}", ulps_between(sum, 0.3));
+    println!(
+        "    abs(1e-9): {}  rel(EPSILON): {}  ulps(1): {}",
+        approx_eq_abs(sum, 0.3, 1e-9),
+        approx_eq_rel(sum, 0.3, f64::EPSILON),
+        approx_eq_ulps(sum, 0.3, 1)
+    );
+}
+
+
+pub fn run() {
+    println!("\n{}", header("FLOAT INSPECTOR"));
+    FloatBits::from_f32(f32::MIN_POSITIVE / 4.0).print();
+    FloatBits::from_f32(f32::NEG_INFINITY).print();
+    point_one_plus_point_two();
+}
+
+
+pub fn register_layouts(registry: &mut Registry) {
+    registry.register("Chapter 3", layout_of!(FloatBits {
+        width, sign, exponent, mantissa, category, value, next,
+        exponent_width, mantissa_width
+    }));
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bit_fields() {
+        let one: FloatBits = FloatBits::from_f64(1.0);
+        assert_eq!((one.sign, one.exponent, one.mantissa), (0, 1023, 0));
+        assert_eq!(one.unbiased_exponent(), 0);
+
+        let tiny: FloatBits = FloatBits::from_f32(f32::MIN_POSITIVE / 4.0);
+        assert_eq!(tiny.category, FpCategory::Subnormal);
+        assert_eq!((tiny.exponent, tiny.mantissa), (0, 1 << 21));
+        assert_eq!(tiny.unbiased_exponent(), -126);
+
+        let minus: FloatBits = FloatBits::from_f32(f32::NEG_INFINITY);
+        assert_eq!((minus.sign, minus.exponent), (1, 0xff));
+        assert_eq!(minus.exact_decimal(), "-inf");
+        assert!(minus.ulp().is_nan());
+    }
+
+    #[test]
+    fn exact_decimals() {
+        let exact = |x: f64| FloatBits::from_f64(x).exact_decimal();
+        assert_eq!(exact(0.0), "0");
+        assert_eq!(exact(-2.5), "-2.5");
+        assert_eq!(exact(1024.0), "1024");
+        assert_eq!(
+            exact(0.1),
+            "0.1000000000000000055511151231257827021181583404541015625"
+        );
+        assert_eq!(exact(f64::NAN), "NaN");
+        assert!(exact(f64::MIN_POSITIVE * f64::EPSILON).starts_with("0.000"));
+    }
+
+    #[test]
+    fn ulps() {
+        assert_eq!(FloatBits::from_f64(1.0).ulp(), f64::EPSILON);
+        assert_eq!(FloatBits::from_f64(0.0).ulp(), 5e-324);
+        assert_eq!(ulps_between(1.0, 1.0_f64.next_up()), Some(1));
+        assert_eq!(ulps_between(-0.0, 0.0), Some(0));
+        assert_eq!(ulps_between(-5e-324, 5e-324), Some(2));
+        assert_eq!(ulps_between(f64::NAN, 1.0), None);
+        assert!(approx_eq_ulps(0.1 + 0.2, 0.3, 1));
+        assert!(!approx_eq_ulps(f64::NAN, f64::NAN, u64::MAX));
+    }
+
+    #[test]
+    fn approx_eq_handles_non_finite_inputs() {
+        assert!(approx_eq_rel(1e300, 1e300 * (1.0 + 1e-15), 1e-12));
+        assert!(!approx_eq_rel(1.0, 1.1, 1e-3));
+        assert!(approx_eq_rel(f64::INFINITY, f64::INFINITY, 1e-9));
+        assert!(!approx_eq_rel(f64::INFINITY, 1.0, 1e-9));
+        assert!(!approx_eq_rel(1.0, f64::NEG_INFINITY, 1e-9));
+        assert!(!approx_eq_rel(f64::INFINITY, f64::NEG_INFINITY, 1.0));
+        assert!(!approx_eq_rel(f64::NAN, f64::NAN, 1.0));
+        assert!(!approx_eq_rel(f64::NAN, 1.0, f64::INFINITY));
+
+        assert!(approx_eq_abs(0.1 + 0.2, 0.3, 1e-9));
+        assert!(approx_eq_abs(f64::INFINITY, f64::INFINITY, 1.0));
+        assert!(!approx_eq_abs(f64::INFINITY, 1e308, 1e308));
+        assert!(!approx_eq_abs(f64::NAN, 0.0, f64::INFINITY));
+    }
+}
@@ -0,0 +1,252 @@
+use std::collections::HashMap;
+use crate::layout::{layout_of, Registry};
+use crate::text::card::header;
+
+
+/*
+* The end of chapter 3 suggests a few small programs to practice with:
+*   - Convert temperatures between Fahrenheit and Celsius
+*   - Generate the nth Fibonacci number
+*   - Print the lyrics to "The Twelve Days of Christmas"
+*/
+
+
+// TEMPERATURE CONVERSION
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Scale {
+    Fahrenheit,
+    Celsius,
+    Kelvin
+}
+
+impl Scale {
+    fn symbol(&self) -> &str {
+        match self {
+            Scale::Fahrenheit => "°F",
+            Scale::Celsius => "°C",
+            Scale::Kelvin => "K",
+        }
+    }
+}
+
+// Every conversion goes through Celsius, so only two formulas per scale
+// are needed instead of one for every pair.
+fn to_celsius(value: f64, from: Scale) -> f64 {
+    match from {
+        Scale::Fahrenheit => (value - 32.0) * 5.0 / 9.0,
+        Scale::Celsius => value,
+        Scale::Kelvin => value - 273.15,
+    }
+}
+
+fn from_celsius(celsius: f64, to: Scale) -> f64 {
+    match to {
+        Scale::Fahrenheit => celsius * 9.0 / 5.0 + 32.0,
+        Scale::Celsius => celsius,
+        Scale::Kelvin => celsius + 273.15,
+    }
+}
+
+pub fn convert_temperature(value: f64, from: Scale, to: Scale) -> f64 {
+    from_celsius(to_celsius(value, from), to)
+}
+
+
+// FIBONACCI
+// Each number is the sum of the two before it: 0, 1, 1, 2, 3, 5, 8, ...
+// F(93) is the last one that fits in a u64, so n >= 94 overflows: a panic
+// in debug builds and a wrong answer in release. Use fibonacci_checked when
+// n might be that big.
+pub fn fibonacci(n: u32) -> u64 {
+    if n == 0 {
+        return 0;
+    }
+    let mut previous: u64 = 0;
+    let mut current: u64 = 1;
+    for _ in 1..n {
+        let next: u64 = previous + current;
+        previous = current;
+        current = next;
+    }
+    current
+}
+
+// The naive recursive version recalculates the same values over and over.
+// Remembering each answer in a HashMap makes it linear instead of
+// exponential.
+pub fn fibonacci_memo(n: u32, memo: &mut HashMap<u32, u64>) -> u64 {
+    if n < 2 {
+        return n as u64;
+    }
+    if let Some(value) = memo.get(&n) {
+        return *value;
+    }
+    let value: u64 = fibonacci_memo(n - 1, memo) + fibonacci_memo(n - 2, memo);
+    memo.insert(n, value);
+    value
+}
+
+// u128 only holds up to F(186). checked_add returns None instead of
+// panicking (debug) or wrapping around (release) when it runs out of room.
+pub fn fibonacci_checked(n: u32) -> Option<u128> {
+    if n == 0 {
+        return Some(0);
+    }
+    let mut previous: u128 = 0;
+    let mut current: u128 = 1;
+    for _ in 1..n {
+        let next: u128 = previous.checked_add(current)?;
+        previous = current;
+        current = next;
+    }
+    Some(current)
+}
+
+
+// THE TWELVE DAYS OF CHRISTMAS
+const DAYS: [&str; 12] = [
+    "first", "second", "third", "fourth", "fifth", "sixth",
+    "seventh", "eighth", "ninth", "tenth", "eleventh", "twelfth"
+];
+
+const GIFTS: [&str; 12] = [
+    "a partridge in a pear tree",
+    "two turtle doves",
+    "three French hens",
+    "four calling birds",
+    "five gold rings",
+    "six geese a-laying",
+    "seven swans a-swimming",
+    "eight maids a-milking",
+    "nine ladies dancing",
+    "ten lords a-leaping",
+    "eleven pipers piping",
+    "twelve drummers drumming"
+];
+
+// None for any day outside 1..=12
+pub fn twelve_days_verse(day: usize) -> Option<String> {
+    let name: &str = DAYS.get(day.checked_sub(1)?)?;
+    let mut verse: String = format!(
+        "On the {name} day of Christmas my true love sent to me:\n"
+    );
+    for gift in (0..day).rev() {
+        let mut line: String = String::from(GIFTS[gift]);
+        if gift == day - 1 {
+            line[..1].make_ascii_uppercase();
+        } else if gift == 0 {
+            line.insert_str(0, "And ");
+        }
+        verse.push_str(&line);
+        verse.push_str(if gift == 0 { ".\n" } else { ",\n" });
+    }
+    Some(verse)
+}
+
+pub fn twelve_days_lyrics() -> String {
+    (1..=12)
+        .filter_map(twelve_days_verse)
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+
+pub fn run() {
+    println!("\n{}", header("FUNCTIONS"));
+
+    println!("TEMPERATURE:");
+    let readings: [(f64, Scale); 3] = [
+        (98.6, Scale::Fahrenheit),
+        (-40.0, Scale::Celsius),
+        (0.0, Scale::Kelvin),
+    ];
+    for (value, scale) in readings {
+        let others: Vec<String> = [Scale::Fahrenheit, Scale::Celsius,
+            Scale::Kelvin]
+            .iter()
+            .filter(|s| **s != scale)
+            .map(|s| {
+                format!("{:.2}{}", convert_temperature(value, scale, *s),
+                    s.symbol())
+            })
+            .collect();
+        println!("  {}{} = {}", value, scale.symbol(), others.join(" = "));
+    }
+
+    println!("FIBONACCI:");
+    let mut memo: HashMap<u32, u64> = HashMap::new();
+    println!("  F(10) = {}", fibonacci(10));
+    println!("  F(90) = {} (memoized)", fibonacci_memo(90, &mut memo));
+    for n in [186, 187] {
+        match fibonacci_checked(n) {
+            Some(value) => println!("  F({n}) = {value}"),
+            None => println!("  F({n}) doesn't fit in a u128"),
+        }
+    }
+
+    println!("TWELVE DAYS OF CHRISTMAS:");
+    for line in twelve_days_verse(12).unwrap_or_default().lines() {
+        println!("  {line}");
+    }
+}
+
+
+pub fn register_layouts(registry: &mut Registry) {
+    registry.register("Chapter 3", layout_of!(Scale));
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn temperature_round_trip() {
+        let k: f64 =
+            convert_temperature(212.0, Scale::Fahrenheit, Scale::Kelvin);
+        assert!((k - 373.15).abs() < 1e-9);
+        let f: f64 = convert_temperature(k, Scale::Kelvin, Scale::Fahrenheit);
+        assert!((f - 212.0).abs() < 1e-9);
+        assert_eq!(convert_temperature(-40.0, Scale::Celsius,
+            Scale::Fahrenheit), -40.0);
+    }
+
+    #[test]
+    fn fibonacci_versions_agree() {
+        let mut memo: HashMap<u32, u64> = HashMap::new();
+        for n in 0..=93 {
+            assert_eq!(fibonacci(n), fibonacci_memo(n, &mut memo));
+            assert_eq!(fibonacci(n) as u128, fibonacci_checked(n).unwrap());
+        }
+        assert_eq!(fibonacci(0), 0);
+        assert_eq!(fibonacci(1), 1);
+        assert_eq!(fibonacci(10), 55);
+    }
+
+    #[test]
+    fn fibonacci_overflow_is_detected() {
+        assert_eq!(
+            fibonacci_checked(186),
+            Some(332825110087067562321196029789634457848)
+        );
+        assert_eq!(fibonacci_checked(187), None);
+    }
+
+    #[test]
+    fn twelve_days() {
+        assert_eq!(
+            twelve_days_verse(1).unwrap(),
+            "On the first day of Christmas my true love sent to me:\n\
+             A partridge in a pear tree.\n"
+        );
+        let second: String = twelve_days_verse(2).unwrap();
+        assert!(second.ends_with(
+            "Two turtle doves,\nAnd a partridge in a pear tree.\n"
+        ));
+        let lyrics: String = twelve_days_lyrics();
+        assert_eq!(lyrics.matches("partridge").count(), 12);
+        assert_eq!(lyrics.matches("drummers").count(), 1);
+        assert_eq!(twelve_days_verse(0), None);
+        assert_eq!(twelve_days_verse(13), None);
+    }
+}
@@ -0,0 +1,590 @@
+use std::fmt;
+use std::ops::{Add, Index, IndexMut, Mul, Sub, Div};
+use crate::layout::{layout_of, Registry};
+use crate::text::card::header;
+
+
+/*
+* A 2D array like [[T; C]; R] is a matrix with its size baked into the type.
+* With const generics the sizes become type parameters, so multiplying a
+* 2x3 by a 2x3 is a compile error instead of a runtime panic:
+*
+*   let a: Matrix<i32, 2, 3> = ...;
+*   let b: Matrix<i32, 3, 4> = ...;
+*   let c: Matrix<i32, 2, 4> = a * b;   // ok
+*   let d = a * a;                      // error[E0308]: mismatched types
+*
+* DynMatrix is the heap version for sizes only known at runtime. Both
+* implement MatrixView, so code like determinant() is written once.
+*/
+
+
+// The bits of arithmetic a matrix needs from its elements. Unsigned types
+// qualify too, though a determinant of theirs overflows if any step of it
+// goes below zero.
+pub trait Numeric:
+    Copy
+    + PartialEq
+    + PartialOrd
+    + fmt::Display
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+{
+    fn zero() -> Self;
+    fn one() -> Self;
+}
+
+// Numbers where division is exact enough to invert a matrix. Integers
+// would truncate, so they only get a determinant. Whether a value is
+// negligible depends on the size of the numbers around it, so it's
+// measured against `scale`: 1e-20 is nothing next to 1, but it's a fine
+// pivot in a matrix whose entries are all about 1e-20.
+pub trait Fractional: Numeric + Div<Output = Self> {
+    fn abs(self) -> Self;
+    fn is_negligible(self, scale: Self) -> bool;
+}
+
+macro_rules! impl_numeric {
+    ($($t:ty),*) => {
+        $(impl Numeric for $t {
+            fn zero() -> Self { 0 as $t }
+            fn one() -> Self { 1 as $t }
+        })*
+    };
+}
+impl_numeric!(i8, i16, i32, i64, i128, u8, u16, u32, u64, u128, f32, f64);
+
+impl Fractional for f32 {
+    fn abs(self) -> Self { f32::abs(self) }
+    fn is_negligible(self, scale: Self) -> bool { self.abs() <= scale * 1e-6 }
+}
+
+impl Fractional for f64 {
+    fn abs(self) -> Self { f64::abs(self) }
+    fn is_negligible(self, scale: Self) -> bool {
+        self.abs() <= scale * 1e-12
+    }
+}
+
+
+pub trait MatrixView<T: Numeric> {
+    fn rows(&self) -> usize;
+    fn cols(&self) -> usize;
+    fn get(&self, row: usize, col: usize) -> T;
+
+    fn is_square(&self) -> bool {
+        self.rows() == self.cols()
+    }
+
+    fn to_dyn(&self) -> DynMatrix<T> {
+        let mut data: Vec<T> = Vec::with_capacity(self.rows() * self.cols());
+        for r in 0..self.rows() {
+            for c in 0..self.cols() {
+                data.push(self.get(r, c));
+            }
+        }
+        DynMatrix { rows: self.rows(), cols: self.cols(), data }
+    }
+
+    // Laplace (cofactor) expansion along the first row. It's O(n!) so it's
+    // only meant for the small matrices in these notes, but it works for
+    // integers without any division.
+    fn determinant(&self) -> Option<T> {
+        if !self.is_square() {
+            return None;
+        }
+        Some(laplace(&self.to_dyn()))
+    }
+}
+
+
+fn laplace<T: Numeric>(m: &DynMatrix<T>) -> T {
+    match m.rows {
+        0 => T::one(),
+        1 => m.get(0, 0),
+        2 => m.get(0, 0) * m.get(1, 1) - m.get(0, 1) * m.get(1, 0),
+        _ => {
+            let mut total: T = T::zero();
+            for col in 0..m.cols {
+                let minor: DynMatrix<T> = m.minor(0, col).unwrap();
+                let term: T = m.get(0, col) * laplace(&minor);
+                total = if col % 2 == 0 { total + term } else { total - term };
+            }
+            total
+        }
+    }
+}
+
+
+// Shared by both matrix types. Columns are padded to the widest entry.
+fn format_matrix<T: Numeric>(
+    m: &impl MatrixView<T>,
+    f: &mut fmt::Formatter
+) -> fmt::Result {
+    let mut cells: Vec<String> = Vec::new();
+    for r in 0..m.rows() {
+        for c in 0..m.cols() {
+            cells.push(match f.precision() {
+                Some(p) => format!("{:.*}", p, m.get(r, c)),
+                None => format!("{}", m.get(r, c)),
+            });
+        }
+    }
+    let width: usize = cells.iter().map(|s| s.chars().count()).max()
+        .unwrap_or(0);
+    for r in 0..m.rows() {
+        let (open, close) = match (r, m.rows()) {
+            (_, 1) => ('[', ']'),
+            (0, _) => ('┌', '┐'),
+            (r, n) if r == n - 1 => ('└', '┘'),
+            _ => ('│', '│'),
+        };
+        let row: Vec<String> = cells[r * m.cols()..(r + 1) * m.cols()]
+            .iter()
+            .map(|s| format!("{s:>width$}"))
+            .collect();
+        write!(f, "{open} {} {close}", row.join("  "))?;
+        if r + 1 < m.rows() {
+            writeln!(f)?;
+        }
+    }
+    Ok(())
+}
+
+
+// FIXED SIZE MATRIX
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Matrix<T, const R: usize, const C: usize> {
+    data: [[T; C]; R]
+}
+
+impl<T: Numeric, const R: usize, const C: usize> Matrix<T, R, C> {
+
+    pub fn new(data: [[T; C]; R]) -> Self {
+        Matrix { data }
+    }
+
+    pub fn zeros() -> Self {
+        Matrix { data: [[T::zero(); C]; R] }
+    }
+
+    pub fn transpose(&self) -> Matrix<T, C, R> {
+        let mut out: Matrix<T, C, R> = Matrix::zeros();
+        for r in 0..R {
+            for c in 0..C {
+                out.data[c][r] = self.data[r][c];
+            }
+        }
+        out
+    }
+}
+
+impl<T: Numeric, const N: usize> Matrix<T, N, N> {
+
+    pub fn identity() -> Self {
+        let mut out: Self = Matrix::zeros();
+        for i in 0..N {
+            out.data[i][i] = T::one();
+        }
+        out
+    }
+}
+
+impl<T: Fractional, const N: usize> Matrix<T, N, N> {
+
+    pub fn inverse(&self) -> Option<Self> {
+        let inverse: DynMatrix<T> = self.to_dyn().inverse()?;
+        let mut out: Self = Matrix::zeros();
+        for r in 0..N {
+            for c in 0..N {
+                out.data[r][c] = inverse.get(r, c);
+            }
+        }
+        Some(out)
+    }
+}
+
+impl<T: Numeric, const R: usize, const C: usize> MatrixView<T>
+    for Matrix<T, R, C>
+{
+    fn rows(&self) -> usize { R }
+    fn cols(&self) -> usize { C }
+    fn get(&self, row: usize, col: usize) -> T { self.data[row][col] }
+}
+
+impl<T, const R: usize, const C: usize> Index<(usize, usize)>
+    for Matrix<T, R, C>
+{
+    type Output = T;
+
+    fn index(&self, (row, col): (usize, usize)) -> &T {
+        &self.data[row][col]
+    }
+}
+
+impl<T, const R: usize, const C: usize> IndexMut<(usize, usize)>
+    for Matrix<T, R, C>
+{
+    fn index_mut(&mut self, (row, col): (usize, usize)) -> &mut T {
+        &mut self.data[row][col]
+    }
+}
+
+impl<T: Numeric, const R: usize, const C: usize> Add for Matrix<T, R, C> {
+    type Output = Matrix<T, R, C>;
+
+    fn add(self, other: Self) -> Self {
+        let mut out: Self = self;
+        for r in 0..R {
+            for c in 0..C {
+                out.data[r][c] = self.data[r][c] + other.data[r][c];
+            }
+        }
+        out
+    }
+}
+
+// (R x C) * (C x K) = (R x K). The shared C is what stops mismatched sizes
+// from compiling.
+impl<T: Numeric, const R: usize, const C: usize, const K: usize>
+    Mul<Matrix<T, C, K>> for Matrix<T, R, C>
+{
+    type Output = Matrix<T, R, K>;
+
+    fn mul(self, other: Matrix<T, C, K>) -> Matrix<T, R, K> {
+        let mut out: Matrix<T, R, K> = Matrix::zeros();
+        for r in 0..R {
+            for k in 0..K {
+                let mut sum: T = T::zero();
+                for c in 0..C {
+                    sum = sum + self.data[r][c] * other.data[c][k];
+                }
+                out.data[r][k] = sum;
+            }
+        }
+        out
+    }
+}
+
+impl<T: Numeric, const R: usize, const C: usize> fmt::Display
+    for Matrix<T, R, C>
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        format_matrix(self, f)
+    }
+}
+
+
+// HEAP MATRIX
+#[derive(Debug, Clone, PartialEq)]
+pub struct DynMatrix<T> {
+    rows: usize,
+    cols: usize,
+    data: Vec<T>
+}
+
+#[derive(Debug, PartialEq)]
+pub enum MatrixError {
+    BadData { expected: usize, found: usize },
+    SizeMismatch { left: (usize, usize), right: (usize, usize) },
+}
+
+impl fmt::Display for MatrixError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MatrixError::BadData { expected, found } => {
+                write!(f, "expected {expected} values, found {found}")
+            }
+            MatrixError::SizeMismatch { left, right } => write!(
+                f, "can't combine a {}x{} with a {}x{}",
+                left.0, left.1, right.0, right.1
+            ),
+        }
+    }
+}
+
+impl<T: Numeric> DynMatrix<T> {
+
+    pub fn new(
+        rows: usize,
+        cols: usize,
+        data: Vec<T>
+    ) -> Result<Self, MatrixError> {
+        if data.len() != rows * cols {
+            return Err(MatrixError::BadData {
+                expected: rows * cols,
+                found: data.len()
+            });
+        }
+        Ok(DynMatrix { rows, cols, data })
+    }
+
+    pub fn zeros(rows: usize, cols: usize) -> Self {
+        DynMatrix { rows, cols, data: vec![T::zero(); rows * cols] }
+    }
+
+    pub fn identity(n: usize) -> Self {
+        let mut out: Self = DynMatrix::zeros(n, n);
+        for i in 0..n {
+            out[(i, i)] = T::one();
+        }
+        out
+    }
+
+    pub fn transpose(&self) -> Self {
+        let mut out: Self = DynMatrix::zeros(self.cols, self.rows);
+        for r in 0..self.rows {
+            for c in 0..self.cols {
+                out[(c, r)] = self[(r, c)];
+            }
+        }
+        out
+    }
+
+    // The matrix with one row and one column removed, or None if either
+    // is out of range
+    pub fn minor(&self, row: usize, col: usize) -> Option<Self> {
+        if row >= self.rows || col >= self.cols {
+            return None;
+        }
+        let mut data: Vec<T> = Vec::new();
+        for r in (0..self.rows).filter(|r| *r != row) {
+            for c in (0..self.cols).filter(|c| *c != col) {
+                data.push(self[(r, c)]);
+            }
+        }
+        Some(DynMatrix { rows: self.rows - 1, cols: self.cols - 1, data })
+    }
+
+    pub fn add(&self, other: &Self) -> Result<Self, MatrixError> {
+        if (self.rows, self.cols) != (other.rows, other.cols) {
+            return Err(self.mismatch(other));
+        }
+        let data: Vec<T> = self.data.iter()
+            .zip(&other.data)
+            .map(|(a, b)| *a + *b)
+            .collect();
+        Ok(DynMatrix { rows: self.rows, cols: self.cols, data })
+    }
+
+    pub fn mul(&self, other: &Self) -> Result<Self, MatrixError> {
+        if self.cols != other.rows {
+            return Err(self.mismatch(other));
+        }
+        let mut out: Self = DynMatrix::zeros(self.rows, other.cols);
+        for r in 0..self.rows {
+            for k in 0..other.cols {
+                let mut sum: T = T::zero();
+                for c in 0..self.cols {
+                    sum = sum + self[(r, c)] * other[(c, k)];
+                }
+                out[(r, k)] = sum;
+            }
+        }
+        Ok(out)
+    }
+
+    fn mismatch(&self, other: &Self) -> MatrixError {
+        MatrixError::SizeMismatch {
+            left: (self.rows, self.cols),
+            right: (other.rows, other.cols)
+        }
+    }
+}
+
+impl<T: Fractional> DynMatrix<T> {
+
+    // Gauss-Jordan elimination: row reduce [A | I] until the left half is
+    // the identity, and the right half is then A's inverse. Picking the
+    // largest pivot in each column keeps rounding errors down.
+    pub fn inverse(&self) -> Option<Self> {
+        if !self.is_square() {
+            return None;
+        }
+        let n: usize = self.rows;
+        let scale: T = self.data.iter()
+            .map(|x| x.abs())
+            .fold(T::zero(), |a, b| if b > a { b } else { a });
+        let mut a: Self = self.clone();
+        let mut inv: Self = DynMatrix::identity(n);
+        for col in 0..n {
+            let pivot: usize = (col..n)
+                .max_by(|x, y| {
+                    a[(*x, col)].abs()
+                        .partial_cmp(&a[(*y, col)].abs())
+                        .unwrap_or(std::cmp::Ordering::Equal)
+                })
+                .unwrap();
+            if a[(pivot, col)].is_negligible(scale) {
+                return None;
+            }
+            a.swap_rows(col, pivot);
+            inv.swap_rows(col, pivot);
+
+            let pivot: T = a[(col, col)];
+            for c in 0..n {
+                a[(col, c)] = a[(col, c)] / pivot;
+                inv[(col, c)] = inv[(col, c)] / pivot;
+            }
+            for r in (0..n).filter(|r| *r != col) {
+                let factor: T = a[(r, col)];
+                for c in 0..n {
+                    a[(r, c)] = a[(r, c)] - factor * a[(col, c)];
+                    inv[(r, c)] = inv[(r, c)] - factor * inv[(col, c)];
+                }
+            }
+        }
+        Some(inv)
+    }
+
+    fn swap_rows(&mut self, a: usize, b: usize) {
+        for c in 0..self.cols {
+            self.data.swap(a * self.cols + c, b * self.cols + c);
+        }
+    }
+}
+
+impl<T: Numeric> MatrixView<T> for DynMatrix<T> {
+    fn rows(&self) -> usize { self.rows }
+    fn cols(&self) -> usize { self.cols }
+    fn get(&self, row: usize, col: usize) -> T { self[(row, col)] }
+}
+
+impl<T> Index<(usize, usize)> for DynMatrix<T> {
+    type Output = T;
+
+    fn index(&self, (row, col): (usize, usize)) -> &T {
+        assert!(row < self.rows && col < self.cols, "index out of bounds");
+        &self.data[row * self.cols + col]
+    }
+}
+
+impl<T> IndexMut<(usize, usize)> for DynMatrix<T> {
+    fn index_mut(&mut self, (row, col): (usize, usize)) -> &mut T {
+        assert!(row < self.rows && col < self.cols, "index out of bounds");
+        &mut self.data[row * self.cols + col]
+    }
+}
+
+impl<T: Numeric> fmt::Display for DynMatrix<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        format_matrix(self, f)
+    }
+}
+
+
+fn indent(text: String) -> String {
+    text.lines()
+        .map(|l| format!("  {l}"))
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+
+pub fn run() {
+    println!("\n{}", header("MATRIX"));
+
+    let a: Matrix<i32, 2, 3> = Matrix::new([[1, 2, 3], [4, 5, 6]]);
+    let b: Matrix<i32, 3, 2> = a.transpose();
+    println!("A (2x3):\n{}", indent(a.to_string()));
+    println!("A transposed (3x2):\n{}", indent(b.to_string()));
+    println!("A * Aᵀ (2x2):\n{}", indent((a * b).to_string()));
+    println!("A + A:\n{}", indent((a + a).to_string()));
+
+    let m: Matrix<f64, 3, 3> = Matrix::new([
+        [2.0, 0.0, 1.0],
+        [1.0, 3.0, 2.0],
+        [1.0, 1.0, 2.0],
+    ]);
+    println!("M:\n{}", indent(m.to_string()));
+    println!("det(M) = {}", m.determinant().unwrap());
+    match m.inverse() {
+        Some(inv) => {
+            println!("M⁻¹:\n{}", indent(format!("{inv:.3}")));
+            println!("M * M⁻¹:\n{}", indent(format!("{:.3}", m * inv)));
+        }
+        None => println!("M is singular"),
+    }
+
+    // Same trait, sizes picked at runtime
+    let d: DynMatrix<i64> = DynMatrix::new(2, 2, vec![3, 8, 4, 6]).unwrap();
+    println!("D (runtime 2x2): det = {:?}", d.determinant());
+    match d.mul(&DynMatrix::identity(3)) {
+        Ok(product) => println!("{product}"),
+        Err(e) => println!("D * I₃: {e}"),
+    }
+}
+
+
+pub fn register_layouts(registry: &mut Registry) {
+    registry.register("Chapter 3", layout_of!(Matrix<f64, 3, 3> { data }));
+    registry.register("Chapter 3", layout_of!(DynMatrix<f64> {
+        rows, cols, data
+    }));
+    registry.register("Chapter 3", layout_of!(MatrixError));
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: &DynMatrix<f64>, b: &DynMatrix<f64>, tolerance: f64) -> bool {
+        a.data.iter().zip(&b.data).all(|(x, y)| (x - y).abs() <= tolerance)
+    }
+
+    #[test]
+    fn determinants() {
+        let m: Matrix<i32, 3, 3> =
+            Matrix::new([[2, 0, 1], [1, 3, 2], [1, 1, 2]]);
+        assert_eq!(m.determinant(), Some(6));
+        assert_eq!(Matrix::<i64, 4, 4>::identity().determinant(), Some(1));
+        let singular: Matrix<f64, 3, 3> =
+            Matrix::new([[1.0, 2.0, 3.0], [4.0, 5.0, 6.0], [7.0, 8.0, 9.0]]);
+        assert_eq!(singular.determinant(), Some(0.0));
+        let d: DynMatrix<i64> =
+            DynMatrix::new(2, 3, vec![1, 2, 3, 4, 5, 6]).unwrap();
+        assert_eq!(d.determinant(), None);
+        assert_eq!(DynMatrix::<i32>::zeros(0, 0).determinant(), Some(1));
+        let small: Matrix<u8, 2, 2> = Matrix::new([[3, 1], [2, 4]]);
+        assert_eq!(small.determinant(), Some(10));
+    }
+
+    #[test]
+    fn inverses() {
+        let m: Matrix<f64, 3, 3> = Matrix::new([
+            [2.0, 0.0, 1.0],
+            [1.0, 3.0, 2.0],
+            [1.0, 1.0, 2.0],
+        ]);
+        let inv: Matrix<f64, 3, 3> = m.inverse().unwrap();
+        let product: DynMatrix<f64> = (m * inv).to_dyn();
+        assert!(close(&product, &DynMatrix::identity(3), 1e-12));
+        assert!((inv[(0, 0)] - 4.0 / 6.0).abs() < 1e-12);
+
+        let singular: Matrix<f64, 2, 2> = Matrix::new([[1.0, 2.0], [2.0, 4.0]]);
+        assert!(singular.inverse().is_none());
+        assert!(Matrix::<f64, 2, 2>::zeros().inverse().is_none());
+        let wide: DynMatrix<f64> = DynMatrix::zeros(2, 3);
+        assert!(wide.inverse().is_none());
+    }
+
+    #[test]
+    fn scaled_down_matrices_still_invert() {
+        let tiny: DynMatrix<f64> =
+            DynMatrix::new(2, 2, vec![2e-20, 1e-20, 1e-20, 3e-20]).unwrap();
+        let inv: DynMatrix<f64> = tiny.inverse().unwrap();
+        let product: DynMatrix<f64> = tiny.mul(&inv).unwrap();
+        assert!(close(&product, &DynMatrix::identity(2), 1e-12));
+    }
+
+    #[test]
+    fn minor_checks_its_indices() {
+        let m: DynMatrix<i32> =
+            DynMatrix::new(2, 2, vec![1, 2, 3, 4]).unwrap();
+        assert_eq!(m.minor(0, 1), DynMatrix::new(1, 1, vec![3]).ok());
+        assert_eq!(m.minor(2, 0), None);
+        assert_eq!(DynMatrix::<i32>::zeros(0, 0).minor(0, 0), None);
+    }
+}
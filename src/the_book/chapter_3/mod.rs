@@ -1,18 +1,39 @@
+use crate::layout::Registry;
+use crate::text::card;
+
 mod compound_types;
 mod primitive_types;
 mod scope;
 mod constants;
 mod shadowing;
 mod loops;
+mod functions;
+mod ticker;
+mod matrix;
+mod float_inspector;
+pub mod char_inspector;
 
 
 pub fn run() {
     compound_types::run();
     primitive_types::run();
     scope::run();
+    card::run();
     constants::run();
     shadowing::run();
     loops::run();
+    functions::run();
+    ticker::run();
+    matrix::run();
+    float_inspector::run();
+    char_inspector::run();
 }
 
 
+pub fn register_layouts(registry: &mut Registry) {
+    float_inspector::register_layouts(registry);
+    char_inspector::register_layouts(registry);
+    functions::register_layouts(registry);
+    ticker::register_layouts(registry);
+    matrix::register_layouts(registry);
+}
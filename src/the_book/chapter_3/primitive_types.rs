@@ -1,3 +1,7 @@
+use super::char_inspector;
+use super::float_inspector::FloatBits;
+use crate::text::card::header;
+
 
 fn integers() {
     /*
@@ -29,6 +33,11 @@ fn floating() {
     let large: f64 = 1.23424987234;
     println!("  F32: {}", small);
     println!("  F64: {}", large);
+
+    // Neither value is stored exactly. The inspector shows what the bits
+    // really hold, see float_inspector.rs for the details.
+    FloatBits::from_f32(small).print();
+    FloatBits::from_f64(large).print();
 }
 
 fn booleans() {
@@ -47,10 +56,13 @@ fn characters() {
     println!("CHARACTERS:");
     let initial: char = 'D'; 
     println!("  My initials start with {}", initial);
+
+    // A char is a whole Unicode scalar value, not a byte
+    char_inspector::CharInfo::new(initial, 0).print();
 }
 
 pub fn run() {
-    println!("\n~~~ PRIMITIVE_TYPES ~~~");
+    println!("\n{}", header("PRIMITIVE_TYPES"));
     
     // Integers 
     integers();
@@ -1,7 +1,8 @@
+use crate::text::card::header;
 
 
 pub fn run() {
-    println!("\n~~~ SHADOWING ~~~");
+    println!("\n{}", header("SHADOWING"));
     let x: i32 = 25;
     let y: &i32 = &x;
     let x: i32 = 74;
@@ -0,0 +1,390 @@
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::layout::{layout_of, Registry};
+use crate::text::card::header;
+
+
+/*
+* Exchange futures symbols are made of three parts glued together:
+*   ES  M  24
+*   |   |  +-- year, 1 or 2 digits
+*   |   +----- month code, one letter from F (Jan) to Z (Dec)
+*   +--------- root, the product being traded
+*
+* The month letters skip a few that look too much like digits or other
+* codes, which is why there's no A, B, C, D, E, I, L, O, P, R, S, T, W or Y.
+*/
+const MONTH_CODES: [char; 12] = [
+    'F', 'G', 'H', 'J', 'K', 'M', 'N', 'Q', 'U', 'V', 'X', 'Z'
+];
+
+const MONTH_NAMES: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun",
+    "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"
+];
+
+// Listing cycles, as month numbers
+pub const MONTHLY: [u8; 12] = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12];
+pub const QUARTERLY: [u8; 4] = [3, 6, 9, 12];
+
+
+pub fn month_code(month: u8) -> Option<char> {
+    MONTH_CODES.get((month as usize).checked_sub(1)?).copied()
+}
+
+pub fn month_from_code(code: char) -> Option<u8> {
+    MONTH_CODES.iter()
+        .position(|c| *c == code)
+        .map(|i| i as u8 + 1)
+}
+
+
+// A calendar month, used as "today" when working out which decade a short
+// year code belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct YearMonth {
+    pub year: u16,
+    pub month: u8
+}
+
+impl YearMonth {
+
+    // The current month in UTC, worked out from the days since 1970 with
+    // Howard Hinnant's civil_from_days algorithm.
+    pub fn now() -> YearMonth {
+        let seconds: u64 = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let days: i64 = (seconds / 86_400) as i64 + 719_468;
+        let era: i64 = days / 146_097;
+        let day_of_era: i64 = days - era * 146_097;
+        let year_of_era: i64 = (day_of_era - day_of_era / 1460
+            + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+        let day_of_year: i64 = day_of_era
+            - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let mp: i64 = (5 * day_of_year + 2) / 153;
+        let month: i64 = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year: i64 = year_of_era + era * 400 + (month <= 2) as i64;
+        YearMonth { year: year as u16, month: month as u8 }
+    }
+}
+
+
+#[derive(Debug, PartialEq)]
+pub enum TickerError {
+    Empty,
+    MissingYear,
+    YearTooLong(usize),
+    MissingMonth,
+    BadMonthCode(char),
+    MissingRoot,
+    BadRoot(String),
+    BadMonth(u8),
+    BadCycle(Vec<u8>),
+}
+
+impl fmt::Display for TickerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TickerError::Empty => write!(f, "symbol is empty"),
+            TickerError::MissingYear => write!(f, "symbol has no year digits"),
+            TickerError::YearTooLong(n) => {
+                write!(f, "year has {n} digits, expected 1 or 2")
+            }
+            TickerError::MissingMonth => write!(f, "symbol has no month code"),
+            TickerError::BadMonthCode(c) => {
+                write!(f, "'{c}' is not a month code (F-Z)")
+            }
+            TickerError::MissingRoot => write!(f, "symbol has no root"),
+            TickerError::BadRoot(root) => {
+                write!(f, "root '{root}' must be letters or digits")
+            }
+            TickerError::BadMonth(month) => {
+                write!(f, "month {month} is not between 1 and 12")
+            }
+            TickerError::BadCycle(cycle) => write!(
+                f,
+                "cycle {cycle:?} must be months 1-12 in increasing order"
+            ),
+        }
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FuturesContract {
+    pub root: String,
+    month: u8,
+    pub year: u16,
+    year_digits: u8
+}
+
+impl FuturesContract {
+
+    pub fn new(
+        root: &str,
+        month: u8,
+        year: u16
+    ) -> Result<FuturesContract, TickerError> {
+        let root: String = root.trim().to_uppercase();
+        check_root(&root)?;
+        if month_code(month).is_none() {
+            return Err(TickerError::BadMonth(month));
+        }
+        Ok(FuturesContract { root, month, year, year_digits: 2 })
+    }
+
+    pub fn month(&self) -> u8 {
+        self.month
+    }
+
+    // Parses a symbol like "ESM24" or "CLZ5". The reference month decides
+    // which decade or century a short year lands in.
+    pub fn parse(
+        symbol: &str,
+        reference: YearMonth
+    ) -> Result<FuturesContract, TickerError> {
+        let symbol: String = symbol.trim().to_uppercase();
+        if symbol.is_empty() {
+            return Err(TickerError::Empty);
+        }
+
+        // Peel the pieces off the end, since the root has no fixed length
+        let root_and_month: &str = symbol.trim_end_matches(|c: char| {
+            c.is_ascii_digit()
+        });
+        let digits: &str = &symbol[root_and_month.len()..];
+        match digits.len() {
+            0 => return Err(TickerError::MissingYear),
+            1 | 2 => (),
+            n => return Err(TickerError::YearTooLong(n)),
+        }
+
+        let code: char = root_and_month.chars()
+            .last()
+            .ok_or(TickerError::MissingMonth)?;
+        let month: u8 = month_from_code(code)
+            .ok_or(TickerError::BadMonthCode(code))?;
+
+        let root: &str = &root_and_month[..root_and_month.len() - 1];
+        check_root(root)?;
+
+        let short_year: u16 = digits.parse().unwrap();
+        let year_digits: u8 = digits.len() as u8;
+        Ok(FuturesContract {
+            root: root.to_string(),
+            month,
+            year: resolve_year(short_year, year_digits, month, reference),
+            year_digits
+        })
+    }
+
+    // month is checked when the contract is made, so this always exists
+    pub fn month_code(&self) -> char {
+        MONTH_CODES[self.month as usize - 1]
+    }
+
+    pub fn expiry(&self) -> YearMonth {
+        YearMonth { year: self.year, month: self.month }
+    }
+
+    pub fn describe(&self) -> String {
+        format!(
+            "{} {} {}",
+            self.root,
+            MONTH_NAMES[self.month as usize - 1],
+            self.year
+        )
+    }
+}
+
+impl fmt::Display for FuturesContract {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let modulus: u16 = if self.year_digits == 1 { 10 } else { 100 };
+        write!(
+            f,
+            "{}{}{:0width$}",
+            self.root,
+            self.month_code(),
+            self.year % modulus,
+            width = self.year_digits as usize
+        )
+    }
+}
+
+
+fn check_root(root: &str) -> Result<(), TickerError> {
+    if root.is_empty() {
+        return Err(TickerError::MissingRoot);
+    }
+    if !root.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return Err(TickerError::BadRoot(root.to_string()));
+    }
+    Ok(())
+}
+
+
+/*
+* One digit years repeat every decade, so "Z5" is read as the next December
+* ending in 5 that hasn't expired yet. Two digit years repeat every century,
+* and those are read as the closest matching year, so old contracts like
+* "ESM24" still come out as 2024.
+*/
+fn resolve_year(
+    short_year: u16,
+    digits: u8,
+    month: u8,
+    reference: YearMonth
+) -> u16 {
+    if digits == 1 {
+        let mut year: u16 = reference.year - reference.year % 10 + short_year;
+        if (YearMonth { year, month }) < reference {
+            year += 10;
+        }
+        year
+    } else {
+        let century: u16 = reference.year - reference.year % 100;
+        [century.saturating_sub(100), century, century + 100]
+            .iter()
+            .map(|c| c + short_year)
+            .min_by_key(|y| y.abs_diff(reference.year))
+            .unwrap()
+    }
+}
+
+
+// The next `count` contracts for a root, starting from the reference month,
+// following a listing cycle such as QUARTERLY. The cycle has to be months
+// 1-12 in increasing order, each listed once.
+pub fn front_months(
+    root: &str,
+    cycle: &[u8],
+    reference: YearMonth,
+    count: usize
+) -> Result<Vec<FuturesContract>, TickerError> {
+    let in_order: bool = cycle.windows(2).all(|pair| pair[0] < pair[1]);
+    if !in_order || cycle.iter().any(|m| month_code(*m).is_none()) {
+        return Err(TickerError::BadCycle(cycle.to_vec()));
+    }
+    let mut contracts: Vec<FuturesContract> = Vec::new();
+    let mut year: u16 = reference.year;
+    while contracts.len() < count && !cycle.is_empty() {
+        for month in cycle {
+            if (YearMonth { year, month: *month }) < reference {
+                continue;
+            }
+            if contracts.len() == count {
+                break;
+            }
+            contracts.push(FuturesContract::new(root, *month, year)?);
+        }
+        year += 1;
+    }
+    Ok(contracts)
+}
+
+
+pub fn run() {
+    println!("\n{}", header("FUTURES TICKERS"));
+    let today: YearMonth = YearMonth { year: 2024, month: 4 };
+    for symbol in ["ESM24", "CLZ5", "6EH25", "ESA24", "ES24", "ESM2024"] {
+        match FuturesContract::parse(symbol, today) {
+            Ok(c) => println!("  {symbol:<8} -> {} ({c})", c.describe()),
+            Err(e) => println!("  {symbol:<8} -> error: {e}"),
+        }
+    }
+
+    let fronts: Vec<String> = front_months("ES", &QUARTERLY, today, 4)
+        .unwrap()
+        .iter()
+        .map(|c| c.to_string())
+        .collect();
+    println!("  ES front months: {}", fronts.join(", "));
+}
+
+
+pub fn register_layouts(registry: &mut Registry) {
+    registry.register("Chapter 3", layout_of!(YearMonth { year, month }));
+    registry.register("Chapter 3", layout_of!(TickerError));
+    registry.register("Chapter 3", layout_of!(FuturesContract {
+        root, month, year, year_digits
+    }));
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const APRIL_2024: YearMonth = YearMonth { year: 2024, month: 4 };
+
+    fn parse(symbol: &str) -> Result<FuturesContract, TickerError> {
+        FuturesContract::parse(symbol, APRIL_2024)
+    }
+
+    #[test]
+    fn parses_symbols() {
+        let es: FuturesContract = parse("esm24").unwrap();
+        assert_eq!((es.root.as_str(), es.month(), es.year), ("ES", 6, 2024));
+        assert_eq!(parse("6EH25").unwrap().expiry(),
+            YearMonth { year: 2025, month: 3 });
+        // One digit years go forward to the next unexpired one
+        assert_eq!(parse("CLZ5").unwrap().year, 2025);
+        assert_eq!(parse("CLF4").unwrap().year, 2034);
+        assert_eq!(parse("CLJ4").unwrap().year, 2024);
+        // Two digit years go to the closest century
+        assert_eq!(parse("ESZ99").unwrap().year, 1999);
+        assert_eq!(parse("ESZ70").unwrap().year, 2070);
+    }
+
+    #[test]
+    fn rejects_bad_symbols() {
+        assert_eq!(parse("  "), Err(TickerError::Empty));
+        assert_eq!(parse("ESM"), Err(TickerError::MissingYear));
+        assert_eq!(parse("ESM2024"), Err(TickerError::YearTooLong(4)));
+        assert_eq!(parse("24"), Err(TickerError::MissingMonth));
+        assert_eq!(parse("ESA24"), Err(TickerError::BadMonthCode('A')));
+        assert_eq!(parse("M24"), Err(TickerError::MissingRoot));
+        assert_eq!(parse("E-SM24"), Err(TickerError::BadRoot("E-S".into())));
+    }
+
+    #[test]
+    fn formats_symbols() {
+        for symbol in ["ESM24", "CLZ5", "6EH25", "ZNU09"] {
+            let contract: FuturesContract = parse(symbol).unwrap();
+            assert_eq!(contract.to_string(), symbol);
+        }
+        let nq: FuturesContract = FuturesContract::new("nq", 12, 2031)
+            .unwrap();
+        assert_eq!(nq.to_string(), "NQZ31");
+        assert_eq!(nq.describe(), "NQ Dec 2031");
+    }
+
+    #[test]
+    fn new_checks_its_input() {
+        assert_eq!(FuturesContract::new("ES", 0, 2024),
+            Err(TickerError::BadMonth(0)));
+        assert_eq!(FuturesContract::new("ES", 13, 2024),
+            Err(TickerError::BadMonth(13)));
+        assert_eq!(FuturesContract::new("", 3, 2024),
+            Err(TickerError::MissingRoot));
+        assert_eq!(FuturesContract::new("E S", 3, 2024),
+            Err(TickerError::BadRoot("E S".into())));
+    }
+
+    #[test]
+    fn front_months_follow_the_cycle() {
+        let fronts: Vec<String> = front_months("ES", &QUARTERLY, APRIL_2024, 5)
+            .unwrap()
+            .iter()
+            .map(|c| c.to_string())
+            .collect();
+        assert_eq!(fronts, ["ESM24", "ESU24", "ESZ24", "ESH25", "ESM25"]);
+        assert_eq!(front_months("ES", &[], APRIL_2024, 3), Ok(Vec::new()));
+        for bad in [&[6, 3][..], &[3, 3], &[0, 6], &[3, 13]] {
+            assert_eq!(front_months("ES", bad, APRIL_2024, 2),
+                Err(TickerError::BadCycle(bad.to_vec())));
+        }
+    }
+}
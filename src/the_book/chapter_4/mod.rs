@@ -1,7 +1,9 @@
 mod slice_samples;
+pub mod words;
+pub mod wrap;
 
 
 pub fn run() {
     slice_samples::run();
+    wrap::run();
 }
-
@@ -1,17 +1,4 @@
-
-fn get_first_word(sentence: &String) -> &str {
-    
-    // Slice the string 
-    for (i, ch) in sentence.bytes().enumerate() {
-        if ch == b' ' {
-            
-            // Using .. is like using : in Python,
-            // to get a string slice.
-            return &sentence[..i] 
-        };
-    }
-    &sentence[..]
-}
+use super::words;
 
 
 pub fn run() {
@@ -19,8 +6,10 @@ pub fn run() {
     // Create a string
     let s: String = String::from("Hell no, world!");
 
-    // Slice the string 
-    let sl: &str = get_first_word(&s);
-    println!("First word: {sl}")
-}
+    // Slice the string. first_word used to scan the bytes for b' ' by
+    // hand, see words.rs for a version that handles any whitespace.
+    let sl: &str = words::first_word(&s).unwrap_or("");
+    println!("First word: {sl}");
 
+    words::run();
+}
@@ -0,0 +1,247 @@
+use std::ops::Range;
+
+use crate::the_book::chapter_3::char_inspector::general_category;
+
+
+/*
+* A word iterator that hands out slices of the original string, so nothing
+* is copied. Words are split on any Unicode whitespace (tabs, newlines,
+* no-break spaces, ideographic spaces...), not just b' '.
+*
+* The iterator keeps a front and a back position into the text, so it can be
+* walked from either end. That is what makes last_word cheap.
+*/
+pub struct Words<'a> {
+    text: &'a str,
+    front: usize,
+    back: usize,
+    strip_punctuation: bool,
+}
+
+pub fn words(text: &str) -> Words<'_> {
+    Words {
+        text,
+        front: 0,
+        back: text.len(),
+        strip_punctuation: false,
+    }
+}
+
+impl<'a> Words<'a> {
+
+    // Trims punctuation from both ends of each word, so "world!" becomes
+    // "world" and a lone "--" is skipped. Apostrophes inside words stay.
+    pub fn strip_punctuation(mut self) -> Words<'a> {
+        self.strip_punctuation = true;
+        self
+    }
+
+    // Byte ranges instead of slices. &text[range] gives the word back.
+    pub fn spans(self) -> impl DoubleEndedIterator<Item = Range<usize>> + 'a {
+        Spans { words: self }
+    }
+
+    fn trim(&self, span: Range<usize>) -> Option<Range<usize>> {
+        if !self.strip_punctuation {
+            return Some(span);
+        }
+        let word: &str = &self.text[span.clone()];
+        let start_trimmed: &str = word.trim_start_matches(is_punctuation);
+        let trimmed: &str = start_trimmed.trim_end_matches(is_punctuation);
+        if trimmed.is_empty() {
+            return None;
+        }
+        let start: usize = span.start + (word.len() - start_trimmed.len());
+        Some(start..start + trimmed.len())
+    }
+
+    fn next_span(&mut self) -> Option<Range<usize>> {
+        loop {
+            let rest: &str = &self.text[self.front..self.back];
+            let skipped: usize = rest.find(|c: char| !c.is_whitespace())?;
+            let start: usize = self.front + skipped;
+            let end: usize = self.text[start..self.back]
+                .find(char::is_whitespace)
+                .map(|i| start + i)
+                .unwrap_or(self.back);
+            self.front = end;
+            if let Some(span) = self.trim(start..end) {
+                return Some(span);
+            }
+        }
+    }
+
+    fn next_span_back(&mut self) -> Option<Range<usize>> {
+        loop {
+            let rest: &str = &self.text[self.front..self.back];
+            let (last, ch) = rest.char_indices()
+                .rev()
+                .find(|(_, c)| !c.is_whitespace())?;
+            let end: usize = self.front + last + ch.len_utf8();
+            let start: usize = self.text[self.front..end]
+                .rfind(char::is_whitespace)
+                .map(|i| {
+                    let ws: char = self.text[self.front + i..].chars()
+                        .next()
+                        .unwrap();
+                    self.front + i + ws.len_utf8()
+                })
+                .unwrap_or(self.front);
+            self.back = start;
+            if let Some(span) = self.trim(start..end) {
+                return Some(span);
+            }
+        }
+    }
+}
+
+impl<'a> Iterator for Words<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        let span: Range<usize> = self.next_span()?;
+        Some(&self.text[span])
+    }
+}
+
+impl<'a> DoubleEndedIterator for Words<'a> {
+    fn next_back(&mut self) -> Option<&'a str> {
+        let span: Range<usize> = self.next_span_back()?;
+        Some(&self.text[span])
+    }
+}
+
+
+struct Spans<'a> {
+    words: Words<'a>,
+}
+
+impl Iterator for Spans<'_> {
+    type Item = Range<usize>;
+
+    fn next(&mut self) -> Option<Range<usize>> {
+        self.words.next_span()
+    }
+}
+
+impl DoubleEndedIterator for Spans<'_> {
+    fn next_back(&mut self) -> Option<Range<usize>> {
+        self.words.next_span_back()
+    }
+}
+
+
+fn is_punctuation(ch: char) -> bool {
+    ch.is_ascii_punctuation() || general_category(ch).starts_with('P')
+}
+
+
+pub fn first_word(text: &str) -> Option<&str> {
+    words(text).next()
+}
+
+pub fn last_word(text: &str) -> Option<&str> {
+    words(text).next_back()
+}
+
+pub fn nth_word(text: &str, n: usize) -> Option<&str> {
+    words(text).nth(n)
+}
+
+pub fn word_spans(text: &str) -> Vec<Range<usize>> {
+    words(text).spans().collect()
+}
+
+
+pub fn run() {
+    println!("\nWords:");
+    let sentence: &str = "  Hell no,\tworld!\u{3000}Zoë says «bonjour» 🦀\n";
+    println!("  Text: {sentence:?}");
+    println!("  Words: {:?}", words(sentence).collect::<Vec<&str>>());
+    println!(
+        "  Stripped: {:?}",
+        words(sentence).strip_punctuation().collect::<Vec<&str>>()
+    );
+    println!("  First: {:?}  Last: {:?}  Third: {:?}",
+        first_word(sentence), last_word(sentence), nth_word(sentence, 2));
+    println!("  Spans: {:?}", word_spans(sentence));
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_on_unicode_whitespace() {
+        let text: &str = "one\ttwo\nthree\u{00A0}four\u{3000}five";
+        let found: Vec<&str> = words(text).collect();
+        assert_eq!(found, ["one", "two", "three", "four", "five"]);
+    }
+
+    #[test]
+    fn empty_and_blank_text() {
+        assert_eq!(first_word(""), None);
+        assert_eq!(last_word(" \t\n "), None);
+        assert_eq!(words("   ").count(), 0);
+    }
+
+    #[test]
+    fn first_last_and_nth() {
+        let text: &str = "  Hell no, world!  ";
+        assert_eq!(first_word(text), Some("Hell"));
+        assert_eq!(last_word(text), Some("world!"));
+        assert_eq!(nth_word(text, 1), Some("no,"));
+        assert_eq!(nth_word(text, 3), None);
+    }
+
+    #[test]
+    fn multibyte_words_and_spans() {
+        let text: &str = "naïve café\u{2003}日本語 🦀";
+        let found: Vec<&str> = words(text).collect();
+        assert_eq!(found, ["naïve", "café", "日本語", "🦀"]);
+        for (span, word) in word_spans(text).into_iter().zip(found) {
+            assert_eq!(&text[span], word);
+        }
+        assert_eq!(word_spans(text)[0], 0..6);
+        assert_eq!(last_word(text), Some("🦀"));
+    }
+
+    #[test]
+    fn reverse_matches_forward() {
+        let text: &str = " «Zoë», 日本語\tsays — hi… ";
+        let mut forward: Vec<&str> = words(text)
+            .strip_punctuation()
+            .collect();
+        forward.reverse();
+        let backward: Vec<&str> = words(text)
+            .strip_punctuation()
+            .rev()
+            .collect();
+        assert_eq!(forward, backward);
+    }
+
+    #[test]
+    fn punctuation_stripping() {
+        let text: &str = "\"Hello,\" she said -- «bonjour»! don't… ¿qué?";
+        let found: Vec<&str> = words(text).strip_punctuation().collect();
+        assert_eq!(
+            found,
+            ["Hello", "she", "said", "bonjour", "don't", "qué"]
+        );
+        let spans: Vec<Range<usize>> = words(text)
+            .strip_punctuation()
+            .spans()
+            .collect();
+        assert_eq!(&text[spans[3].clone()], "bonjour");
+    }
+
+    #[test]
+    fn meeting_in_the_middle() {
+        let mut it: Words = words("a b c");
+        assert_eq!(it.next(), Some("a"));
+        assert_eq!(it.next_back(), Some("c"));
+        assert_eq!(it.next(), Some("b"));
+        assert_eq!(it.next_back(), None);
+    }
+}
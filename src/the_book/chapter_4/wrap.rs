@@ -0,0 +1,383 @@
+use std::ops::Range;
+
+use super::words::words;
+use crate::text::display_width;
+
+
+/*
+* Line wrapping with string slices. Every wrapped line is a &str pointing
+* back into the original text, so wrapping a long explanation doesn't copy
+* any of it. The only allocations are the Vec of lines and a little
+* bookkeeping per paragraph.
+*
+* Widths are display columns (see text::display_width), not bytes.
+*
+* Two ways to pick the line breaks:
+*   Greedy          fill each line as much as possible, then move on
+*   MinRaggedness   look at the whole paragraph and pick the breaks that
+*                   keep the right edge most even (the idea behind Knuth and
+*                   Plass's TeX algorithm, without the stretchy glue)
+*/
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    Greedy,
+    MinRaggedness,
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct WrapOptions {
+    pub width: usize,
+    pub mode: Mode,
+    pub initial_indent: usize,
+    pub subsequent_indent: usize,
+    pub break_hyphens: bool,
+}
+
+impl WrapOptions {
+
+    pub fn new(width: usize) -> WrapOptions {
+        WrapOptions {
+            width,
+            mode: Mode::Greedy,
+            initial_indent: 0,
+            subsequent_indent: 0,
+            break_hyphens: true,
+        }
+    }
+
+    pub fn mode(mut self, mode: Mode) -> WrapOptions {
+        self.mode = mode;
+        self
+    }
+
+    // A hanging indent is a small (or zero) first indent with a bigger one
+    // for the rest of the paragraph, like a bullet point or a definition.
+    pub fn indent(mut self, initial: usize, subsequent: usize) -> WrapOptions {
+        self.initial_indent = initial;
+        self.subsequent_indent = subsequent;
+        self
+    }
+
+    pub fn break_hyphens(mut self, allowed: bool) -> WrapOptions {
+        self.break_hyphens = allowed;
+        self
+    }
+
+    fn available(&self, first_line: bool) -> usize {
+        let indent: usize = if first_line {
+            self.initial_indent
+        } else {
+            self.subsequent_indent
+        };
+        self.width.saturating_sub(indent).max(1)
+    }
+}
+
+
+/*
+* A piece of a paragraph that can't be split. Usually a whole word, but
+* "well-known" becomes "well-" and "known" when hyphen breaks are allowed.
+* `glued` means there's no space before the next fragment.
+*/
+#[derive(Debug)]
+struct Fragment {
+    span: Range<usize>,
+    start_col: usize,
+    end_col: usize,
+    glued: bool,
+}
+
+
+fn fragments(paragraph: &str, break_hyphens: bool) -> Vec<Fragment> {
+    let mut spans: Vec<(Range<usize>, bool)> = Vec::new();
+    for word in words(paragraph).spans() {
+        let mut start: usize = word.start;
+        if break_hyphens {
+            let text: &str = &paragraph[word.clone()];
+            for (i, ch) in text.char_indices() {
+                let after: usize = word.start + i + ch.len_utf8();
+                // Only break after a hyphen that sits between two letters,
+                // so "--" and a trailing "-" stay put.
+                let prev_ok: bool = paragraph[start..word.start + i].chars()
+                    .last()
+                    .is_some_and(|c| c.is_alphanumeric());
+                let next_ok: bool = paragraph[after..word.end].chars()
+                    .next()
+                    .is_some_and(|c| c.is_alphanumeric());
+                if ch == '-' && prev_ok && next_ok {
+                    spans.push((start..after, true));
+                    start = after;
+                }
+            }
+        }
+        spans.push((start..word.end, false));
+    }
+
+    // Lay the paragraph out on one endless line to get each fragment's
+    // columns. The width of any run of fragments is then a subtraction.
+    let mut out: Vec<Fragment> = Vec::with_capacity(spans.len());
+    let mut col: usize = 0;
+    for (i, (span, glued)) in spans.iter().enumerate() {
+        if i > 0 && !spans[i - 1].1 {
+            let gap: &str = &paragraph[spans[i - 1].0.end..span.start];
+            col += gap.chars().count();
+        }
+        let width: usize = display_width(&paragraph[span.clone()]);
+        out.push(Fragment {
+            span: span.clone(),
+            start_col: col,
+            end_col: col + width,
+            glued: *glued,
+        });
+        col += width;
+    }
+    out
+}
+
+
+fn run_width(frags: &[Fragment], i: usize, j: usize) -> usize {
+    frags[j - 1].end_col - frags[i].start_col
+}
+
+
+// Break indices for one paragraph: line k is fragments breaks[k]..breaks[k+1]
+fn greedy_breaks(frags: &[Fragment], options: &WrapOptions) -> Vec<usize> {
+    let mut breaks: Vec<usize> = vec![0];
+    let mut start: usize = 0;
+    for j in 1..=frags.len() {
+        let limit: usize = options.available(breaks.len() == 1);
+        if j - 1 > start && run_width(frags, start, j) > limit {
+            breaks.push(j - 1);
+            start = j - 1;
+        }
+    }
+    breaks.push(frags.len());
+    breaks
+}
+
+
+/*
+* Minimum raggedness by dynamic programming. best[j] is the lowest cost of
+* setting the first j fragments, and a line from i to j costs the square of
+* its leftover space. Squaring is what spreads the slack out, since two
+* lines 3 short (9 + 9) beat one line 6 short (36 + 0). The last line is
+* free, and breaking at a hyphen costs a little extra.
+*/
+fn optimal_breaks(frags: &[Fragment], options: &WrapOptions) -> Vec<usize> {
+    const OVERFLOW: u64 = 1_000_000;
+    const HYPHEN: u64 = 25;
+    let n: usize = frags.len();
+    // Runs only get wider as i goes down, so once one is too wide for
+    // every line it can stop. The first line can be the wider one with a
+    // hanging indent, so it's the larger of the two limits that counts.
+    let widest: usize = options.available(true).max(options.available(false));
+    let mut best: Vec<u64> = vec![u64::MAX; n + 1];
+    let mut from: Vec<usize> = vec![0; n + 1];
+    best[0] = 0;
+    for j in 1..=n {
+        for i in (0..j).rev() {
+            let limit: usize = options.available(i == 0);
+            let width: usize = run_width(frags, i, j);
+            if width > widest && i < j - 1 {
+                break;
+            }
+            let mut cost: u64 = if width > limit {
+                OVERFLOW
+            } else if j == n {
+                0
+            } else {
+                ((limit - width) as u64).pow(2)
+            };
+            if j < n && frags[j - 1].glued {
+                cost += HYPHEN;
+            }
+            if best[i] != u64::MAX && best[i] + cost < best[j] {
+                best[j] = best[i] + cost;
+                from[j] = i;
+            }
+        }
+    }
+
+    let mut breaks: Vec<usize> = vec![n];
+    let mut j: usize = n;
+    while j > 0 {
+        j = from[j];
+        breaks.push(j);
+    }
+    breaks.reverse();
+    breaks
+}
+
+
+// Wraps text into lines that borrow from it. Existing newlines start a new
+// paragraph, and blank lines are kept as empty lines.
+pub fn wrap<'a>(text: &'a str, options: &WrapOptions) -> Vec<&'a str> {
+    let mut lines: Vec<&'a str> = Vec::new();
+    for paragraph in text.lines() {
+        let frags: Vec<Fragment> = fragments(paragraph, options.break_hyphens);
+        if frags.is_empty() {
+            lines.push("");
+            continue;
+        }
+        let breaks: Vec<usize> = match options.mode {
+            Mode::Greedy => greedy_breaks(&frags, options),
+            Mode::MinRaggedness => optimal_breaks(&frags, options),
+        };
+        for pair in breaks.windows(2) {
+            let first: &Fragment = &frags[pair[0]];
+            let last: &Fragment = &frags[pair[1] - 1];
+            lines.push(&paragraph[first.span.start..last.span.end]);
+        }
+    }
+    lines
+}
+
+
+// The wrapped lines joined back together with their indents. Paragraph
+// starts are tracked so every paragraph gets the initial indent.
+pub fn fill(text: &str, options: &WrapOptions) -> String {
+    render(text, options, false)
+}
+
+
+// Like fill, but stretches every line except the last of each paragraph so
+// both edges are straight. Extra spaces go to the leftmost gaps first.
+pub fn justify(text: &str, options: &WrapOptions) -> String {
+    render(text, options, true)
+}
+
+
+fn render(text: &str, options: &WrapOptions, justified: bool) -> String {
+    let mut out: String = String::new();
+    for paragraph in text.lines() {
+        let lines: Vec<&str> = wrap(paragraph, options);
+        for (i, line) in lines.iter().enumerate() {
+            let indent: usize = if i == 0 {
+                options.initial_indent
+            } else {
+                options.subsequent_indent
+            };
+            if !line.is_empty() {
+                out.push_str(&" ".repeat(indent));
+            }
+            if justified && i + 1 < lines.len() {
+                out.push_str(&justify_line(line, options.available(i == 0)));
+            } else {
+                out.push_str(line);
+            }
+            out.push('\n');
+        }
+    }
+    out
+}
+
+
+fn justify_line(line: &str, width: usize) -> String {
+    let parts: Vec<&str> = words(line).collect();
+    if parts.len() < 2 {
+        return line.to_string();
+    }
+    let used: usize = parts.iter().map(|w| display_width(w)).sum();
+    let gaps: usize = parts.len() - 1;
+    let spaces: usize = width.saturating_sub(used).max(gaps);
+    let mut out: String = String::new();
+    for (i, part) in parts.iter().enumerate() {
+        out.push_str(part);
+        if i < gaps {
+            let extra: usize = if i < spaces % gaps { 1 } else { 0 };
+            out.push_str(&" ".repeat(spaces / gaps + extra));
+        }
+    }
+    out
+}
+
+
+pub fn run() {
+    println!("\nWrapping:");
+    let text: &str = "Slices let a function look at part of a string without \
+        taking ownership of it. A well-known trick is to hand back &str \
+        values that point into the caller's data, which keeps everything \
+        zero-copy and allocation-free.";
+    let ruler: String = "-".repeat(30);
+
+    for mode in [Mode::Greedy, Mode::MinRaggedness] {
+        println!("  {mode:?}:\n  {ruler}");
+        for line in wrap(text, &WrapOptions::new(30).mode(mode)) {
+            println!("  {line}");
+        }
+    }
+
+    println!("  Justified, hanging indent:\n  {ruler}");
+    let options: WrapOptions = WrapOptions::new(30)
+        .mode(Mode::MinRaggedness)
+        .indent(0, 4);
+    for line in justify(text, &options).lines() {
+        println!("  {line}");
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &str = "aaa bb cc ddddd";
+
+    #[test]
+    fn greedy_fills_each_line() {
+        let options: WrapOptions = WrapOptions::new(6);
+        assert_eq!(wrap(TEXT, &options), ["aaa bb", "cc", "ddddd"]);
+        assert_eq!(wrap("one\n\ntwo", &options), ["one", "", "two"]);
+        // A word wider than the line gets a line of its own
+        assert_eq!(wrap("a abcdefgh b", &options), ["a", "abcdefgh", "b"]);
+    }
+
+    #[test]
+    fn min_raggedness_evens_out_the_edge() {
+        let options: WrapOptions = WrapOptions::new(6)
+            .mode(Mode::MinRaggedness);
+        assert_eq!(wrap(TEXT, &options), ["aaa", "bb cc", "ddddd"]);
+    }
+
+    #[test]
+    fn hanging_indent_first_line_can_be_longer() {
+        for mode in [Mode::Greedy, Mode::MinRaggedness] {
+            let options: WrapOptions = WrapOptions::new(10)
+                .indent(0, 4)
+                .mode(mode);
+            assert_eq!(wrap("a b ccccc", &options), ["a b ccccc"], "{mode:?}");
+            assert_eq!(
+                fill("a b ccccc dd", &options),
+                "a b ccccc\n    dd\n",
+                "{mode:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn breaks_at_existing_hyphens() {
+        let options: WrapOptions = WrapOptions::new(8);
+        assert_eq!(wrap("a well-known fact", &options),
+            ["a well-", "known", "fact"]);
+        let options: WrapOptions = options.break_hyphens(false);
+        assert_eq!(wrap("a well-known fact", &options),
+            ["a", "well-known", "fact"]);
+    }
+
+    #[test]
+    fn lines_borrow_from_the_text() {
+        let text: String = String::from("zero copy 日本語 lines");
+        let range = text.as_bytes().as_ptr_range();
+        for line in wrap(&text, &WrapOptions::new(9)) {
+            assert!(range.contains(&line.as_ptr()));
+            assert!(display_width(line) <= 9);
+        }
+    }
+
+    #[test]
+    fn justify_stretches_all_but_the_last_line() {
+        let options: WrapOptions = WrapOptions::new(8);
+        assert_eq!(justify("aa b c dd e", &options), "aa  b  c\ndd e\n");
+    }
+}
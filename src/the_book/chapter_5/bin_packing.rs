@@ -0,0 +1,584 @@
+use super::rectangle::{FitOptions, Rect, Rectangle};
+use crate::layout::{layout_of, Registry};
+
+
+/*
+* 2D bin packing: place a list of rectangles (labels) onto as few fixed size
+* bins (sheets) as possible. Finding the best answer is NP-hard, so these
+* are the usual heuristics, from simplest to best packing:
+*
+*   Shelf       rows of items, each row as tall as its tallest item
+*   Guillotine  every placement cuts the free space into two smaller rects,
+*               like cutting a sheet with a guillotine
+*   MaxRects    keeps every maximal free rectangle, even overlapping ones,
+*               so it sees more of the free space than guillotine cuts can
+*
+* Items are packed largest first, which helps all three a lot.
+*/
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Heuristic {
+    Shelf,
+    Guillotine,
+    MaxRects,
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Placement {
+    pub item: usize,
+    pub bin: usize,
+    pub rect: Rect,
+    pub rotated: bool,
+}
+
+
+#[derive(Debug)]
+pub struct Packing {
+    pub bin_size: Rectangle,
+    pub bins: usize,
+    pub placements: Vec<Placement>,
+    pub unplaced: Vec<usize>,
+}
+
+
+// One open bin. Each heuristic keeps track of free space its own way.
+trait Bin {
+    fn insert(&mut self, item: Rectangle, allow_rotation: bool)
+        -> Option<(Rect, bool)>;
+}
+
+
+// The orientations worth trying, built with rotate() from chapter 5
+fn orientations(item: Rectangle, allow_rotation: bool) -> Vec<(Rectangle, bool)> {
+    let mut out: Vec<(Rectangle, bool)> = vec![(item, false)];
+    if allow_rotation && item.width != item.height {
+        let mut turned: Rectangle = item;
+        turned.rotate();
+        out.push((turned, true));
+    }
+    out
+}
+
+const EXACT: FitOptions = FitOptions {
+    allow_rotation: false,
+    allow_exact: true,
+};
+
+
+// SHELF
+struct Shelf {
+    y: u32,
+    height: u32,
+    used: u32,
+}
+
+struct ShelfBin {
+    size: Rectangle,
+    shelves: Vec<Shelf>,
+}
+
+impl Bin for ShelfBin {
+    fn insert(&mut self, item: Rectangle, allow_rotation: bool)
+        -> Option<(Rect, bool)>
+    {
+        let options: Vec<(Rectangle, bool)> = orientations(item, allow_rotation);
+
+        // First shelf with room, trying each orientation
+        for shelf in self.shelves.iter_mut() {
+            let space = Rectangle {
+                width: self.size.width - shelf.used,
+                height: shelf.height,
+            };
+            for (r, rotated) in &options {
+                if r.fits_inside(&space, EXACT) {
+                    let rect: Rect = Rect::new(shelf.used, shelf.y, r.width,
+                        r.height);
+                    shelf.used += r.width;
+                    return Some((rect, *rotated));
+                }
+            }
+        }
+
+        // Otherwise start a new shelf, lying the item flat to keep it short
+        let top: u32 = self.shelves.last()
+            .map(|s| s.y + s.height)
+            .unwrap_or(0);
+        let space = Rectangle {
+            width: self.size.width,
+            height: self.size.height - top,
+        };
+        let (r, rotated) = options.into_iter()
+            .filter(|(r, _)| r.fits_inside(&space, EXACT))
+            .min_by_key(|(r, _)| r.height)?;
+        self.shelves.push(Shelf { y: top, height: r.height, used: r.width });
+        Some((Rect::new(0, top, r.width, r.height), rotated))
+    }
+}
+
+
+// GUILLOTINE
+struct GuillotineBin {
+    free: Vec<Rect>,
+}
+
+impl Bin for GuillotineBin {
+    fn insert(&mut self, item: Rectangle, allow_rotation: bool)
+        -> Option<(Rect, bool)>
+    {
+        // Best area fit: the free rect with the least area left over
+        let mut best: Option<(usize, Rectangle, bool, u64)> = None;
+        for (i, free) in self.free.iter().enumerate() {
+            for (r, rotated) in orientations(item, allow_rotation) {
+                if !r.fits_inside(&free.size(), EXACT) {
+                    continue;
+                }
+                let leftover: u64 = free.area() - r.area();
+                if best.is_none_or(|b| leftover < b.3) {
+                    best = Some((i, r, rotated, leftover));
+                }
+            }
+        }
+        let (i, r, rotated, _) = best?;
+        let free: Rect = self.free.swap_remove(i);
+        let placed: Rect = Rect::new(free.x, free.y, r.width, r.height);
+
+        // Cut the rest of the free rect in two. Splitting along the shorter
+        // leftover side keeps the bigger piece as large as possible.
+        let right_w: u32 = free.width - r.width;
+        let below_h: u32 = free.height - r.height;
+        let (right, below) = if right_w < below_h {
+            (
+                Rect::new(free.x + r.width, free.y, right_w, r.height),
+                Rect::new(free.x, free.y + r.height, free.width, below_h),
+            )
+        } else {
+            (
+                Rect::new(free.x + r.width, free.y, right_w, free.height),
+                Rect::new(free.x, free.y + r.height, r.width, below_h),
+            )
+        };
+        for piece in [right, below] {
+            if !piece.is_empty() {
+                self.free.push(piece);
+            }
+        }
+        Some((placed, rotated))
+    }
+}
+
+
+// MAXRECTS
+struct MaxRectsBin {
+    free: Vec<Rect>,
+}
+
+impl MaxRectsBin {
+
+    // The parts of a free rect that are left after `used` is taken out of
+    // it. Each one is as big as it can be, so they overlap each other.
+    fn split(free: &Rect, used: &Rect) -> Vec<Rect> {
+        if !free.overlaps(used) {
+            return vec![*free];
+        }
+        let mut out: Vec<Rect> = Vec::new();
+        if used.x > free.x {
+            out.push(Rect::new(free.x, free.y, used.x - free.x, free.height));
+        }
+        if used.right() < free.right() {
+            out.push(Rect::new(used.right() as u32, free.y,
+                (free.right() - used.right()) as u32, free.height));
+        }
+        if used.y > free.y {
+            out.push(Rect::new(free.x, free.y, free.width, used.y - free.y));
+        }
+        if used.bottom() < free.bottom() {
+            out.push(Rect::new(free.x, used.bottom() as u32, free.width,
+                (free.bottom() - used.bottom()) as u32));
+        }
+        out
+    }
+
+    fn prune(&mut self) {
+        let mut i: usize = 0;
+        while i < self.free.len() {
+            let inside_another: bool = self.free.iter()
+                .enumerate()
+                .any(|(j, other)| {
+                    j != i && other.contains(&self.free[i])
+                        && (other != &self.free[i] || j < i)
+                });
+            if inside_another {
+                self.free.swap_remove(i);
+            } else {
+                i += 1;
+            }
+        }
+    }
+}
+
+impl Bin for MaxRectsBin {
+    fn insert(&mut self, item: Rectangle, allow_rotation: bool)
+        -> Option<(Rect, bool)>
+    {
+        // Best short side fit: the spot where the tighter of the two
+        // leftover gaps is smallest
+        let mut best: Option<(Rect, bool, u32)> = None;
+        for free in &self.free {
+            for (r, rotated) in orientations(item, allow_rotation) {
+                if !r.fits_inside(&free.size(), EXACT) {
+                    continue;
+                }
+                let short: u32 = (free.width - r.width)
+                    .min(free.height - r.height);
+                if best.is_none_or(|b| short < b.2) {
+                    let rect: Rect = Rect::new(free.x, free.y, r.width,
+                        r.height);
+                    best = Some((rect, rotated, short));
+                }
+            }
+        }
+        let (placed, rotated, _) = best?;
+        self.free = self.free.iter()
+            .flat_map(|free| MaxRectsBin::split(free, &placed))
+            .collect();
+        self.prune();
+        Some((placed, rotated))
+    }
+}
+
+
+fn new_bin(heuristic: Heuristic, size: Rectangle) -> Box<dyn Bin> {
+    match heuristic {
+        Heuristic::Shelf => Box::new(ShelfBin { size, shelves: Vec::new() }),
+        Heuristic::Guillotine => Box::new(GuillotineBin {
+            free: vec![Rect::at_origin(size)],
+        }),
+        Heuristic::MaxRects => Box::new(MaxRectsBin {
+            free: vec![Rect::at_origin(size)],
+        }),
+    }
+}
+
+
+pub fn pack(
+    items: &[Rectangle],
+    bin_size: Rectangle,
+    heuristic: Heuristic,
+    allow_rotation: bool
+) -> Packing {
+    let mut order: Vec<usize> = (0..items.len()).collect();
+    match heuristic {
+        Heuristic::Shelf => order.sort_by_key(|i| {
+            std::cmp::Reverse(items[*i].height.max(items[*i].width))
+        }),
+        _ => order.sort_by_key(|i| std::cmp::Reverse(items[*i].area())),
+    }
+
+    let empty_fit = FitOptions { allow_rotation, allow_exact: true };
+    let mut bins: Vec<Box<dyn Bin>> = Vec::new();
+    let mut placements: Vec<Placement> = Vec::new();
+    let mut unplaced: Vec<usize> = Vec::new();
+
+    for i in order {
+        let item: Rectangle = items[i];
+        if item.area() == 0 || !item.fits_inside(&bin_size, empty_fit) {
+            unplaced.push(i);
+            continue;
+        }
+        let mut placed: bool = false;
+        for (b, bin) in bins.iter_mut().enumerate() {
+            if let Some((rect, rotated)) = bin.insert(item, allow_rotation) {
+                placements.push(Placement { item: i, bin: b, rect, rotated });
+                placed = true;
+                break;
+            }
+        }
+        if !placed {
+            let mut bin: Box<dyn Bin> = new_bin(heuristic, bin_size);
+            let (rect, rotated) = bin.insert(item, allow_rotation)
+                .expect("an item that fits the bin fits an empty bin");
+            placements.push(Placement {
+                item: i,
+                bin: bins.len(),
+                rect,
+                rotated,
+            });
+            bins.push(bin);
+        }
+    }
+    placements.sort_by_key(|p| p.item);
+
+    Packing { bin_size, bins: bins.len(), placements, unplaced }
+}
+
+
+fn label(item: usize) -> char {
+    const LABELS: &[u8] =
+        b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
+    LABELS[item % LABELS.len()] as char
+}
+
+const COLORS: [&str; 8] = [
+    "#e6194b", "#3cb44b", "#ffe119", "#4363d8",
+    "#f58231", "#911eb4", "#46f0f0", "#f032e6",
+];
+
+
+impl Packing {
+
+    pub fn used_area(&self, bin: usize) -> u64 {
+        self.placements.iter()
+            .filter(|p| p.bin == bin)
+            .map(|p| p.rect.area())
+            .sum()
+    }
+
+    pub fn wasted_area(&self) -> u64 {
+        self.bin_size.area() * self.bins as u64
+            - (0..self.bins).map(|b| self.used_area(b)).sum::<u64>()
+    }
+
+    pub fn utilization(&self) -> f64 {
+        if self.bins == 0 {
+            return 0.0;
+        }
+        let total: u64 = self.bin_size.area() * self.bins as u64;
+        (total - self.wasted_area()) as f64 / total as f64
+    }
+
+    // Each bin drawn with one character per cell, scaled down for big bins.
+    // Terminal cells are about twice as tall as they are wide, so rows
+    // cover twice as many units as columns.
+    pub fn to_ascii(&self, max_columns: u32) -> String {
+        let sx: u32 = self.bin_size.width.div_ceil(max_columns.max(1)).max(1);
+        let sy: u32 = sx * 2;
+        let columns: u32 = self.bin_size.width.div_ceil(sx);
+        let rows: u32 = self.bin_size.height.div_ceil(sy);
+        let mut out: String = String::new();
+        for bin in 0..self.bins {
+            out.push_str(&format!(
+                "Bin {} ({} of {} used)\n",
+                bin + 1,
+                self.used_area(bin),
+                self.bin_size.area()
+            ));
+            out.push_str(&format!("+{}+\n", "-".repeat(columns as usize)));
+            for row in 0..rows {
+                out.push('|');
+                for col in 0..columns {
+                    let (x, y) = (col * sx, row * sy);
+                    let cell: char = self.placements.iter()
+                        .filter(|p| p.bin == bin)
+                        .find(|p| p.rect.contains_point(x, y))
+                        .map(|p| label(p.item))
+                        .unwrap_or('.');
+                    out.push(cell);
+                }
+                out.push_str("|\n");
+            }
+            out.push_str(&format!("+{}+\n", "-".repeat(columns as usize)));
+        }
+        out
+    }
+
+    // Bins side by side, 10 units apart, each item a coloured box
+    pub fn to_svg(&self) -> String {
+        let gap: u64 = 10;
+        let width: u64 = (self.bin_size.width as u64 + gap)
+            * self.bins.max(1) as u64 + gap;
+        let height: u64 = self.bin_size.height as u64 + gap * 2;
+        let mut svg: String = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" \
+             height=\"{height}\" viewBox=\"0 0 {width} {height}\">\n"
+        );
+        for bin in 0..self.bins {
+            let left: u64 = gap + bin as u64 * (self.bin_size.width as u64 + gap);
+            svg.push_str(&format!(
+                "  <rect x=\"{left}\" y=\"{gap}\" width=\"{}\" height=\"{}\" \
+                 fill=\"#eeeeee\" stroke=\"#333333\"/>\n",
+                self.bin_size.width, self.bin_size.height
+            ));
+            for p in self.placements.iter().filter(|p| p.bin == bin) {
+                let x: u64 = left + p.rect.x as u64;
+                let y: u64 = gap + p.rect.y as u64;
+                svg.push_str(&format!(
+                    "  <rect x=\"{x}\" y=\"{y}\" width=\"{}\" height=\"{}\" \
+                     fill=\"{}\" stroke=\"#333333\"/>\n",
+                    p.rect.width,
+                    p.rect.height,
+                    COLORS[p.item % COLORS.len()]
+                ));
+                svg.push_str(&format!(
+                    "  <text x=\"{}\" y=\"{}\" font-size=\"8\" \
+                     text-anchor=\"middle\" dominant-baseline=\"middle\">\
+                     {}</text>\n",
+                    x + p.rect.width as u64 / 2,
+                    y + p.rect.height as u64 / 2,
+                    label(p.item)
+                ));
+            }
+        }
+        svg.push_str("</svg>\n");
+        svg
+    }
+}
+
+
+pub fn run() {
+    println!("\n  -- Bin Packing --");
+    let sheet = Rectangle { width: 60, height: 40 };
+    let labels: Vec<Rectangle> = [
+        (30, 20), (25, 10), (10, 25), (20, 20), (15, 10), (40, 8),
+        (12, 12), (8, 30), (18, 6), (22, 14), (9, 9), (70, 5),
+    ]
+    .iter()
+    .map(|(w, h)| Rectangle { width: *w, height: *h })
+    .collect();
+
+    for heuristic in [Heuristic::Shelf, Heuristic::Guillotine,
+        Heuristic::MaxRects]
+    {
+        for allow_rotation in [false, true] {
+            let packing: Packing = pack(&labels, sheet, heuristic,
+                allow_rotation);
+            println!(
+                "{:<10} rotation {:<5}: {} sheet(s), {} wasted, \
+                 {:.0}% used, unplaced {:?}",
+                format!("{heuristic:?}"),
+                allow_rotation,
+                packing.bins,
+                packing.wasted_area(),
+                packing.utilization() * 100.0,
+                packing.unplaced
+            );
+        }
+    }
+
+    let best: Packing = pack(&labels, sheet, Heuristic::MaxRects, true);
+    print!("{}", best.to_ascii(60));
+    println!("As SVG: {} bytes", best.to_svg().len());
+}
+
+
+pub fn register_layouts(registry: &mut Registry) {
+    registry.register("Chapter 5", layout_of!(Heuristic));
+    registry.register("Chapter 5", layout_of!(Placement {
+        item, bin, rect, rotated
+    }));
+    registry.register("Chapter 5", layout_of!(Packing {
+        bin_size, bins, placements, unplaced
+    }));
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    const CASES: usize = 200;
+
+    const HEURISTICS: [Heuristic; 3] = [
+        Heuristic::Shelf,
+        Heuristic::Guillotine,
+        Heuristic::MaxRects,
+    ];
+
+    // Mostly items that fit, plus the odd empty or oversized one
+    fn random_items(rng: &mut StdRng, bin: Rectangle) -> Vec<Rectangle> {
+        let count: usize = rng.gen_range(0..30);
+        (0..count)
+            .map(|_| Rectangle {
+                width: rng.gen_range(0..=bin.width + 5),
+                height: rng.gen_range(0..=bin.height / 2),
+            })
+            .collect()
+    }
+
+    // Every placement lies inside its bin, none overlap, and every item is
+    // either placed once with its own size or reported as unplaced
+    fn check(items: &[Rectangle], packing: &Packing, allow_rotation: bool) {
+        let bin: Rect = Rect::at_origin(packing.bin_size);
+        let mut seen: Vec<usize> = vec![0; items.len()];
+        for (n, p) in packing.placements.iter().enumerate() {
+            seen[p.item] += 1;
+            assert!(p.bin < packing.bins);
+            assert!(bin.contains(&p.rect), "{p:?} outside the bin");
+            let size: Rectangle = if p.rotated {
+                assert!(allow_rotation);
+                items[p.item].rotated()
+            } else {
+                items[p.item]
+            };
+            assert_eq!(p.rect.size(), size);
+            for other in &packing.placements[n + 1..] {
+                assert!(
+                    other.bin != p.bin || !other.rect.overlaps(&p.rect),
+                    "{p:?} overlaps {other:?}"
+                );
+            }
+        }
+        for &i in &packing.unplaced {
+            seen[i] += 1;
+            let item: Rectangle = items[i];
+            let fit = FitOptions { allow_rotation, allow_exact: true };
+            assert!(
+                item.area() == 0 || !item.fits_inside(&packing.bin_size, fit)
+            );
+        }
+        assert!(seen.iter().all(|&count| count == 1), "{seen:?}");
+    }
+
+    #[test]
+    fn packings_are_valid() {
+        let mut rng: StdRng = StdRng::seed_from_u64(1);
+        let bin = Rectangle { width: 40, height: 30 };
+        for _ in 0..CASES {
+            let items: Vec<Rectangle> = random_items(&mut rng, bin);
+            for heuristic in HEURISTICS {
+                for allow_rotation in [false, true] {
+                    let packing: Packing =
+                        pack(&items, bin, heuristic, allow_rotation);
+                    check(&items, &packing, allow_rotation);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn rotation_lets_tall_items_in() {
+        let bin = Rectangle { width: 20, height: 10 };
+        let items: [Rectangle; 1] = [Rectangle { width: 5, height: 15 }];
+        for heuristic in HEURISTICS {
+            let packing: Packing = pack(&items, bin, heuristic, false);
+            assert_eq!(packing.unplaced, [0]);
+            assert_eq!(packing.bins, 0);
+            let packing: Packing = pack(&items, bin, heuristic, true);
+            assert!(packing.unplaced.is_empty());
+            assert!(packing.placements[0].rotated);
+        }
+    }
+
+    #[test]
+    fn exact_fits_fill_the_bin() {
+        let bin = Rectangle { width: 10, height: 10 };
+        let quarter = Rectangle { width: 5, height: 5 };
+        for heuristic in HEURISTICS {
+            let packing: Packing = pack(&[quarter; 5], bin, heuristic, false);
+            assert_eq!(packing.bins, 2);
+            assert_eq!(packing.used_area(0), 100);
+            assert_eq!(packing.wasted_area(), 75);
+        }
+    }
+
+    #[test]
+    fn svg_has_a_box_per_bin_and_item() {
+        let bin = Rectangle { width: 10, height: 10 };
+        let items: [Rectangle; 3] = [Rectangle { width: 8, height: 8 }; 3];
+        let packing: Packing = pack(&items, bin, Heuristic::MaxRects, false);
+        let svg: String = packing.to_svg();
+        assert_eq!(svg.matches("<rect").count(), 6);
+        assert_eq!(svg.matches("<text").count(), 3);
+    }
+}
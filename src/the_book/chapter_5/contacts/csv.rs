@@ -0,0 +1,421 @@
+use super::{Address, Contact, ContactError, Phone};
+use crate::the_book::chapter_5::email::EmailAddress;
+use crate::the_book::chapter_5::person::User;
+
+
+/*
+* CSV in the RFC 4180 style: comma separated, fields with commas, quotes or
+* line breaks wrapped in double quotes, and "" for a quote inside them.
+*
+* The columns follow the layout spreadsheet exports of address books use,
+* with numbered groups for the repeated fields:
+*
+*   Name, Email, Email Type, Email Params, Active, Tags,
+*   Phone 1 - Type, Phone 1 - Value, ...,
+*   Address 1 - Type, Address 1 - PO Box, ... Address 1 - Country, ...,
+*   vCard Extra
+*
+* Lists inside one cell (types, tags) are separated by " ::: ". A colon or
+* backslash inside an item is written \: or \\ so it can't be mistaken for
+* the separator. Email Params and vCard Extra carry Contact::email_params
+* and Contact::extra, one per line, and a phone that was a tel: URI keeps
+* its "tel:" in the Value column, so a card that goes through CSV and back
+* keeps everything.
+*/
+const LIST_SEPARATOR: &str = " ::: ";
+
+const ADDRESS_PARTS: [&str; 7] = [
+    "PO Box",
+    "Extended",
+    "Street",
+    "City",
+    "Region",
+    "Postal Code",
+    "Country",
+];
+
+
+pub fn write(contacts: &[Contact]) -> String {
+    let phones: usize = contacts.iter().map(|c| c.phones.len()).max()
+        .unwrap_or(0);
+    let addresses: usize = contacts.iter().map(|c| c.addresses.len()).max()
+        .unwrap_or(0);
+
+    let mut header: Vec<String> = ["Name", "Email", "Email Type",
+        "Email Params", "Active", "Tags"].iter()
+        .map(|h| h.to_string())
+        .collect();
+    for n in 1..=phones {
+        header.push(format!("Phone {n} - Type"));
+        header.push(format!("Phone {n} - Value"));
+    }
+    for n in 1..=addresses {
+        header.push(format!("Address {n} - Type"));
+        for part in ADDRESS_PARTS {
+            header.push(format!("Address {n} - {part}"));
+        }
+    }
+    header.push(String::from("vCard Extra"));
+
+    let mut out: String = write_row(&header);
+    for contact in contacts {
+        let mut row: Vec<String> = vec![
+            contact.user.name.clone(),
+            contact.user.email.to_string(),
+            join_list(&contact.email_kinds),
+            contact.email_params.join("\n"),
+            contact.user.active.to_string(),
+            join_list(&contact.tags),
+        ];
+        for n in 0..phones {
+            match contact.phones.get(n) {
+                Some(phone) => {
+                    row.push(join_list(&phone.kinds));
+                    row.push(match phone.uri {
+                        true => format!("tel:{}", phone.number),
+                        false => phone.number.clone(),
+                    });
+                }
+                None => row.extend([String::new(), String::new()]),
+            }
+        }
+        for n in 0..addresses {
+            match contact.addresses.get(n) {
+                Some(address) => {
+                    row.push(join_list(&address.kinds));
+                    row.extend(address.parts().iter().map(|p| p.to_string()));
+                }
+                None => row.extend(vec![String::new(); 8]),
+            }
+        }
+        row.push(contact.extra.join("\n"));
+        out.push_str(&write_row(&row));
+    }
+    out
+}
+
+fn write_row(fields: &[String]) -> String {
+    let mut out: String = String::new();
+    for (i, field) in fields.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        let needs_quotes: bool = field.contains([',', '"', '\n', '\r'])
+            || field.starts_with(' ')
+            || field.ends_with(' ');
+        if needs_quotes {
+            out.push('"');
+            out.push_str(&field.replace('"', "\"\""));
+            out.push('"');
+        } else {
+            out.push_str(field);
+        }
+    }
+    out.push_str("\r\n");
+    out
+}
+
+
+pub fn parse(text: &str) -> Result<Vec<Contact>, ContactError> {
+    let rows: Vec<(usize, Vec<String>)> = read_rows(text)?;
+    let Some(((_, header), rows)) = rows.split_first() else {
+        return Ok(Vec::new());
+    };
+    let column = |name: &str| header.iter().position(|h| h.trim() == name);
+    let missing = |name: &str| ContactError::Syntax {
+        line: 1,
+        reason: format!("no {name} column"),
+    };
+    let name_col: usize = column("Name").ok_or_else(|| missing("Name"))?;
+    let email_col: usize = column("Email").ok_or_else(|| missing("Email"))?;
+    let email_type_col: Option<usize> = column("Email Type");
+    let email_params_col: Option<usize> = column("Email Params");
+    let active_col: Option<usize> = column("Active");
+    let tags_col: Option<usize> = column("Tags");
+    let extra_col: Option<usize> = column("vCard Extra");
+
+    let mut phone_cols: Vec<(Option<usize>, usize)> = Vec::new();
+    for n in 1.. {
+        let Some(value) = column(&format!("Phone {n} - Value")) else {
+            break;
+        };
+        phone_cols.push((column(&format!("Phone {n} - Type")), value));
+    }
+    let mut address_cols: Vec<(Option<usize>, [Option<usize>; 7])> =
+        Vec::new();
+    for n in 1.. {
+        let parts: [Option<usize>; 7] = ADDRESS_PARTS
+            .map(|part| column(&format!("Address {n} - {part}")));
+        if parts.iter().all(Option::is_none) {
+            break;
+        }
+        address_cols.push((column(&format!("Address {n} - Type")), parts));
+    }
+
+    let mut contacts: Vec<Contact> = Vec::new();
+    for (line, row) in rows {
+        let line: usize = *line;
+        let cell = |col: Option<usize>| -> &str {
+            col.and_then(|c| row.get(c)).map_or("", |s| s.as_str())
+        };
+        // The name is kept exactly as written, it only has to have
+        // something in it
+        let name: &str = cell(Some(name_col));
+        let email: &str = cell(Some(email_col)).trim();
+        if name.trim().is_empty() {
+            return Err(ContactError::Syntax {
+                line,
+                reason: String::from("row has no name"),
+            });
+        }
+        if email.is_empty() {
+            return Err(ContactError::MissingEmail {
+                line,
+                name: name.to_string(),
+            });
+        }
+        let email: EmailAddress = EmailAddress::parse(email)
+            .map_err(|error| ContactError::InvalidEmail { line, error })?;
+        let active: bool = match cell(active_col).trim() {
+            "" | "true" | "TRUE" => true,
+            "false" | "FALSE" => false,
+            other => {
+                return Err(ContactError::Syntax {
+                    line,
+                    reason: format!("Active should be true or false, \
+                        not {other:?}"),
+                });
+            }
+        };
+
+        let mut contact: Contact = Contact::new(User {
+            active,
+            name: name.to_string(),
+            email,
+        });
+        contact.email_kinds = split_list(cell(email_type_col));
+        contact.email_params = split_lines(cell(email_params_col));
+        contact.tags = split_list(cell(tags_col));
+        for (kinds, value) in &phone_cols {
+            let number: &str = cell(Some(*value));
+            if !number.is_empty() {
+                let uri: Option<&str> = number.strip_prefix("tel:");
+                contact.phones.push(Phone {
+                    kinds: split_list(cell(*kinds)),
+                    number: uri.unwrap_or(number).to_string(),
+                    uri: uri.is_some(),
+                });
+            }
+        }
+        for (kinds, parts) in &address_cols {
+            let parts: [String; 7] = parts.map(|c| cell(c).to_string());
+            if parts.iter().any(|p| !p.is_empty()) {
+                let kinds: Vec<String> = split_list(cell(*kinds));
+                contact.addresses.push(Address::from_parts(kinds, parts));
+            }
+        }
+        contact.extra = split_lines(cell(extra_col));
+        contacts.push(contact);
+    }
+    Ok(contacts)
+}
+
+fn join_list(items: &[String]) -> String {
+    let escaped: Vec<String> = items.iter()
+        .map(|item| item.replace('\\', "\\\\").replace(':', "\\:"))
+        .collect();
+    escaped.join(LIST_SEPARATOR)
+}
+
+// Splits on separators that aren't escaped. Colons that aren't part of a
+// separator don't need escaping, so hand-written "a:b" still works.
+fn split_list(cell: &str) -> Vec<String> {
+    let mut items: Vec<String> = Vec::new();
+    let mut item: String = String::new();
+    let mut rest: &str = cell;
+    while let Some(ch) = rest.chars().next() {
+        if let Some(after) = rest.strip_prefix(LIST_SEPARATOR) {
+            items.push(std::mem::take(&mut item));
+            rest = after;
+            continue;
+        }
+        rest = &rest[ch.len_utf8()..];
+        match ch {
+            '\\' => match rest.chars().next() {
+                Some(next) => {
+                    item.push(next);
+                    rest = &rest[next.len_utf8()..];
+                }
+                None => item.push('\\'),
+            },
+            _ => item.push(ch),
+        }
+    }
+    items.push(item);
+    items.into_iter()
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+fn split_lines(cell: &str) -> Vec<String> {
+    cell.lines()
+        .filter(|l| !l.is_empty())
+        .map(|l| l.to_string())
+        .collect()
+}
+
+
+// Splits CSV text into rows of fields, numbering each row by the line it
+// starts on. Quoted fields can span lines. Blank lines are skipped.
+fn read_rows(text: &str) -> Result<Vec<(usize, Vec<String>)>, ContactError> {
+    let mut rows: Vec<(usize, Vec<String>)> = Vec::new();
+    let mut row: Vec<String> = Vec::new();
+    let mut field: String = String::new();
+    let mut quoted: bool = false;
+    let mut line: usize = 1;
+    let mut row_start: usize = 1;
+    let mut chars = text.chars().peekable();
+
+    while let Some(ch) = chars.next() {
+        if quoted {
+            match ch {
+                '"' if chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.push('"');
+                }
+                '"' => {
+                    quoted = false;
+                    if !matches!(chars.peek(), None | Some(',' | '\r' | '\n'))
+                    {
+                        return Err(ContactError::Syntax {
+                            line,
+                            reason: String::from("text after a closing quote"),
+                        });
+                    }
+                }
+                '\n' => {
+                    line += 1;
+                    field.push(ch);
+                }
+                _ => field.push(ch),
+            }
+            continue;
+        }
+        match ch {
+            '"' if field.is_empty() => quoted = true,
+            ',' => row.push(std::mem::take(&mut field)),
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' => {
+                row.push(std::mem::take(&mut field));
+                if !(row.len() == 1 && row[0].is_empty()) {
+                    rows.push((row_start, std::mem::take(&mut row)));
+                }
+                row.clear();
+                line += 1;
+                row_start = line;
+            }
+            _ => field.push(ch),
+        }
+    }
+
+    if quoted {
+        return Err(ContactError::Syntax {
+            line: row_start,
+            reason: String::from("quoted field is never closed"),
+        });
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push((row_start, row));
+    }
+    Ok(rows)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn contact(name: &str, email: &str) -> Contact {
+        Contact::new(User {
+            active: true,
+            name: name.to_string(),
+            email: email.parse().unwrap(),
+        })
+    }
+
+    #[test]
+    fn awkward_fields_round_trip() {
+        let name: &str = " Ann \"Nan\" Lee, Jr.\r\n";
+        let mut ann: Contact = contact(name, "ann@x.com");
+        ann.tags = vec![
+            String::from("a ::: b"),
+            String::from("c:d"),
+            String::from("back\\slash"),
+        ];
+        ann.email_kinds = vec![String::from("work"), String::from("pref=3")];
+        ann.email_params = vec![String::from("X-LABEL=\"Work, main\"")];
+        ann.phones.push(Phone {
+            kinds: vec![String::from("cell")],
+            number: String::from("+1 555 010 0100"),
+            uri: false,
+        });
+        ann.phones.push(Phone {
+            kinds: Vec::new(),
+            number: String::from("+1-555-0101;ext=12"),
+            uri: true,
+        });
+        ann.addresses.push(Address {
+            street: String::from("1 Main St\r\nRear"),
+            ..Address::default()
+        });
+        ann.extra = vec![String::from("BDAY:19850412")];
+        let mut bob: Contact = contact("Bob", "bob@x.com");
+        bob.user.active = false;
+
+        let contacts: Vec<Contact> = vec![ann, bob];
+        let text: String = write(&contacts);
+        assert_eq!(parse(&text).unwrap(), contacts);
+    }
+
+    #[test]
+    fn quoted_fields_span_lines() {
+        let text: &str = "Name,Email,Tags\r\n\
+            \"Ann\r\nLee\",ann@x.com,\"one, two\"\r\n\
+            \r\n\
+            \"Bob \"\"B\"\"\",bob@x.com,a ::: b:c\r\n\
+            Cy,not an email,\r\n";
+        let err: ContactError = parse(text).unwrap_err();
+        assert!(matches!(err, ContactError::InvalidEmail { line: 6, .. }));
+
+        let text: &str = text.rsplit_once("Cy").unwrap().0;
+        let contacts: Vec<Contact> = parse(text).unwrap();
+        assert_eq!(contacts[0].user.name, "Ann\r\nLee");
+        assert_eq!(contacts[0].tags, ["one, two"]);
+        assert_eq!(contacts[1].user.name, "Bob \"B\"");
+        assert_eq!(contacts[1].tags, ["a", "b:c"]);
+    }
+
+    #[test]
+    fn bad_rows() {
+        let unclosed: &str = "Name,Email\r\n\"Ann,ann@x.com\r\n";
+        assert!(matches!(
+            parse(unclosed),
+            Err(ContactError::Syntax { line: 2, .. })
+        ));
+        let trailing: &str = "Name,Email\r\n\"Ann\"x,ann@x.com\r\n";
+        assert!(matches!(
+            parse(trailing),
+            Err(ContactError::Syntax { line: 2, .. })
+        ));
+        assert!(matches!(
+            parse("Name,Email\r\n  ,ann@x.com\r\n"),
+            Err(ContactError::Syntax { line: 2, .. })
+        ));
+        assert!(matches!(
+            parse("Name\r\nAnn\r\n"),
+            Err(ContactError::Syntax { line: 1, .. })
+        ));
+    }
+}
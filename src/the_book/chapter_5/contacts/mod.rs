@@ -0,0 +1,445 @@
+use std::fmt;
+
+use super::email::{EmailAddress, EmailError};
+use super::person::User;
+use crate::layout::{layout_of, Registry};
+
+pub mod csv;
+pub mod vcard;
+
+pub use vcard::Version;
+
+
+/*
+* A contact is a User plus the things an address book keeps about a person:
+* phone numbers, postal addresses and tags. The email on the User is the
+* contact's identity, so two records with the same email are the same
+* person and get merged.
+*
+* `kinds` are the vCard TYPE values ("cell", "work", "home"...), always
+* lowercase here, plus "pref" for the preferred one. A 4.0 PREF rank other
+* than 1 is kept as "pref=N". `email_params` holds any other parameters on
+* the EMAIL line exactly as written. `extra` holds vCard lines this module
+* doesn't understand, unfolded but otherwise untouched, so exporting a card
+* we imported doesn't quietly drop someone's birthday or photo.
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct Contact {
+    pub user: User,
+    pub email_kinds: Vec<String>,
+    pub email_params: Vec<String>,
+    pub phones: Vec<Phone>,
+    pub addresses: Vec<Address>,
+    pub tags: Vec<String>,
+    pub extra: Vec<String>,
+}
+
+
+// `uri` is set for a number that came as a tel: URI (without the "tel:"),
+// so it can be written back as one
+#[derive(Debug, Clone, PartialEq)]
+pub struct Phone {
+    pub kinds: Vec<String>,
+    pub number: String,
+    pub uri: bool,
+}
+
+
+// The seven parts of a vCard ADR, in vCard order
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Address {
+    pub kinds: Vec<String>,
+    pub po_box: String,
+    pub extended: String,
+    pub street: String,
+    pub locality: String,
+    pub region: String,
+    pub postal_code: String,
+    pub country: String,
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ContactError {
+    Syntax { line: usize, reason: String },
+    UnsupportedVersion { line: usize, version: String },
+    MissingEmail { line: usize, name: String },
+    InvalidEmail { line: usize, error: EmailError },
+}
+
+impl fmt::Display for ContactError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ContactError::Syntax { line, reason } => {
+                write!(f, "line {line}: {reason}")
+            }
+            ContactError::UnsupportedVersion { line, version } => {
+                write!(f, "line {line}: vCard version {version} isn't \
+                    supported, only 3.0 and 4.0")
+            }
+            ContactError::MissingEmail { line, name } => {
+                write!(f, "line {line}: contact {name:?} has no email")
+            }
+            ContactError::InvalidEmail { line, error } => {
+                write!(f, "line {line}: {error}")
+            }
+        }
+    }
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Added {
+    New,
+    Merged,
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ImportSummary {
+    pub added: usize,
+    pub merged: usize,
+}
+
+
+impl Contact {
+
+    pub fn new(user: User) -> Contact {
+        Contact {
+            user,
+            email_kinds: Vec::new(),
+            email_params: Vec::new(),
+            phones: Vec::new(),
+            addresses: Vec::new(),
+            tags: Vec::new(),
+            extra: Vec::new(),
+        }
+    }
+
+    pub fn email(&self) -> &EmailAddress {
+        &self.user.email
+    }
+
+    /*
+    * Folds another record for the same person into this one. The name and
+    * active flag already here win. Everything else is a union: phones are
+    * the same if their digits match, addresses if every part matches, tags
+    * ignoring case, and in each case the kinds are combined.
+    */
+    pub fn merge(&mut self, other: Contact) {
+        union_kinds(&mut self.email_kinds, other.email_kinds);
+        union_kinds(&mut self.email_params, other.email_params);
+        for phone in other.phones {
+            let digits: String = phone_digits(&phone.number);
+            match self.phones.iter_mut()
+                .find(|p| phone_digits(&p.number) == digits)
+            {
+                Some(existing) => union_kinds(&mut existing.kinds, phone.kinds),
+                None => self.phones.push(phone),
+            }
+        }
+        for address in other.addresses {
+            match self.addresses.iter_mut()
+                .find(|a| a.same_place(&address))
+            {
+                Some(existing) => {
+                    union_kinds(&mut existing.kinds, address.kinds)
+                }
+                None => self.addresses.push(address),
+            }
+        }
+        for tag in other.tags {
+            if !self.tags.iter().any(|t| t.eq_ignore_ascii_case(&tag)) {
+                self.tags.push(tag);
+            }
+        }
+        for line in other.extra {
+            if !self.extra.contains(&line) {
+                self.extra.push(line);
+            }
+        }
+    }
+}
+
+
+impl Address {
+
+    pub fn parts(&self) -> [&str; 7] {
+        [
+            &self.po_box,
+            &self.extended,
+            &self.street,
+            &self.locality,
+            &self.region,
+            &self.postal_code,
+            &self.country,
+        ]
+    }
+
+    pub fn from_parts(kinds: Vec<String>, parts: [String; 7]) -> Address {
+        let [po_box, extended, street, locality, region, postal_code, country] =
+            parts;
+        Address {
+            kinds,
+            po_box,
+            extended,
+            street,
+            locality,
+            region,
+            postal_code,
+            country,
+        }
+    }
+
+    fn same_place(&self, other: &Address) -> bool {
+        self.parts().iter()
+            .zip(other.parts())
+            .all(|(a, b)| a.trim().eq_ignore_ascii_case(b.trim()))
+    }
+}
+
+
+// "+1 (555) 010-0100" and "+15550100100" are the same phone
+fn phone_digits(number: &str) -> String {
+    number.chars()
+        .filter(|c| c.is_ascii_digit() || *c == '+')
+        .collect()
+}
+
+fn union_kinds(kinds: &mut Vec<String>, more: Vec<String>) {
+    for kind in more {
+        if !kinds.contains(&kind) {
+            kinds.push(kind);
+        }
+    }
+}
+
+
+/*
+* Contacts in the order they were first added. Adding a contact whose email
+* is already in the book merges the two rather than keeping a duplicate.
+*/
+#[derive(Debug, Default)]
+pub struct ContactBook {
+    contacts: Vec<Contact>,
+}
+
+impl ContactBook {
+
+    pub fn new() -> ContactBook {
+        ContactBook { contacts: Vec::new() }
+    }
+
+    pub fn add(&mut self, contact: Contact) -> Added {
+        match self.contacts.iter_mut()
+            .find(|c| c.email() == contact.email())
+        {
+            Some(existing) => {
+                existing.merge(contact);
+                Added::Merged
+            }
+            None => {
+                self.contacts.push(contact);
+                Added::New
+            }
+        }
+    }
+
+    pub fn get(&self, email: &EmailAddress) -> Option<&Contact> {
+        self.contacts.iter().find(|c| c.email() == email)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Contact> {
+        self.contacts.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.contacts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.contacts.is_empty()
+    }
+
+    // Imports are all or nothing: a syntax error anywhere in the text
+    // leaves the book as it was.
+    pub fn import_vcard(
+        &mut self,
+        text: &str,
+    ) -> Result<ImportSummary, ContactError> {
+        let contacts: Vec<Contact> = vcard::parse(text)?;
+        Ok(self.add_all(contacts))
+    }
+
+    pub fn export_vcard(&self, version: Version) -> String {
+        self.contacts.iter()
+            .map(|c| vcard::write(c, version))
+            .collect()
+    }
+
+    pub fn import_csv(
+        &mut self,
+        text: &str,
+    ) -> Result<ImportSummary, ContactError> {
+        let contacts: Vec<Contact> = csv::parse(text)?;
+        Ok(self.add_all(contacts))
+    }
+
+    pub fn export_csv(&self) -> String {
+        csv::write(&self.contacts)
+    }
+
+    fn add_all(&mut self, contacts: Vec<Contact>) -> ImportSummary {
+        let mut summary: ImportSummary = ImportSummary::default();
+        for contact in contacts {
+            match self.add(contact) {
+                Added::New => summary.added += 1,
+                Added::Merged => summary.merged += 1,
+            }
+        }
+        summary
+    }
+}
+
+
+pub fn run() {
+    println!("\nContact book:");
+    // Two cards for the same person (the email domain differs only in
+    // case), one folded line, and a property we don't model (BDAY).
+    let cards: &str = "BEGIN:VCARD\r\n\
+        VERSION:3.0\r\n\
+        N:Pad;D-;;;\r\n\
+        FN:D-Pad\r\n\
+        EMAIL;TYPE=INTERNET,WORK:dpad@dpadllc.com\r\n\
+        TEL;TYPE=CELL:+1 555 010 0100\r\n\
+        ADR;TYPE=WORK:;Suite 5;1 Main St\\, Rear;Springfield;IL;62701;USA\r\n\
+        CATEGORIES:rust,examples\r\n\
+        NOTE:Wrote most of the chapter 5 examples and keeps asking for mor\r\n \
+        e of them.\r\n\
+        END:VCARD\r\n\
+        BEGIN:VCARD\r\n\
+        VERSION:4.0\r\n\
+        FN:D-Pad\r\n\
+        EMAIL:dpad@DPADLLC.COM\r\n\
+        TEL;VALUE=uri;TYPE=cell,voice;PREF=1:tel:+1-555-010-0100\r\n\
+        BDAY:19850412\r\n\
+        CATEGORIES:Examples,structs\r\n\
+        END:VCARD\r\n\
+        BEGIN:VCARD\r\n\
+        VERSION:4.0\r\n\
+        FN:Alecia\r\n\
+        EMAIL;TYPE=home:alecia@dpadllc.com\r\n\
+        END:VCARD\r\n";
+
+    let mut book: ContactBook = ContactBook::new();
+    match book.import_vcard(cards) {
+        Ok(summary) => println!("  Imported: {summary:?}"),
+        Err(err) => println!("  Import failed: {err}"),
+    }
+    let dpad: EmailAddress = "dpad@dpadllc.com".parse().unwrap();
+    if let Some(contact) = book.get(&dpad) {
+        println!("  Merged {}: {} phone(s), tags {:?}",
+            contact.user.name, contact.phones.len(), contact.tags);
+    }
+
+    let v4: String = book.export_vcard(Version::V4);
+    println!("  As vCard 4.0:");
+    for line in v4.lines().take(12) {
+        println!("    {line}");
+    }
+
+    let csv_text: String = book.export_csv();
+    let mut from_csv: ContactBook = ContactBook::new();
+    from_csv.import_csv(&csv_text).unwrap();
+    let mut from_v3: ContactBook = ContactBook::new();
+    from_v3.import_vcard(&book.export_vcard(Version::V3)).unwrap();
+    println!("  CSV round trip matches: {}",
+        from_csv.iter().eq(book.iter()));
+    println!("  vCard 3.0 round trip matches: {}",
+        from_v3.export_vcard(Version::V3) == book.export_vcard(Version::V3));
+
+    let broken: &str = "BEGIN:VCARD\nVERSION:4.0\nFN:Nobody\nEND:VCARD\n";
+    if let Err(err) = ContactBook::new().import_vcard(broken) {
+        println!("  Import failed: {err}");
+    }
+}
+
+
+pub fn register_layouts(registry: &mut Registry) {
+    registry.register("Chapter 5", layout_of!(Contact {
+        user, email_kinds, email_params, phones, addresses, tags, extra
+    }));
+    registry.register("Chapter 5", layout_of!(Phone { kinds, number, uri }));
+    registry.register("Chapter 5", layout_of!(Address {
+        kinds, po_box, extended, street, locality, region, postal_code,
+        country
+    }));
+    registry.register("Chapter 5", layout_of!(ContactBook { contacts }));
+    registry.register("Chapter 5", layout_of!(ContactError));
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CARDS: &str = "BEGIN:VCARD\r\n\
+        VERSION:3.0\r\n\
+        N:Pad;D-;;;\r\n\
+        FN:D-Pad\r\n\
+        EMAIL;TYPE=INTERNET,WORK;X-LABEL=\"a, b\":dpad@dpadllc.com\r\n\
+        TEL;TYPE=CELL,PREF:+1 555 010 0100\r\n\
+        ADR;TYPE=WORK:;Suite 5;1 Main St\\, Rear;Springfield;IL;62701;USA\r\n\
+        CATEGORIES:rust,a ::: b\r\n\
+        NOTE:Line one\\nline two\r\n\
+        END:VCARD\r\n\
+        BEGIN:VCARD\r\n\
+        VERSION:4.0\r\n\
+        FN:Alecia\r\n\
+        EMAIL;TYPE=home;PREF=2:alecia@dpadllc.com\r\n\
+        KIND:individual\r\n\
+        END:VCARD\r\n";
+
+    #[test]
+    fn vcard_to_csv_and_back() {
+        let mut book: ContactBook = ContactBook::new();
+        book.import_vcard(CARDS).unwrap();
+        let mut from_csv: ContactBook = ContactBook::new();
+        let summary: ImportSummary =
+            from_csv.import_csv(&book.export_csv()).unwrap();
+        assert_eq!(summary, ImportSummary { added: 2, merged: 0 });
+        assert!(from_csv.iter().eq(book.iter()));
+        for version in [Version::V3, Version::V4] {
+            assert_eq!(
+                from_csv.export_vcard(version),
+                book.export_vcard(version)
+            );
+        }
+    }
+
+    #[test]
+    fn same_email_merges() {
+        let mut book: ContactBook = ContactBook::new();
+        book.import_vcard(CARDS).unwrap();
+        let again: &str = "BEGIN:VCARD\r\nVERSION:4.0\r\nFN:Someone\r\n\
+            EMAIL;TYPE=work:dpad@DPADLLC.COM\r\n\
+            TEL:+15550100100\r\nTEL;TYPE=home:+1 555 010 0200\r\n\
+            CATEGORIES:Rust,new\r\nEND:VCARD\r\n";
+        let summary: ImportSummary = book.import_vcard(again).unwrap();
+        assert_eq!(summary, ImportSummary { added: 0, merged: 1 });
+        let dpad: &Contact = book.get(&"dpad@dpadllc.com".parse().unwrap())
+            .unwrap();
+        assert_eq!(dpad.user.name, "D-Pad");
+        assert_eq!(dpad.phones.len(), 2);
+        assert_eq!(dpad.tags, ["rust", "a ::: b", "new"]);
+    }
+
+    #[test]
+    fn failed_imports_change_nothing() {
+        let mut book: ContactBook = ContactBook::new();
+        book.import_vcard(CARDS).unwrap();
+        let broken: String = format!("{CARDS}BEGIN:VCARD\r\nVERSION:4.0\r\n");
+        assert!(book.import_vcard(&broken).is_err());
+        assert_eq!(book.len(), 2);
+    }
+}
@@ -0,0 +1,896 @@
+use super::{Address, Contact, ContactError, Phone};
+use crate::the_book::chapter_5::email::EmailAddress;
+use crate::the_book::chapter_5::person::User;
+
+
+/*
+* vCard 3.0 (RFC 2426) and 4.0 (RFC 6350). A card is a block of content
+* lines:
+*
+*   BEGIN:VCARD
+*   VERSION:4.0
+*   FN:D-Pad
+*   TEL;TYPE=cell;PREF=1:tel:+1-555-010-0100
+*   END:VCARD
+*
+* Each line is NAME;PARAM=value;...:value. Lines longer than 75 bytes are
+* folded by breaking them and starting the rest with a space, and in text
+* values a backslash escapes commas, semicolons, backslashes and newlines.
+*
+* FN, EMAIL, TEL, ADR and CATEGORIES become fields on the Contact. Anything
+* else, and any TEL or ADR with parameters we don't model, is kept as-is in
+* Contact::extra. Those lines are written back unchanged unless the other
+* version needs them spelled differently, see convert_extra.
+*/
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Version {
+    V3,
+    V4,
+}
+
+impl Version {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Version::V3 => "3.0",
+            Version::V4 => "4.0",
+        }
+    }
+}
+
+
+const FOLD_AT: usize = 75;
+const ACTIVE: &str = "X-NOTES-ACTIVE";
+
+
+struct Property<'a> {
+    group: Option<&'a str>,
+    name: String,
+    params: Vec<Param<'a>>,
+    value: &'a str,
+}
+
+// One parameter, with its values unquoted and the text it was read from
+struct Param<'a> {
+    name: String,
+    values: Vec<String>,
+    raw: &'a str,
+}
+
+impl<'a> Property<'a> {
+
+    // TYPE values, lowercased, plus "pref" or "pref=N" for a 4.0 PREF
+    // parameter, and every other parameter as it was.
+    fn kinds_and_rest(&self) -> (Vec<String>, Vec<&Param<'a>>) {
+        let mut kinds: Vec<String> = Vec::new();
+        let mut rest: Vec<&Param> = Vec::new();
+        for param in &self.params {
+            match (param.name.as_str(), param.values.as_slice()) {
+                // 4.0 allows TYPE="work,voice", so split inside quotes too
+                ("TYPE", values) => kinds.extend(values.iter()
+                    .flat_map(|v| v.split(','))
+                    .filter(|v| !v.is_empty())
+                    .map(|v| v.to_ascii_lowercase())),
+                ("PREF", [rank]) if rank.trim() == "1" => {
+                    kinds.push(String::from("pref"));
+                }
+                ("PREF", [rank]) => kinds.push(format!("pref={}", rank.trim())),
+                _ => rest.push(param),
+            }
+        }
+        (kinds, rest)
+    }
+
+    // None if the line has a parameter we'd lose by storing only the kinds
+    fn kinds(&self) -> Option<Vec<String>> {
+        let (kinds, rest) = self.kinds_and_rest();
+        rest.iter().all(|p| p.name == "VALUE").then_some(kinds)
+    }
+
+    /*
+    * For a TEL: the number inside a tel: URI, Ok(None) for a plain text
+    * number, and Err for a URI of some other kind. VALUE=uri says it's a
+    * URI, and so does a tel: prefix on a line with no VALUE. URIs have no
+    * backslash escapes, so the number is returned exactly as written.
+    */
+    fn tel_uri(&self) -> Result<Option<&'a str>, ()> {
+        let value_type: Option<&str> = self.params.iter()
+            .find(|p| p.name == "VALUE")
+            .and_then(|p| p.values.first())
+            .map(|v| v.as_str());
+        let number: Option<&'a str> = self.value.strip_prefix("tel:");
+        match value_type {
+            Some(v) if v.eq_ignore_ascii_case("uri") => {
+                number.map(Some).ok_or(())
+            }
+            Some(_) => Ok(None),
+            None => Ok(number),
+        }
+    }
+}
+
+
+// A card being read, with the line numbers errors should point at
+struct Partial {
+    start: usize,
+    version: Option<&'static str>,
+    name: Option<String>,
+    fallback_name: Option<String>,
+    email: Option<(usize, String)>,
+    email_kinds: Vec<String>,
+    email_params: Vec<String>,
+    active: bool,
+    phones: Vec<Phone>,
+    addresses: Vec<Address>,
+    tags: Vec<String>,
+    extra: Vec<String>,
+}
+
+
+pub fn parse(text: &str) -> Result<Vec<Contact>, ContactError> {
+    let mut contacts: Vec<Contact> = Vec::new();
+    let mut card: Option<Partial> = None;
+
+    for (line, content) in unfold(text) {
+        let syntax = |reason: &str| ContactError::Syntax {
+            line,
+            reason: reason.to_string(),
+        };
+        let prop: Property = parse_property(&content)
+            .ok_or_else(|| syntax("expected NAME:value"))?;
+        let is_vcard: bool = prop.value.eq_ignore_ascii_case("VCARD");
+
+        if prop.name == "BEGIN" && is_vcard {
+            if card.is_some() {
+                return Err(syntax("BEGIN:VCARD inside another card"));
+            }
+            card = Some(Partial {
+                start: line,
+                version: None,
+                name: None,
+                fallback_name: None,
+                email: None,
+                email_kinds: Vec::new(),
+                email_params: Vec::new(),
+                active: true,
+                phones: Vec::new(),
+                addresses: Vec::new(),
+                tags: Vec::new(),
+                extra: Vec::new(),
+            });
+            continue;
+        }
+        let Some(partial) = card.as_mut() else {
+            return Err(syntax("property outside BEGIN:VCARD ... END:VCARD"));
+        };
+        if prop.name == "END" && is_vcard {
+            contacts.push(finish(card.take().unwrap())?);
+            continue;
+        }
+        if prop.group.is_some() {
+            partial.extra.push(content.clone());
+            continue;
+        }
+
+        match prop.name.as_str() {
+            "VERSION" => {
+                partial.version = match prop.value.trim() {
+                    "3.0" => Some("3.0"),
+                    "4.0" => Some("4.0"),
+                    other => {
+                        return Err(ContactError::UnsupportedVersion {
+                            line,
+                            version: other.to_string(),
+                        });
+                    }
+                };
+            }
+            // Only the first FN is the name. Any others (a 4.0 card can
+            // give the name in several languages) are kept as they were.
+            "FN" if partial.name.is_none() => {
+                partial.name = Some(unescape(prop.value));
+            }
+            "N" => {
+                let parts: Vec<String> = split_escaped(prop.value, ';');
+                let given: &str = parts.get(1).map_or("", |s| s.as_str());
+                let family: &str = parts.first().map_or("", |s| s.as_str());
+                let name: String = format!("{given} {family}");
+                partial.fallback_name = Some(name.trim().to_string());
+                partial.extra.push(content.clone());
+            }
+            "EMAIL" if partial.email.is_none() => {
+                let (kinds, rest) = prop.kinds_and_rest();
+                partial.email_kinds = kinds;
+                partial.email_params = rest.iter()
+                    .map(|p| p.raw.to_string())
+                    .collect();
+                partial.email = Some((line, unescape(prop.value)));
+            }
+            "TEL" => match (prop.kinds(), prop.tel_uri()) {
+                (Some(kinds), Ok(Some(number))) => {
+                    partial.phones.push(Phone {
+                        kinds,
+                        number: number.to_string(),
+                        uri: true,
+                    });
+                }
+                (Some(kinds), Ok(None)) => partial.phones.push(Phone {
+                    kinds,
+                    number: unescape(prop.value),
+                    uri: false,
+                }),
+                _ => partial.extra.push(content.clone()),
+            },
+            "ADR" => match prop.kinds() {
+                Some(kinds) => {
+                    let mut parts: Vec<String> = split_escaped(prop.value, ';');
+                    if parts.len() > 7 {
+                        return Err(syntax("ADR has more than 7 parts"));
+                    }
+                    parts.resize(7, String::new());
+                    let parts: [String; 7] = parts.try_into().unwrap();
+                    partial.addresses.push(Address::from_parts(kinds, parts));
+                }
+                None => partial.extra.push(content.clone()),
+            },
+            "CATEGORIES" => {
+                for tag in split_escaped(prop.value, ',') {
+                    let tag: &str = tag.trim();
+                    let seen: bool = partial.tags.iter()
+                        .any(|t| t.eq_ignore_ascii_case(tag));
+                    if !tag.is_empty() && !seen {
+                        partial.tags.push(tag.to_string());
+                    }
+                }
+            }
+            ACTIVE => {
+                partial.active = !prop.value.eq_ignore_ascii_case("FALSE");
+            }
+            _ => partial.extra.push(content.clone()),
+        }
+    }
+
+    match card {
+        Some(partial) => Err(ContactError::Syntax {
+            line: partial.start,
+            reason: String::from("BEGIN:VCARD without END:VCARD"),
+        }),
+        None => Ok(contacts),
+    }
+}
+
+
+fn finish(partial: Partial) -> Result<Contact, ContactError> {
+    let line: usize = partial.start;
+    if partial.version.is_none() {
+        return Err(ContactError::Syntax {
+            line,
+            reason: String::from("card has no VERSION"),
+        });
+    }
+    let name: String = partial.name
+        .or(partial.fallback_name)
+        .filter(|n| !n.trim().is_empty())
+        .ok_or_else(|| ContactError::Syntax {
+            line,
+            reason: String::from("card has no FN or N"),
+        })?;
+    let Some((email_line, email)) = partial.email else {
+        return Err(ContactError::MissingEmail { line, name });
+    };
+    let email: EmailAddress = EmailAddress::parse(email.trim())
+        .map_err(|error| ContactError::InvalidEmail {
+            line: email_line,
+            error,
+        })?;
+
+    Ok(Contact {
+        user: User {
+            active: partial.active,
+            name,
+            email,
+        },
+        email_kinds: partial.email_kinds,
+        email_params: partial.email_params,
+        phones: partial.phones,
+        addresses: partial.addresses,
+        tags: partial.tags,
+        extra: partial.extra,
+    })
+}
+
+
+pub fn write(contact: &Contact, version: Version) -> String {
+    let mut lines: Vec<String> = vec![
+        String::from("BEGIN:VCARD"),
+        format!("VERSION:{}", version.as_str()),
+        format!("FN:{}", escape(&contact.user.name)),
+    ];
+
+    // 3.0 requires N. If the card came with one it's in extra, and goes
+    // next to FN either way. Otherwise guess that the last word of the name
+    // is the family name.
+    let is_n = |line: &&String| {
+        let upper: String = line.to_ascii_uppercase();
+        upper.starts_with("N:") || upper.starts_with("N;")
+    };
+    let n_lines: Vec<&String> = contact.extra.iter().filter(is_n).collect();
+    lines.extend(n_lines.iter().map(|line| convert_extra(line, version)));
+    if version == Version::V3 && n_lines.is_empty() {
+        let name: &str = contact.user.name.trim();
+        let (given, family) = name.rsplit_once(' ').unwrap_or(("", name));
+        lines.push(format!("N:{};{};;;", escape(family), escape(given)));
+    }
+
+    let email_params: String = contact.email_params.iter()
+        .map(|p| format!(";{p}"))
+        .collect();
+    lines.push(format!(
+        "EMAIL{}{email_params}:{}",
+        kind_params(&contact.email_kinds, version),
+        escape(&contact.user.email.to_string())
+    ));
+    for phone in &contact.phones {
+        let params: String = kind_params(&phone.kinds, version);
+        // Written back the way it was read, as a tel: URI or as text
+        let line: String = match (version, phone.uri) {
+            (_, true) => format!("TEL;VALUE=uri{params}:tel:{}", phone.number),
+            (Version::V3, false) => {
+                format!("TEL{params}:{}", escape(&phone.number))
+            }
+            (Version::V4, false) => {
+                format!("TEL;VALUE=text{params}:{}", escape(&phone.number))
+            }
+        };
+        lines.push(line);
+    }
+    for address in &contact.addresses {
+        let parts: Vec<String> = address.parts().iter()
+            .map(|p| escape(p))
+            .collect();
+        lines.push(format!(
+            "ADR{}:{}",
+            kind_params(&address.kinds, version),
+            parts.join(";")
+        ));
+    }
+    if !contact.tags.is_empty() {
+        let tags: Vec<String> = contact.tags.iter().map(|t| escape(t)).collect();
+        lines.push(format!("CATEGORIES:{}", tags.join(",")));
+    }
+    if !contact.user.active {
+        lines.push(format!("{ACTIVE}:FALSE"));
+    }
+    lines.extend(contact.extra.iter()
+        .filter(|line| !is_n(line))
+        .map(|line| convert_extra(line, version)));
+    lines.push(String::from("END:VCARD"));
+
+    let mut out: String = String::new();
+    for line in &lines {
+        out.push_str(&fold(line));
+    }
+    out
+}
+
+
+// 3.0 writes TYPE=CELL,PREF. 4.0 uses lowercase types and a separate PREF.
+// 3.0 has no way to rank, so "pref=N" is just PREF there.
+fn kind_params(kinds: &[String], version: Version) -> String {
+    let mut out: String = String::new();
+    let mut types: Vec<String> = Vec::new();
+    let mut pref: Option<&str> = None;
+    for kind in kinds {
+        let rank: Option<&str> = match kind.as_str() {
+            "pref" => Some("1"),
+            other => other.strip_prefix("pref="),
+        };
+        let kind: String = match (rank, version) {
+            (Some(rank), Version::V4) => {
+                pref = pref.or(Some(rank));
+                continue;
+            }
+            (Some(_), Version::V3) => String::from("PREF"),
+            (None, Version::V3) => kind.to_ascii_uppercase(),
+            (None, Version::V4) => kind.clone(),
+        };
+        if !types.contains(&kind) {
+            types.push(kind);
+        }
+    }
+    if !types.is_empty() {
+        out.push_str(";TYPE=");
+        out.push_str(&types.join(","));
+    }
+    if let Some(rank) = pref {
+        out.push_str(";PREF=");
+        out.push_str(rank);
+    }
+    out
+}
+
+
+// Properties only one of the two versions defines. The other version gets
+// them with an X- in front, and loses the X- again on the way back.
+const ONLY_V3: [&str; 7] =
+    ["AGENT", "CLASS", "LABEL", "MAILER", "NAME", "PROFILE", "SORT-STRING"];
+const ONLY_V4: [&str; 8] = [
+    "ANNIVERSARY", "CLIENTPIDMAP", "GENDER", "KIND", "LANG", "MEMBER",
+    "RELATED", "XML",
+];
+
+/*
+* An extra line written for `version`. Most lines mean the same in both
+* versions and come back exactly as they went in. The ones that don't:
+*
+*   3.0 TYPE=PREF           4.0 PREF=1
+*   3.0 ENCODING=b;TYPE=JPEG:<base64>
+*                           4.0 data:image/jpeg;base64,<base64>
+*   KIND, GENDER...         X-KIND, X-GENDER... in 3.0, and back again
+*/
+fn convert_extra(line: &str, version: Version) -> String {
+    let Some(prop) = parse_property(line) else {
+        return line.to_string();
+    };
+    let (missing, native): (&[&str], &[&str]) = match version {
+        Version::V3 => (&ONLY_V4, &ONLY_V3),
+        Version::V4 => (&ONLY_V3, &ONLY_V4),
+    };
+    let mut changed: bool = false;
+    let mut name: String = prop.name.clone();
+    if missing.contains(&name.as_str()) {
+        name = format!("X-{name}");
+        changed = true;
+    } else if let Some(base) = name.strip_prefix("X-")
+        .filter(|base| native.contains(base))
+    {
+        name = base.to_string();
+        changed = true;
+    }
+
+    let mut types: Vec<String> = Vec::new();
+    let mut pref: Option<String> = None;
+    let mut params: Vec<&str> = Vec::new();
+    for param in &prop.params {
+        match param.name.as_str() {
+            "TYPE" => types.extend(param.values.iter()
+                .flat_map(|v| v.split(','))
+                .map(|v| v.to_string())),
+            "PREF" => pref = param.values.first().cloned(),
+            _ => params.push(param.raw),
+        }
+    }
+    let is_pref = |t: &String| t.eq_ignore_ascii_case("PREF");
+    match version {
+        Version::V3 if pref.is_some() => {
+            pref = None;
+            types.push(String::from("PREF"));
+            changed = true;
+        }
+        Version::V4 if types.iter().any(is_pref) => {
+            types.retain(|t| !is_pref(t));
+            pref = Some(String::from("1"));
+            changed = true;
+        }
+        _ => {}
+    }
+
+    let mut value: String = prop.value.to_string();
+    let media: Option<&str> = match name.as_str() {
+        "PHOTO" | "LOGO" => Some("image"),
+        "SOUND" => Some("audio"),
+        "KEY" => Some("application"),
+        _ => None,
+    };
+    let is_encoding = |p: &&str| {
+        let upper: String = p.to_ascii_uppercase();
+        upper == "ENCODING=B" || upper == "ENCODING=BASE64"
+    };
+    match (media, version) {
+        (Some(media), Version::V4) if params.iter().any(is_encoding) => {
+            params.retain(|p| !is_encoding(p));
+            let subtype: String = types.first()
+                .map_or(String::from("octet-stream"), |t| t.to_lowercase());
+            let media: &str = if types.is_empty() { "application" } else {
+                media
+            };
+            value = format!("data:{media}/{subtype};base64,{value}");
+            types.clear();
+            changed = true;
+        }
+        (Some(_), Version::V3) => {
+            let data: Option<(&str, &str)> = prop.value
+                .strip_prefix("data:")
+                .and_then(|rest| rest.split_once(";base64,"));
+            if let Some((mediatype, base64)) = data {
+                let subtype: &str = mediatype.rsplit('/').next().unwrap();
+                params.retain(|p| {
+                    !p.to_ascii_uppercase().starts_with("VALUE=")
+                });
+                params.push("ENCODING=b");
+                types = vec![subtype.to_ascii_uppercase()];
+                value = base64.to_string();
+                changed = true;
+            }
+        }
+        _ => {}
+    }
+
+    if !changed {
+        return line.to_string();
+    }
+    let mut out: String = match prop.group {
+        Some(group) => format!("{group}.{name}"),
+        None => name,
+    };
+    for param in params {
+        out.push(';');
+        out.push_str(param);
+    }
+    if !types.is_empty() {
+        out.push_str(";TYPE=");
+        out.push_str(&types.join(","));
+    }
+    if let Some(rank) = pref {
+        out.push_str(";PREF=");
+        out.push_str(&rank);
+    }
+    out.push(':');
+    out.push_str(&value);
+    out
+}
+
+// Joins folded lines back together and numbers each logical line by the
+// physical line it started on. Blank lines are skipped.
+fn unfold(text: &str) -> Vec<(usize, String)> {
+    let mut lines: Vec<(usize, String)> = Vec::new();
+    for (i, line) in text.split('\n').enumerate() {
+        let line: &str = line.strip_suffix('\r').unwrap_or(line);
+        let continues: bool = line.starts_with([' ', '\t']);
+        match lines.last_mut() {
+            Some((_, last)) if continues => last.push_str(&line[1..]),
+            _ if line.trim().is_empty() => {}
+            _ => lines.push((i + 1, line.to_string())),
+        }
+    }
+    lines
+}
+
+// Breaks a line into pieces of at most 75 bytes, never inside a UTF-8
+// character. Continuation lines start with a space, which counts.
+fn fold(line: &str) -> String {
+    let mut out: String = String::new();
+    let mut used: usize = 0;
+    for ch in line.chars() {
+        if used + ch.len_utf8() > FOLD_AT {
+            out.push_str("\r\n ");
+            used = 1;
+        }
+        out.push(ch);
+        used += ch.len_utf8();
+    }
+    out.push_str("\r\n");
+    out
+}
+
+
+fn parse_property(line: &str) -> Option<Property<'_>> {
+    let colon: usize = find_unquoted(line, ':')?;
+    let head: &str = &line[..colon];
+    let value: &str = &line[colon + 1..];
+
+    let pieces: Vec<&str> = split_unquoted(head, ';');
+
+    let (group, name) = match pieces[0].rsplit_once('.') {
+        Some((group, name)) => (Some(group), name),
+        None => (None, pieces[0]),
+    };
+    if name.is_empty() {
+        return None;
+    }
+
+    let mut params: Vec<Param> = Vec::new();
+    for piece in &pieces[1..] {
+        // 2.1-style bare parameters like TEL;CELL: are TYPE values
+        let (key, values) = piece.split_once('=').unwrap_or(("TYPE", piece));
+        let values: Vec<String> = split_unquoted(values, ',')
+            .into_iter()
+            .map(|v| v.trim_matches('"').to_string())
+            .collect();
+        params.push(Param {
+            name: key.to_ascii_uppercase(),
+            values,
+            raw: piece,
+        });
+    }
+
+    Some(Property {
+        group,
+        name: name.to_ascii_uppercase(),
+        params,
+        value,
+    })
+}
+
+fn split_unquoted(text: &str, separator: char) -> Vec<&str> {
+    let mut pieces: Vec<&str> = Vec::new();
+    let mut start: usize = 0;
+    while let Some(i) = find_unquoted(&text[start..], separator) {
+        pieces.push(&text[start..start + i]);
+        start += i + 1;
+    }
+    pieces.push(&text[start..]);
+    pieces
+}
+
+fn find_unquoted(text: &str, target: char) -> Option<usize> {
+    let mut quoted: bool = false;
+    for (i, ch) in text.char_indices() {
+        if ch == '"' {
+            quoted = !quoted;
+        } else if ch == target && !quoted {
+            return Some(i);
+        }
+    }
+    None
+}
+
+
+fn escape(text: &str) -> String {
+    let mut out: String = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '\\' => out.push_str("\\\\"),
+            ',' => out.push_str("\\,"),
+            ';' => out.push_str("\\;"),
+            '\n' => out.push_str("\\n"),
+            '\r' => {}
+            _ => out.push(ch),
+        }
+    }
+    out
+}
+
+fn unescape(text: &str) -> String {
+    let mut out: String = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            out.push(ch);
+            continue;
+        }
+        match chars.next() {
+            Some('n') | Some('N') => out.push('\n'),
+            Some(other) => out.push(other),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+// Splits a structured value on separators that aren't escaped, then
+// unescapes each part.
+fn split_escaped(text: &str, separator: char) -> Vec<String> {
+    let mut parts: Vec<String> = Vec::new();
+    let mut start: usize = 0;
+    let mut escaped: bool = false;
+    for (i, ch) in text.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if ch == '\\' {
+            escaped = true;
+        } else if ch == separator {
+            parts.push(unescape(&text[start..i]));
+            start = i + 1;
+        }
+    }
+    parts.push(unescape(&text[start..]));
+    parts
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const V3_CARD: &str = "BEGIN:VCARD\r\n\
+        VERSION:3.0\r\n\
+        N:Pad;D-;;;\r\n\
+        FN:D-Pad\r\n\
+        EMAIL;TYPE=INTERNET,PREF;X-LABEL=\"Work, main\":dpad@dpadllc.com\r\n\
+        TEL;TYPE=CELL:+1 555 010 0100\r\n\
+        TEL;TYPE=WORK;X-EXT=12:+1 555 010 0199\r\n\
+        ADR;TYPE=WORK:;Suite 5;1 Main St\\, Rear;Springfield;IL;62701;USA\r\n\
+        LABEL;TYPE=WORK:1 Main St\\, Rear\\nSpringfield\r\n\
+        URL;TYPE=WORK,PREF:https://dpadllc.com\r\n\
+        PHOTO;ENCODING=b;TYPE=JPEG:/9j/4AAQSkZJRg==\r\n\
+        item1.X-ABLABEL:Cat\r\n\
+        CATEGORIES:rust,examples\r\n\
+        X-NOTES-ACTIVE:FALSE\r\n\
+        END:VCARD\r\n";
+
+    const V4_CARD: &str = "BEGIN:VCARD\r\n\
+        VERSION:4.0\r\n\
+        FN:Alecia\r\n\
+        KIND:individual\r\n\
+        EMAIL;TYPE=home;PREF=3;PID=1.1:alecia@dpadllc.com\r\n\
+        TEL;VALUE=uri;TYPE=\"cell,voice\";PREF=2:tel:+1-555-010-0101\r\n\
+        TEL;VALUE=text;TYPE=work:ext. 12\r\n\
+        PHOTO:data:image/png;base64,iVBORw0KGgo=\r\n\
+        GENDER:F\r\n\
+        BDAY:--0412\r\n\
+        END:VCARD\r\n";
+
+    fn one(text: &str) -> Contact {
+        let mut contacts: Vec<Contact> = parse(text).unwrap();
+        assert_eq!(contacts.len(), 1);
+        contacts.remove(0)
+    }
+
+    #[test]
+    fn reads_a_v3_card() {
+        let contact: Contact = one(V3_CARD);
+        assert_eq!(contact.user.name, "D-Pad");
+        assert!(!contact.user.active);
+        assert_eq!(contact.email_kinds, ["internet", "pref"]);
+        assert_eq!(contact.email_params, ["X-LABEL=\"Work, main\""]);
+        assert_eq!(contact.phones.len(), 1);
+        assert_eq!(contact.addresses[0].street, "1 Main St, Rear");
+        assert_eq!(contact.tags, ["rust", "examples"]);
+        assert_eq!(contact.extra.len(), 6);
+    }
+
+    #[test]
+    fn reads_a_v4_card() {
+        let contact: Contact = one(V4_CARD);
+        assert_eq!(contact.email_kinds, ["home", "pref=3"]);
+        assert_eq!(contact.email_params, ["PID=1.1"]);
+        assert_eq!(contact.phones[0].kinds, ["cell", "voice", "pref=2"]);
+        assert_eq!(contact.phones[0].number, "+1-555-010-0101");
+        assert!(contact.phones[0].uri);
+        assert_eq!(contact.phones[1].number, "ext. 12");
+        assert!(!contact.phones[1].uri);
+    }
+
+    #[test]
+    fn phones_keep_their_value_type() {
+        let card = |tel: &str| -> String {
+            format!("BEGIN:VCARD\r\nVERSION:4.0\r\nFN:A\r\n\
+                EMAIL:a@x.com\r\n{tel}\r\nEND:VCARD\r\n")
+        };
+        for (tel, number, uri) in [
+            ("TEL;VALUE=uri:tel:+1-555-0101;ext=12", "+1-555-0101;ext=12",
+                true),
+            ("TEL;VALUE=uri:tel:+15550101", "+15550101", true),
+            ("TEL;VALUE=text:+15550101", "+15550101", false),
+            ("TEL;VALUE=text:ext\\; 12", "ext; 12", false),
+        ] {
+            let contact: Contact = one(&card(tel));
+            assert_eq!(contact.phones[0].number, number);
+            assert_eq!(contact.phones[0].uri, uri);
+            let written: String = write(&contact, Version::V4);
+            assert!(written.contains(&format!("\r\n{tel}\r\n")), "{written}");
+        }
+        // A 3.0 number with no VALUE is text, and a URI that isn't tel: is
+        // kept as it was
+        let v3: String = card("TEL;TYPE=CELL:+1 555 0101")
+            .replace("4.0", "3.0");
+        assert!(write(&one(&v3), Version::V3)
+            .contains("\r\nTEL;TYPE=CELL:+1 555 0101\r\n"));
+        let sip: Contact = one(&card("TEL;VALUE=uri:sip:a@x.com"));
+        assert!(sip.phones.is_empty());
+        assert_eq!(sip.extra, ["TEL;VALUE=uri:sip:a@x.com"]);
+    }
+
+    #[test]
+    fn later_fn_lines_are_kept() {
+        let text: &str = "BEGIN:VCARD\r\nVERSION:4.0\r\n\
+            FN;LANGUAGE=en:Ann\r\nFN;LANGUAGE=fr:Anne\r\n\
+            EMAIL:a@x.com\r\nEND:VCARD\r\n";
+        let contact: Contact = one(text);
+        assert_eq!(contact.user.name, "Ann");
+        assert_eq!(contact.extra, ["FN;LANGUAGE=fr:Anne"]);
+        let written: String = write(&contact, Version::V4);
+        assert!(written.contains("\r\nFN;LANGUAGE=fr:Anne\r\n"));
+        assert_eq!(one(&written), contact);
+    }
+
+    #[test]
+    fn same_version_round_trips() {
+        for (text, version) in [(V3_CARD, Version::V3), (V4_CARD, Version::V4)]
+        {
+            let contact: Contact = one(text);
+            let written: String = write(&contact, version);
+            assert_eq!(one(&written), contact, "{written}");
+            assert_eq!(write(&one(&written), version), written);
+        }
+    }
+
+    #[test]
+    fn extras_follow_the_target_version() {
+        let v4: String = write(&one(V3_CARD), Version::V4);
+        for line in [
+            "URL;TYPE=WORK;PREF=1:https://dpadllc.com",
+            "PHOTO:data:image/jpeg;base64,/9j/4AAQSkZJRg==",
+            "X-LABEL;TYPE=WORK:1 Main St\\, Rear\\nSpringfield",
+            "EMAIL;TYPE=internet;PREF=1;X-LABEL=\"Work, main\":",
+            "item1.X-ABLABEL:Cat",
+        ] {
+            assert!(v4.contains(line), "{line} not in\n{v4}");
+        }
+        // And back again to what we started with
+        let v3: String = write(&one(&v4), Version::V3);
+        assert_eq!(one(&v3), one(V3_CARD));
+
+        let v3: String = write(&one(V4_CARD), Version::V3);
+        for line in [
+            "X-KIND:individual",
+            "X-GENDER:F",
+            "PHOTO;ENCODING=b;TYPE=PNG:iVBORw0KGgo=",
+            "EMAIL;TYPE=HOME,PREF;PID=1.1:",
+            "TEL;VALUE=uri;TYPE=CELL,VOICE,PREF:tel:+1-555-010-0101",
+        ] {
+            assert!(v3.contains(line), "{line} not in\n{v3}");
+        }
+        let v4: String = write(&one(&v3), Version::V4);
+        assert!(v4.contains("\r\nKIND:individual\r\n"));
+        assert!(v4.contains("\r\nPHOTO:data:image/png;base64,iVBORw0KGgo="));
+    }
+
+    #[test]
+    fn quoted_params_keep_their_commas() {
+        let prop: Property =
+            parse_property("ADR;LABEL=\"1 Main St, Rear; IL\";TYPE=work:;;")
+                .unwrap();
+        assert_eq!(prop.params[0].values, ["1 Main St, Rear; IL"]);
+        assert_eq!(prop.params[0].raw, "LABEL=\"1 Main St, Rear; IL\"");
+        assert_eq!(prop.params[1].values, ["work"]);
+        assert!(prop.kinds().is_none());
+        assert_eq!(prop.value, ";;");
+    }
+
+    #[test]
+    fn folds_between_multibyte_chars() {
+        // "NOTE:" and 23 three-byte chars fill 74 bytes, so the 24th would
+        // straddle the 75th
+        let note: String = "日本語".repeat(30);
+        let folded: String = fold(&format!("NOTE:{note}"));
+        let lines: Vec<&str> = folded.split("\r\n")
+            .filter(|l| !l.is_empty())
+            .collect();
+        assert_eq!(lines[0].len(), 74);
+        for line in &lines {
+            assert!(line.len() <= FOLD_AT, "{} bytes", line.len());
+        }
+        assert!(lines[1..].iter().all(|l| l.starts_with(' ')));
+        assert_eq!(unfold(&folded), [(1, format!("NOTE:{note}"))]);
+
+        let mut contact: Contact = one(V4_CARD);
+        contact.user.name = "Zoë ".repeat(40);
+        contact.extra.push(format!("NOTE:{note}"));
+        assert_eq!(one(&write(&contact, Version::V4)), contact);
+    }
+
+    #[test]
+    fn errors_point_at_the_line() {
+        let missing: &str = "BEGIN:VCARD\nVERSION:4.0\nFN:Nobody\nEND:VCARD\n";
+        assert_eq!(
+            parse(missing),
+            Err(ContactError::MissingEmail {
+                line: 1,
+                name: String::from("Nobody"),
+            })
+        );
+        let old: &str = "BEGIN:VCARD\nVERSION:2.1\nEND:VCARD\n";
+        assert_eq!(
+            parse(old),
+            Err(ContactError::UnsupportedVersion {
+                line: 2,
+                version: String::from("2.1"),
+            })
+        );
+        assert!(matches!(
+            parse("BEGIN:VCARD\nVERSION:4.0\nFN:A\nEMAIL:a@x.com\n"),
+            Err(ContactError::Syntax { line: 1, .. })
+        ));
+    }
+}
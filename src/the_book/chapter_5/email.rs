@@ -0,0 +1,497 @@
+use std::fmt;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+
+use crate::layout::{layout_of, Registry};
+
+
+/*
+* An email address that has been checked, so a User can't end up with
+* "banana" as its email. The parser covers the parts of RFC 5322 that real
+* addresses use:
+*
+*   dpad@dpadllc.com              dot-atom local part and domain
+*   "D Pad"@dpadllc.com           quoted local part, with \" and \\ escapes
+*   dpad@[192.0.2.1]              IPv4 literal
+*   dpad@[IPv6:2001:db8::1]       IPv6 literal
+*   dpad@bücher.example           IDN domain, stored as xn--bcher-kva
+*
+* Comments, folding whitespace and obsolete syntax are not accepted.
+*
+* Normalizing lowercases the domain, since domains are case-insensitive,
+* but leaves the local part exactly as written. Whether "DPad" and "dpad"
+* are the same mailbox is up to the receiving server.
+*/
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct EmailAddress {
+    local: String,
+    domain: String,
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EmailError {
+    Empty,
+    MissingAt,
+    EmptyLocalPart,
+    LocalPartTooLong(usize),
+    InvalidLocalChar(char),
+    MisplacedDot,
+    UnterminatedQuote,
+    InvalidQuotedChar(char),
+    EmptyDomain,
+    DomainTooLong(usize),
+    EmptyLabel,
+    LabelTooLong(String),
+    InvalidDomainChar(char),
+    MisplacedHyphen(String),
+    InvalidIpLiteral(String),
+    AddressTooLong(usize),
+}
+
+impl fmt::Display for EmailError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EmailError::Empty => write!(f, "address is empty"),
+            EmailError::MissingAt => write!(f, "address has no @"),
+            EmailError::EmptyLocalPart => {
+                write!(f, "nothing before the @")
+            }
+            EmailError::LocalPartTooLong(len) => {
+                write!(f, "local part is {len} bytes, the limit is 64")
+            }
+            EmailError::InvalidLocalChar(ch) => {
+                write!(f, "{ch:?} is not allowed in the local part")
+            }
+            EmailError::MisplacedDot => write!(
+                f,
+                "local part can't start or end with a dot or have two in a row"
+            ),
+            EmailError::UnterminatedQuote => {
+                write!(f, "quoted local part is missing its closing quote")
+            }
+            EmailError::InvalidQuotedChar(ch) => {
+                write!(f, "{ch:?} is not allowed inside quotes")
+            }
+            EmailError::EmptyDomain => write!(f, "nothing after the @"),
+            EmailError::DomainTooLong(len) => {
+                write!(f, "domain is {len} bytes, the limit is 253")
+            }
+            EmailError::EmptyLabel => {
+                write!(f, "domain has an empty label (two dots in a row?)")
+            }
+            EmailError::LabelTooLong(label) => {
+                write!(f, "domain label {label} is longer than 63 bytes")
+            }
+            EmailError::InvalidDomainChar(ch) => {
+                write!(f, "{ch:?} is not allowed in a domain")
+            }
+            EmailError::MisplacedHyphen(label) => {
+                write!(f, "domain label {label} starts or ends with a hyphen")
+            }
+            EmailError::InvalidIpLiteral(literal) => {
+                write!(f, "[{literal}] is not an IPv4 or IPv6 address")
+            }
+            EmailError::AddressTooLong(len) => {
+                write!(f, "address is {len} bytes, the limit is 254")
+            }
+        }
+    }
+}
+
+
+impl EmailAddress {
+
+    pub fn parse(text: &str) -> Result<EmailAddress, EmailError> {
+        if text.is_empty() {
+            return Err(EmailError::Empty);
+        }
+        let (local, rest) = if text.starts_with('"') {
+            split_quoted(text)?
+        } else {
+            let at: usize = text.rfind('@').ok_or(EmailError::MissingAt)?;
+            (&text[..at], &text[at + 1..])
+        };
+        check_local(local)?;
+
+        let domain: String = if rest.starts_with('[') {
+            ip_literal(rest)?
+        } else {
+            dns_domain(rest)?
+        };
+
+        let address: EmailAddress = EmailAddress {
+            local: local.to_string(),
+            domain,
+        };
+        let len: usize = address.local.len() + 1 + address.domain.len();
+        if len > 254 {
+            return Err(EmailError::AddressTooLong(len));
+        }
+        Ok(address)
+    }
+
+    pub fn local_part(&self) -> &str {
+        &self.local
+    }
+
+    // The normalized domain: lowercase ASCII, with IDN labels in punycode
+    pub fn domain(&self) -> &str {
+        &self.domain
+    }
+}
+
+impl FromStr for EmailAddress {
+    type Err = EmailError;
+
+    fn from_str(text: &str) -> Result<EmailAddress, EmailError> {
+        EmailAddress::parse(text)
+    }
+}
+
+impl fmt::Display for EmailAddress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}@{}", self.local, self.domain)
+    }
+}
+
+
+// Splits `"quoted local"@domain` after the closing quote. The quotes stay
+// in the local part because they're part of how the address is written.
+fn split_quoted(text: &str) -> Result<(&str, &str), EmailError> {
+    let mut escaped: bool = false;
+    for (i, ch) in text.char_indices().skip(1) {
+        if escaped {
+            if !(ch == ' ' || ch.is_ascii_graphic()) {
+                return Err(EmailError::InvalidQuotedChar(ch));
+            }
+            escaped = false;
+        } else if ch == '\\' {
+            escaped = true;
+        } else if ch == '"' {
+            let end: usize = i + 1;
+            return match text[end..].strip_prefix('@') {
+                Some(domain) => Ok((&text[..end], domain)),
+                None => match text[end..].chars().next() {
+                    Some(ch) => Err(EmailError::InvalidLocalChar(ch)),
+                    None => Err(EmailError::MissingAt),
+                },
+            };
+        } else if !(ch == ' ' || ch.is_ascii_graphic()) {
+            return Err(EmailError::InvalidQuotedChar(ch));
+        }
+    }
+    Err(EmailError::UnterminatedQuote)
+}
+
+
+// A dot-atom: runs of atext separated by single dots. Non-ASCII letters are
+// allowed too, as RFC 6532 does for internationalized mail.
+fn check_local(local: &str) -> Result<(), EmailError> {
+    if local.is_empty() {
+        return Err(EmailError::EmptyLocalPart);
+    }
+    if local.len() > 64 {
+        return Err(EmailError::LocalPartTooLong(local.len()));
+    }
+    if local.starts_with('"') {
+        return Ok(());
+    }
+    for atom in local.split('.') {
+        if atom.is_empty() {
+            return Err(EmailError::MisplacedDot);
+        }
+        if let Some(ch) = atom.chars().find(|&c| !is_atext(c)) {
+            return Err(EmailError::InvalidLocalChar(ch));
+        }
+    }
+    Ok(())
+}
+
+fn is_atext(ch: char) -> bool {
+    ch.is_ascii_alphanumeric()
+        || "!#$%&'*+-/=?^_`{|}~".contains(ch)
+        || (!ch.is_ascii() && ch.is_alphanumeric())
+}
+
+
+fn ip_literal(text: &str) -> Result<String, EmailError> {
+    let inner: &str = text.strip_prefix('[')
+        .and_then(|t| t.strip_suffix(']'))
+        .ok_or_else(|| EmailError::InvalidIpLiteral(text.to_string()))?;
+    let bad = || EmailError::InvalidIpLiteral(inner.to_string());
+    let tag: Option<&str> = inner.get(..5);
+    if tag.is_some_and(|t| t.eq_ignore_ascii_case("ipv6:")) {
+        let ip: Ipv6Addr = inner[5..].parse().map_err(|_| bad())?;
+        Ok(format!("[IPv6:{ip}]"))
+    } else {
+        let ip: Ipv4Addr = inner.parse().map_err(|_| bad())?;
+        Ok(format!("[{ip}]"))
+    }
+}
+
+
+fn dns_domain(text: &str) -> Result<String, EmailError> {
+    if text.is_empty() {
+        return Err(EmailError::EmptyDomain);
+    }
+    let mut labels: Vec<String> = Vec::new();
+    // IDNA treats the ideographic and full-width full stops as dots too
+    for label in text.split(['.', '\u{3002}', '\u{FF0E}', '\u{FF61}']) {
+        if label.is_empty() {
+            return Err(EmailError::EmptyLabel);
+        }
+        let label: String = if label.is_ascii() {
+            label.to_ascii_lowercase()
+        } else {
+            // Punycode never comes out shorter than the characters going
+            // in, so a label this long can be turned down straight away
+            if label.chars().count() > 63 {
+                return Err(EmailError::LabelTooLong(label.to_string()));
+            }
+            if let Some(ch) = label.chars()
+                .find(|&c| !(c.is_alphanumeric() || c == '-'))
+            {
+                return Err(EmailError::InvalidDomainChar(ch));
+            }
+            format!("xn--{}", punycode(&label.to_lowercase()))
+        };
+        if let Some(ch) = label.chars()
+            .find(|&c| !(c.is_ascii_alphanumeric() || c == '-'))
+        {
+            return Err(EmailError::InvalidDomainChar(ch));
+        }
+        if label.len() > 63 {
+            return Err(EmailError::LabelTooLong(label));
+        }
+        if label.starts_with('-') || label.ends_with('-') {
+            return Err(EmailError::MisplacedHyphen(label));
+        }
+        labels.push(label);
+    }
+    let domain: String = labels.join(".");
+    if domain.len() > 253 {
+        return Err(EmailError::DomainTooLong(domain.len()));
+    }
+    Ok(domain)
+}
+
+
+/*
+* Punycode (RFC 3492) turns a Unicode label into ASCII. The plain ASCII
+* letters are copied first, then each non-ASCII character is written as a
+* base-36 "delta" saying how far along in code points and in position it
+* goes. "bücher" becomes "bcher-kva".
+*
+* dns_domain only passes in labels of at most 63 characters. The largest
+* delta is then about 0x10FFFF * 64, well inside a u32, but a longer label
+* could overflow it.
+*/
+fn punycode(label: &str) -> String {
+    const BASE: u32 = 36;
+    const T_MIN: u32 = 1;
+    const T_MAX: u32 = 26;
+
+    let chars: Vec<u32> = label.chars().map(|c| c as u32).collect();
+    let mut out: String = label.chars().filter(char::is_ascii).collect();
+    let basic: u32 = out.len() as u32;
+    if basic > 0 {
+        out.push('-');
+    }
+
+    let mut n: u32 = 128;
+    let mut delta: u32 = 0;
+    let mut bias: u32 = 72;
+    let mut handled: u32 = basic;
+    while (handled as usize) < chars.len() {
+        let next: u32 = chars.iter()
+            .copied()
+            .filter(|&c| c >= n)
+            .min()
+            .unwrap();
+        delta += (next - n) * (handled + 1);
+        n = next;
+        for &c in &chars {
+            if c < n {
+                delta += 1;
+            }
+            if c != n {
+                continue;
+            }
+            let mut q: u32 = delta;
+            let mut k: u32 = BASE;
+            loop {
+                let t: u32 = k.saturating_sub(bias).clamp(T_MIN, T_MAX);
+                if q < t {
+                    break;
+                }
+                out.push(punycode_digit(t + (q - t) % (BASE - t)));
+                q = (q - t) / (BASE - t);
+                k += BASE;
+            }
+            out.push(punycode_digit(q));
+            bias = punycode_adapt(delta, handled + 1, handled == basic);
+            delta = 0;
+            handled += 1;
+        }
+        delta += 1;
+        n += 1;
+    }
+    out
+}
+
+fn punycode_digit(d: u32) -> char {
+    match d {
+        0..=25 => (b'a' + d as u8) as char,
+        _ => (b'0' + (d - 26) as u8) as char,
+    }
+}
+
+fn punycode_adapt(delta: u32, points: u32, first: bool) -> u32 {
+    let mut delta: u32 = if first { delta / 700 } else { delta / 2 };
+    delta += delta / points;
+    let mut k: u32 = 0;
+    while delta > ((36 - 1) * 26) / 2 {
+        delta /= 36 - 1;
+        k += 36;
+    }
+    k + (36 * delta) / (delta + 38)
+}
+
+
+pub fn run() {
+    println!("\nEmail addresses:");
+    for text in [
+        "dpad@DPadLLC.com",
+        "\"D Pad\"@dpadllc.com",
+        "dpad@[192.0.2.1]",
+        "dpad@[ipv6:2001:DB8:0:0::1]",
+        "alecia@Bücher.example",
+        "zoë@münchen.de",
+        "banana",
+        "dpad..llc@dpadllc.com",
+        "dpad@-dpad.com",
+        "\"dpad@dpadllc.com",
+        "dpad@[999.0.0.1]",
+    ] {
+        match EmailAddress::parse(text) {
+            Ok(email) => println!("  {text:<30} -> {email}"),
+            Err(err) => println!("  {text:<30} !! {err}"),
+        }
+    }
+}
+
+
+pub fn register_layouts(registry: &mut Registry) {
+    registry.register("Chapter 5", layout_of!(EmailAddress { local, domain }));
+    registry.register("Chapter 5", layout_of!(EmailError));
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_the_domain_only() {
+        let email: EmailAddress = "DPad@DPadLLC.COM".parse().unwrap();
+        assert_eq!(email.local_part(), "DPad");
+        assert_eq!(email.domain(), "dpadllc.com");
+        assert_eq!(email.to_string(), "DPad@dpadllc.com");
+
+        for (text, shown) in [
+            ("\"D Pad\"@x.com", "\"D Pad\"@x.com"),
+            ("\"a\\\"b\"@x.com", "\"a\\\"b\"@x.com"),
+            ("first.o'neil+tag@x.com", "first.o'neil+tag@x.com"),
+            ("a@[192.0.2.1]", "a@[192.0.2.1]"),
+            ("a@[ipv6:2001:DB8:0:0::1]", "a@[IPv6:2001:db8::1]"),
+            ("zoë@Bücher.example", "zoë@xn--bcher-kva.example"),
+            ("a@例え。テスト", "a@xn--r8jz45g.xn--zckzah"),
+        ] {
+            assert_eq!(
+                EmailAddress::parse(text).map(|e| e.to_string()),
+                Ok(shown.to_string()),
+                "{text}"
+            );
+        }
+    }
+
+    #[test]
+    fn punycode_vectors() {
+        for (label, encoded) in [
+            ("bücher", "bcher-kva"),
+            ("例え", "r8jz45g"),
+            ("テスト", "zckzah"),
+            ("münchen", "mnchen-3ya"),
+            // From RFC 3492 section 7.1
+            ("他们为什么不说中文", "ihqwcrb4cv8a8dqg056pqjye"),
+            ("ليهمابتكلموشعربي؟", "egbpdaj6bu4bxfgehfvwxn"),
+            ("3年b組金八先生", "3b-ww4c5e180e575a65lsy2b"),
+        ] {
+            assert_eq!(punycode(label), encoded, "{label}");
+        }
+    }
+
+    #[test]
+    fn every_error() {
+        let long_local: String = "a".repeat(65);
+        let long_label: String = "b".repeat(64);
+        let long_domain: String = vec!["c".repeat(63); 5].join(".");
+        let long_address: String = format!(
+            "{}@{}",
+            "a".repeat(64),
+            vec!["c".repeat(60); 4].join(".")
+        );
+        let cases: Vec<(String, EmailError)> = vec![
+            (String::new(), EmailError::Empty),
+            (String::from("banana"), EmailError::MissingAt),
+            (String::from("@x.com"), EmailError::EmptyLocalPart),
+            (format!("{long_local}@x.com"), EmailError::LocalPartTooLong(65)),
+            (String::from("a b@x.com"), EmailError::InvalidLocalChar(' ')),
+            (String::from("\"a\"b@x.com"), EmailError::InvalidLocalChar('b')),
+            (String::from("a..b@x.com"), EmailError::MisplacedDot),
+            (String::from(".a@x.com"), EmailError::MisplacedDot),
+            (String::from("\"abc@x.com"), EmailError::UnterminatedQuote),
+            (
+                String::from("\"a\u{7}\"@x.com"),
+                EmailError::InvalidQuotedChar('\u{7}'),
+            ),
+            (String::from("a@"), EmailError::EmptyDomain),
+            (format!("a@{long_domain}"), EmailError::DomainTooLong(319)),
+            (String::from("a@x..com"), EmailError::EmptyLabel),
+            (
+                format!("a@{long_label}.com"),
+                EmailError::LabelTooLong(long_label.clone()),
+            ),
+            (String::from("a@x_y.com"), EmailError::InvalidDomainChar('_')),
+            (String::from("a@bü_r.de"), EmailError::InvalidDomainChar('_')),
+            (
+                String::from("a@-x.com"),
+                EmailError::MisplacedHyphen(String::from("-x")),
+            ),
+            (
+                String::from("a@[999.0.0.1]"),
+                EmailError::InvalidIpLiteral(String::from("999.0.0.1")),
+            ),
+            (long_address, EmailError::AddressTooLong(308)),
+        ];
+        for (text, err) in cases {
+            assert_eq!(EmailAddress::parse(&text), Err(err), "{text}");
+        }
+    }
+
+    #[test]
+    fn long_idn_labels_are_rejected_before_encoding() {
+        let label: String = format!("{}\u{2A700}", "a".repeat(25_000));
+        let text: String = format!("a@{label}.com");
+        assert_eq!(
+            EmailAddress::parse(&text),
+            Err(EmailError::LabelTooLong(label))
+        );
+        let label: String = "ü".repeat(64);
+        assert_eq!(
+            EmailAddress::parse(&format!("a@{label}.de")),
+            Err(EmailError::LabelTooLong(label))
+        );
+    }
+}
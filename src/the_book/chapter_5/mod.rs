@@ -1,10 +1,35 @@
-mod person;
-mod rectangle;
+use crate::layout::Registry;
+
+pub mod person;
+pub mod email;
+pub mod user_builder;
+pub mod rectangle;
+pub mod bin_packing;
+pub mod tiling;
+pub mod user_store;
+pub mod contacts;
 
 
 pub fn run() {
     println!("\nChapter 5: Structs"); 
     person::run();
+    email::run();
+    user_builder::run();
     rectangle::run();
+    bin_packing::run();
+    tiling::run();
+    user_store::run();
+    contacts::run();
 }
 
+
+pub fn register_layouts(registry: &mut Registry) {
+    person::register_layouts(registry);
+    email::register_layouts(registry);
+    user_builder::register_layouts(registry);
+    rectangle::register_layouts(registry);
+    bin_packing::register_layouts(registry);
+    tiling::register_layouts(registry);
+    user_store::register_layouts(registry);
+    contacts::register_layouts(registry);
+}
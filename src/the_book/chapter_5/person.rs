@@ -1,31 +1,33 @@
+use super::email::EmailAddress;
+use crate::layout::{layout_of, Registry};
+
 // User Struct
-#[derive(Debug)]
-struct User {
-    active: bool,
-    name: String,
-    email: String
+#[derive(Debug, Clone, PartialEq)]
+pub struct User {
+    pub active: bool,
+    pub name: String,
+    pub email: EmailAddress
 }
 
 impl User {
-    fn greet(&self) {
+    pub fn greet(&self) {
         println!("Hello, my name is {}", self.name); 
     }
 }
 
-fn build_new_user(email: String, name: String) -> User {
-    let user = User {
+pub fn build_new_user(email: EmailAddress, name: String) -> User {
+    User {
         active: true,
         email,
-        name 
-    };
-    user
+        name
+    }
 }
 
 pub fn run() {
     let mut user = User {
         active: true,
         name: String::from("D-Pad"),
-        email: String::from("dpad@dpadllc.com")
+        email: "dpad@dpadllc.com".parse().unwrap()
     };
 
     user.active = false;
@@ -35,19 +37,24 @@ pub fn run() {
 
     // Make a new user with the function 
     let new_name: String = String::from("Alecia");
-    let new_email: String = String::from("alecia@dpadllc.com");
+    let new_email: EmailAddress = "alecia@dpadllc.com".parse().unwrap();
     
     let new_user: User = build_new_user(new_email, new_name);
     println!("New user: {}", new_user.name);
 
-    // Copy syntax
-    let copied_user = User {
-        active: false,
-        ..user 
-    };
+    // Copy with the builder. Struct update syntax (`User { active: false,
+    // ..user }`) would move user's name and email and skip every check.
+    let copied_user: User = user.to_builder()
+        .active(false)
+        .build()
+        .unwrap();
     println!("Copied user: {:#?}", copied_user);
     copied_user.greet();
+    user.greet();
 
 }
 
 
+pub fn register_layouts(registry: &mut Registry) {
+    registry.register("Chapter 5", layout_of!(User { active, name, email }));
+}
@@ -1,29 +1,160 @@
+use crate::layout::{layout_of, Registry};
+
 
 // Rectangle struct 
-#[derive(Debug)]
-struct Rectangle {
-    width: u32,
-    height: u32 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rectangle {
+    pub width: u32,
+    pub height: u32 
+}
+
+// How strict a fit check should be. The original can_fit_inside needed
+// room to spare on both sides and never tried turning the rectangle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FitOptions {
+    pub allow_rotation: bool,
+    pub allow_exact: bool
+}
+
+impl FitOptions {
+    pub const STRICT: FitOptions = FitOptions {
+        allow_rotation: false,
+        allow_exact: false
+    };
+    pub const LOOSE: FitOptions = FitOptions {
+        allow_rotation: true,
+        allow_exact: true
+    };
 }
 
 impl Rectangle {
     
-    fn area(&self) -> u32 {
-        self.width * self.height 
+    // Two u32s multiplied together can need up to 64 bits, so the area is
+    // widened first instead of overflowing.
+    pub fn area(&self) -> u64 {
+        self.width as u64 * self.height as u64 
     }
 
-    fn rotate(&mut self) {
+    pub fn rotate(&mut self) {
         let temp_w: u32 = self.width;
         let temp_h: u32 = self.height;
         self.width = temp_h;
         self.height = temp_w;
     }
+
+    pub fn rotated(&self) -> Rectangle {
+        Rectangle { width: self.height, height: self.width }
+    }
     
-    fn can_fit_inside(&self, other: &Rectangle) -> bool {
-        if self.width < other.width && self.height < other.height {
-            return true
+    pub fn can_fit_inside(&self, other: &Rectangle) -> bool {
+        self.fits_inside(other, FitOptions::STRICT)
+    }
+
+    pub fn fits_inside(&self, other: &Rectangle, options: FitOptions) -> bool {
+        let fits = |a: &Rectangle| {
+            if options.allow_exact {
+                a.width <= other.width && a.height <= other.height
+            } else {
+                a.width < other.width && a.height < other.height
+            }
+        };
+        fits(self) || (options.allow_rotation && fits(&self.rotated()))
+    }
+}
+
+
+/*
+* A rectangle with a position. (x, y) is the top left corner, and x grows
+* to the right while y grows downward, like screen coordinates. The right
+* and bottom edges are exclusive, so a 10 wide rect at x = 0 covers columns
+* 0 through 9 and a rect at x = 10 sits right next to it without touching.
+*
+* Edges are returned as u64 since x + width can go past u32::MAX.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32
+}
+
+impl Rect {
+
+    pub fn new(x: u32, y: u32, width: u32, height: u32) -> Rect {
+        Rect { x, y, width, height }
+    }
+
+    pub fn at_origin(size: Rectangle) -> Rect {
+        Rect::new(0, 0, size.width, size.height)
+    }
+
+    pub fn size(&self) -> Rectangle {
+        Rectangle { width: self.width, height: self.height }
+    }
+
+    pub fn right(&self) -> u64 {
+        self.x as u64 + self.width as u64
+    }
+
+    pub fn bottom(&self) -> u64 {
+        self.y as u64 + self.height as u64
+    }
+
+    pub fn area(&self) -> u64 {
+        self.size().area()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+
+    pub fn contains_point(&self, x: u32, y: u32) -> bool {
+        x >= self.x && y >= self.y
+            && (x as u64) < self.right() && (y as u64) < self.bottom()
+    }
+
+    // True when every part of other is inside self. Edges can line up.
+    pub fn contains(&self, other: &Rect) -> bool {
+        other.x >= self.x && other.y >= self.y
+            && other.right() <= self.right() && other.bottom() <= self.bottom()
+    }
+
+    // Only counts shared area. Rects that just touch along an edge don't
+    // overlap.
+    pub fn overlaps(&self, other: &Rect) -> bool {
+        self.intersection(other).is_some()
+    }
+
+    pub fn intersection(&self, other: &Rect) -> Option<Rect> {
+        let left: u32 = self.x.max(other.x);
+        let top: u32 = self.y.max(other.y);
+        let right: u64 = self.right().min(other.right());
+        let bottom: u64 = self.bottom().min(other.bottom());
+        if right <= left as u64 || bottom <= top as u64 {
+            return None;
         }
-        false
+        Some(Rect::new(
+            left,
+            top,
+            (right - left as u64) as u32,
+            (bottom - top as u64) as u32
+        ))
+    }
+
+    // The smallest rect that covers both. None if it would be too big to
+    // describe with a u32 width or height.
+    pub fn union(&self, other: &Rect) -> Option<Rect> {
+        let left: u32 = self.x.min(other.x);
+        let top: u32 = self.y.min(other.y);
+        let width: u64 = self.right().max(other.right()) - left as u64;
+        let height: u64 = self.bottom().max(other.bottom()) - top as u64;
+        Some(Rect::new(
+            left,
+            top,
+            u32::try_from(width).ok()?,
+            u32::try_from(height).ok()?
+        ))
     }
 }
 
@@ -53,5 +184,171 @@ pub fn run() {
     Rectangle::rotate(&mut rect_a);
     println!("Rotating rectangle 'A'");
     println!("Width and height of 'A': {}x{}", &rect_a.width, &rect_a.height);
+    println!("'A' fits in 'B' if it can be turned: {}",
+        rect_a.fits_inside(&rect_b, FitOptions::LOOSE));
+
+    // Big enough that a u32 area would overflow
+    let huge = Rectangle { width: u32::MAX, height: u32::MAX };
+    println!("Area of a {}x{} rectangle: {}", huge.width, huge.height,
+        huge.area());
+
+    println!("\n  -- Positioned Rects --");
+    let window = Rect::new(0, 0, 80, 24);
+    let popup = Rect::new(70, 20, 20, 10);
+    println!("Window: {:?}", window);
+    println!("Popup: {:?}", popup);
+    println!("Overlap: {}", window.overlaps(&popup));
+    println!("Intersection: {:?}", window.intersection(&popup));
+    println!("Union: {:?}", window.union(&popup));
+    println!("Window contains popup: {}", window.contains(&popup));
 }
 
+
+pub fn register_layouts(registry: &mut Registry) {
+    registry.register("Chapter 5", layout_of!(Rectangle { width, height }));
+    registry.register("Chapter 5", layout_of!(FitOptions {
+        allow_rotation, allow_exact
+    }));
+    registry.register("Chapter 5", layout_of!(Rect { x, y, width, height }));
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    // Property tests: throw lots of random rects at each rule. Coordinates
+    // come from a small range most of the time so rects actually overlap,
+    // and from the far end of u32 the rest of the time to catch overflow.
+    const CASES: usize = 2_000;
+
+    fn random_u32(rng: &mut StdRng) -> u32 {
+        if rng.gen_bool(0.8) {
+            rng.gen_range(0..40)
+        } else {
+            rng.gen_range(u32::MAX - 40..=u32::MAX)
+        }
+    }
+
+    fn random_rect(rng: &mut StdRng) -> Rect {
+        Rect::new(
+            random_u32(rng),
+            random_u32(rng),
+            random_u32(rng),
+            random_u32(rng)
+        )
+    }
+
+    fn random_size(rng: &mut StdRng) -> Rectangle {
+        Rectangle { width: random_u32(rng), height: random_u32(rng) }
+    }
+
+    #[test]
+    fn area_never_overflows() {
+        let mut rng: StdRng = StdRng::seed_from_u64(1);
+        for _ in 0..CASES {
+            let r: Rectangle = random_size(&mut rng);
+            assert_eq!(r.area(), r.width as u64 * r.height as u64);
+            assert_eq!(r.area(), r.rotated().area());
+        }
+        let max = Rectangle { width: u32::MAX, height: u32::MAX };
+        assert_eq!(max.area(), 18_446_744_065_119_617_025);
+    }
+
+    #[test]
+    fn intersection_properties() {
+        let mut rng: StdRng = StdRng::seed_from_u64(2);
+        for _ in 0..CASES {
+            let a: Rect = random_rect(&mut rng);
+            let b: Rect = random_rect(&mut rng);
+            assert_eq!(a.intersection(&b), b.intersection(&a));
+            assert_eq!(a.overlaps(&b), a.intersection(&b).is_some());
+            if let Some(i) = a.intersection(&b) {
+                assert!(!i.is_empty());
+                assert!(a.contains(&i) && b.contains(&i));
+                assert!(i.area() <= a.area().min(b.area()));
+                assert!(a.contains_point(i.x, i.y));
+                assert!(b.contains_point(i.x, i.y));
+            }
+        }
+    }
+
+    #[test]
+    fn union_properties() {
+        let mut rng: StdRng = StdRng::seed_from_u64(3);
+        for _ in 0..CASES {
+            let a: Rect = random_rect(&mut rng);
+            let b: Rect = random_rect(&mut rng);
+            assert_eq!(a.union(&b), b.union(&a));
+            match a.union(&b) {
+                Some(u) => {
+                    assert!(u.contains(&a) && u.contains(&b));
+                    assert!(u.area() >= a.area().max(b.area()));
+                }
+                None => {
+                    let wide: u64 = a.right().max(b.right())
+                        - a.x.min(b.x) as u64;
+                    let tall: u64 = a.bottom().max(b.bottom())
+                        - a.y.min(b.y) as u64;
+                    assert!(wide > u32::MAX as u64 || tall > u32::MAX as u64);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn containment_properties() {
+        let mut rng: StdRng = StdRng::seed_from_u64(4);
+        for _ in 0..CASES {
+            let a: Rect = random_rect(&mut rng);
+            let b: Rect = random_rect(&mut rng);
+            assert!(a.contains(&a));
+            if a.contains(&b) {
+                if !b.is_empty() {
+                    assert_eq!(a.intersection(&b), Some(b));
+                }
+                if let Some(u) = a.union(&b) {
+                    assert_eq!(u, a);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn touching_edges_do_not_overlap() {
+        let a: Rect = Rect::new(0, 0, 10, 10);
+        let b: Rect = Rect::new(10, 0, 10, 10);
+        assert!(!a.overlaps(&b));
+        assert!(!a.contains_point(10, 5));
+        assert_eq!(a.union(&b), Some(Rect::new(0, 0, 20, 10)));
+    }
+
+    #[test]
+    fn fit_options() {
+        let mut rng: StdRng = StdRng::seed_from_u64(5);
+        for _ in 0..CASES {
+            let a: Rectangle = random_size(&mut rng);
+            let b: Rectangle = random_size(&mut rng);
+            let strict: bool = a.fits_inside(&b, FitOptions::STRICT);
+            let exact = FitOptions { allow_rotation: false, allow_exact: true };
+            let turn = FitOptions { allow_rotation: true, allow_exact: false };
+            assert_eq!(strict, a.can_fit_inside(&b));
+
+            // Loosening an option can only ever let more rectangles fit
+            assert!(!strict || a.fits_inside(&b, exact));
+            assert!(!strict || a.fits_inside(&b, turn));
+            assert!(!a.fits_inside(&b, exact)
+                || a.fits_inside(&b, FitOptions::LOOSE));
+
+            // Rotation makes the check independent of orientation
+            assert_eq!(
+                a.fits_inside(&b, FitOptions::LOOSE),
+                a.rotated().fits_inside(&b, FitOptions::LOOSE)
+            );
+            assert!(a.fits_inside(&a, exact));
+            assert!(!a.fits_inside(&a, FitOptions::STRICT));
+        }
+    }
+}
@@ -0,0 +1,604 @@
+use std::fmt;
+
+use super::rectangle::{Rect, Rectangle};
+use crate::layout::{layout_of, Registry};
+
+
+/*
+* A tiling layout, like the ones in i3 or dwm. The screen is a tree: every
+* leaf is a pane, and every branch splits its area between two children.
+*
+*   Horizontal  children side by side, left then right
+*   Vertical    children stacked, top then bottom
+*
+* Each split has a ratio for how much goes to the first child. Panes are
+* never placed on top of each other, and together with the gaps between
+* them they always cover the whole screen.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct PaneId(pub u32);
+
+impl fmt::Display for PaneId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Direction {
+    Horizontal,
+    Vertical,
+}
+
+
+#[derive(Debug, PartialEq)]
+pub enum LayoutError {
+    UnknownPane(PaneId),
+    MissingTarget,
+    NoRoom,
+    NoSplit(PaneId),
+}
+
+impl fmt::Display for LayoutError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LayoutError::UnknownPane(id) => write!(f, "no pane {id}"),
+            LayoutError::MissingTarget => {
+                write!(f, "pick a pane to split, the screen isn't empty")
+            }
+            LayoutError::NoRoom => {
+                write!(f, "not enough room for the panes at minimum size")
+            }
+            LayoutError::NoSplit(id) => {
+                write!(f, "{id} fills the screen, there's no split to resize")
+            }
+        }
+    }
+}
+
+
+#[derive(Debug, Clone)]
+enum Node {
+    Pane(PaneId),
+    Split {
+        direction: Direction,
+        ratio: f64,
+        first: Box<Node>,
+        second: Box<Node>,
+    },
+}
+
+impl Node {
+
+    fn contains(&self, id: PaneId) -> bool {
+        match self {
+            Node::Pane(pane) => *pane == id,
+            Node::Split { first, second, .. } => {
+                first.contains(id) || second.contains(id)
+            }
+        }
+    }
+
+    // The smallest (width, height) this subtree can shrink to
+    fn min_size(&self, min: Rectangle, gap: u32) -> (u64, u64) {
+        match self {
+            Node::Pane(_) => (min.width as u64, min.height as u64),
+            Node::Split { direction, first, second, .. } => {
+                let a: (u64, u64) = first.min_size(min, gap);
+                let b: (u64, u64) = second.min_size(min, gap);
+                match direction {
+                    Direction::Horizontal => {
+                        (a.0 + gap as u64 + b.0, a.1.max(b.1))
+                    }
+                    Direction::Vertical => {
+                        (a.0.max(b.0), a.1 + gap as u64 + b.1)
+                    }
+                }
+            }
+        }
+    }
+
+    fn layout(
+        &self,
+        area: Rect,
+        min: Rectangle,
+        gap: u32,
+        out: &mut Vec<(PaneId, Rect)>,
+        gaps: &mut Vec<Rect>
+    ) {
+        let (direction, ratio, first, second) = match self {
+            Node::Pane(id) => {
+                out.push((*id, area));
+                return;
+            }
+            Node::Split { direction, ratio, first, second } => {
+                (direction, ratio, first, second)
+            }
+        };
+        let horizontal: bool = *direction == Direction::Horizontal;
+        let length: u32 = if horizontal { area.width } else { area.height };
+        let available: u32 = length.saturating_sub(gap);
+
+        // Follow the ratio, but never squeeze either side under its minimum
+        let pick = |size: (u64, u64)| -> u32 {
+            (if horizontal { size.0 } else { size.1 }) as u32
+        };
+        let first_min: u32 = pick(first.min_size(min, gap));
+        let second_min: u32 = pick(second.min_size(min, gap));
+        let wanted: u32 = (available as f64 * ratio).round() as u32;
+        let first_len: u32 = wanted
+            .min(available.saturating_sub(second_min))
+            .max(first_min.min(available));
+        let second_len: u32 = available - first_len;
+        let skip: u32 = first_len + length - available;
+
+        let gap_len: u32 = length - available;
+        let (a, strip, b) = if horizontal {
+            (
+                Rect::new(area.x, area.y, first_len, area.height),
+                Rect::new(area.x + first_len, area.y, gap_len, area.height),
+                Rect::new(area.x + skip, area.y, second_len, area.height),
+            )
+        } else {
+            (
+                Rect::new(area.x, area.y, area.width, first_len),
+                Rect::new(area.x, area.y + first_len, area.width, gap_len),
+                Rect::new(area.x, area.y + skip, area.width, second_len),
+            )
+        };
+        if !strip.is_empty() {
+            gaps.push(strip);
+        }
+        first.layout(a, min, gap, out, gaps);
+        second.layout(b, min, gap, out, gaps);
+    }
+
+    // Replaces a pane with whatever `build` makes from it
+    fn replace_pane(&mut self, id: PaneId, build: &dyn Fn(PaneId) -> Node)
+        -> bool
+    {
+        match self {
+            Node::Pane(pane) if *pane == id => {
+                *self = build(id);
+                true
+            }
+            Node::Pane(_) => false,
+            Node::Split { first, second, .. } => {
+                first.replace_pane(id, build) || second.replace_pane(id, build)
+            }
+        }
+    }
+
+    fn rename(&mut self, from: PaneId, to: PaneId) {
+        match self {
+            Node::Pane(pane) if *pane == from => *pane = to,
+            Node::Pane(_) => (),
+            Node::Split { first, second, .. } => {
+                first.rename(from, to);
+                second.rename(from, to);
+            }
+        }
+    }
+
+    // Adjusts the split directly above the pane. A positive delta makes
+    // the pane bigger.
+    fn resize(&mut self, id: PaneId, delta: f64) -> bool {
+        let Node::Split { ratio, first, second, .. } = self else {
+            return false;
+        };
+        let change: f64 = if matches!(**first, Node::Pane(p) if p == id) {
+            delta
+        } else if matches!(**second, Node::Pane(p) if p == id) {
+            -delta
+        } else {
+            return first.resize(id, delta) || second.resize(id, delta);
+        };
+        *ratio = (*ratio + change).clamp(0.05, 0.95);
+        true
+    }
+}
+
+
+// Takes the pane out and lets its sibling fill the space it leaves
+fn remove_pane(node: Node, id: PaneId) -> Option<Node> {
+    match node {
+        Node::Pane(pane) if pane == id => None,
+        Node::Pane(_) => Some(node),
+        Node::Split { direction, ratio, first, second } => {
+            match (remove_pane(*first, id), remove_pane(*second, id)) {
+                (Some(a), Some(b)) => Some(Node::Split {
+                    direction,
+                    ratio,
+                    first: Box::new(a),
+                    second: Box::new(b),
+                }),
+                (Some(only), None) | (None, Some(only)) => Some(only),
+                (None, None) => None,
+            }
+        }
+    }
+}
+
+
+#[derive(Debug, Clone)]
+pub struct TilingLayout {
+    screen: Rect,
+    gap: u32,
+    min_size: Rectangle,
+    root: Option<Node>,
+    next_id: u32,
+}
+
+impl TilingLayout {
+
+    pub fn new(screen: Rect, gap: u32, min_size: Rectangle) -> TilingLayout {
+        TilingLayout { screen, gap, min_size, root: None, next_id: 1 }
+    }
+
+    // Refuses a screen too small to give every pane its minimum size, the
+    // same as insert does
+    pub fn set_screen(&mut self, screen: Rect) -> Result<(), LayoutError> {
+        if let Some(root) = &self.root {
+            if !self.fits(root, screen) {
+                return Err(LayoutError::NoRoom);
+            }
+        }
+        self.screen = screen;
+        Ok(())
+    }
+
+    fn fits(&self, root: &Node, screen: Rect) -> bool {
+        let (w, h) = root.min_size(self.min_size, self.gap);
+        w <= screen.width as u64 && h <= screen.height as u64
+    }
+
+    // Splits `target` in two and puts a new pane in the second half. The
+    // very first pane fills the screen, so it takes no target.
+    pub fn insert(
+        &mut self,
+        target: Option<PaneId>,
+        direction: Direction
+    ) -> Result<PaneId, LayoutError> {
+        let id: PaneId = PaneId(self.next_id);
+        let root: Node = match (&self.root, target) {
+            (None, None) => Node::Pane(id),
+            (None, Some(target)) => {
+                return Err(LayoutError::UnknownPane(target));
+            }
+            (Some(root), Some(target)) => {
+                let mut root: Node = root.clone();
+                let split = |old: PaneId| Node::Split {
+                    direction,
+                    ratio: 0.5,
+                    first: Box::new(Node::Pane(old)),
+                    second: Box::new(Node::Pane(id)),
+                };
+                if !root.replace_pane(target, &split) {
+                    return Err(LayoutError::UnknownPane(target));
+                }
+                root
+            }
+            (Some(_), None) => return Err(LayoutError::MissingTarget),
+        };
+        if !self.fits(&root, self.screen) {
+            return Err(LayoutError::NoRoom);
+        }
+        self.root = Some(root);
+        self.next_id += 1;
+        Ok(id)
+    }
+
+    pub fn remove(&mut self, id: PaneId) -> Result<(), LayoutError> {
+        if !self.contains(id) {
+            return Err(LayoutError::UnknownPane(id));
+        }
+        self.root = self.root.take().and_then(|root| remove_pane(root, id));
+        Ok(())
+    }
+
+    pub fn swap(&mut self, a: PaneId, b: PaneId) -> Result<(), LayoutError> {
+        for id in [a, b] {
+            if !self.contains(id) {
+                return Err(LayoutError::UnknownPane(id));
+            }
+        }
+        if let Some(root) = &mut self.root {
+            // Go through an id that can't be in use so a and b don't clash
+            let temp: PaneId = PaneId(u32::MAX);
+            root.rename(a, temp);
+            root.rename(b, a);
+            root.rename(temp, b);
+        }
+        Ok(())
+    }
+
+    pub fn resize(&mut self, id: PaneId, delta: f64) -> Result<(), LayoutError> {
+        match &mut self.root {
+            Some(root) if root.contains(id) => {
+                if root.resize(id, delta) {
+                    Ok(())
+                } else {
+                    Err(LayoutError::NoSplit(id))
+                }
+            }
+            _ => Err(LayoutError::UnknownPane(id)),
+        }
+    }
+
+    pub fn contains(&self, id: PaneId) -> bool {
+        self.root.as_ref().is_some_and(|root| root.contains(id))
+    }
+
+    pub fn panes(&self) -> Vec<(PaneId, Rect)> {
+        self.arrange().0
+    }
+
+    // The panes, plus the strips of gap left between them
+    fn arrange(&self) -> (Vec<(PaneId, Rect)>, Vec<Rect>) {
+        let mut panes: Vec<(PaneId, Rect)> = Vec::new();
+        let mut gaps: Vec<Rect> = Vec::new();
+        if let Some(root) = &self.root {
+            root.layout(self.screen, self.min_size, self.gap, &mut panes,
+                &mut gaps);
+        }
+        (panes, gaps)
+    }
+
+    // Checks the promise made at the top of the file: panes and gaps never
+    // overlap, stay on the screen, and add up to exactly the screen's area.
+    pub fn verify(&self) -> Result<(), String> {
+        let (panes, gaps) = self.arrange();
+        if panes.is_empty() {
+            return Ok(());
+        }
+        let pieces: Vec<(String, Rect)> = panes.iter()
+            .map(|(id, r)| (id.to_string(), *r))
+            .chain(gaps.iter().map(|r| (String::from("gap"), *r)))
+            .collect();
+        for (i, (a, ra)) in pieces.iter().enumerate() {
+            if !self.screen.contains(ra) {
+                return Err(format!("{a} {ra:?} is off the screen"));
+            }
+            if let Some((b, _)) = pieces[i + 1..].iter()
+                .find(|(_, rb)| ra.overlaps(rb))
+            {
+                return Err(format!("{a} overlaps {b}"));
+            }
+        }
+        let covered: u64 = pieces.iter().map(|(_, r)| r.area()).sum();
+        if covered != self.screen.area() {
+            return Err(format!(
+                "{} of {} cells are covered",
+                covered,
+                self.screen.area()
+            ));
+        }
+        Ok(())
+    }
+
+    // Each pane drawn as a box with its id in the corner
+    pub fn to_ascii(&self) -> String {
+        let w: usize = self.screen.width as usize;
+        let h: usize = self.screen.height as usize;
+        let mut grid: Vec<Vec<char>> = vec![vec![' '; w]; h];
+        for (id, r) in self.panes() {
+            // A pane squeezed down to nothing has no box to draw
+            if r.is_empty() {
+                continue;
+            }
+            let left: usize = (r.x - self.screen.x) as usize;
+            let top: usize = (r.y - self.screen.y) as usize;
+            let right: usize = left + r.width as usize - 1;
+            let bottom: usize = top + r.height as usize - 1;
+            for row in grid.iter_mut().take(bottom + 1).skip(top) {
+                row[left] = '|';
+                row[right] = '|';
+            }
+            grid[top][left..=right].fill('-');
+            grid[bottom][left..=right].fill('-');
+            for (x, y) in [(left, top), (right, top), (left, bottom),
+                (right, bottom)]
+            {
+                grid[y][x] = '+';
+            }
+            for (i, ch) in id.to_string().chars().enumerate() {
+                if left + 2 + i < right && top < bottom {
+                    grid[top + 1][left + 2 + i] = ch;
+                }
+            }
+        }
+        grid.iter()
+            .map(|row| row.iter().collect::<String>())
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+}
+
+
+pub fn run() {
+    println!("\n  -- Tiling Layout --");
+    let screen: Rect = Rect::new(0, 0, 60, 16);
+    let min = Rectangle { width: 8, height: 4 };
+    let mut tiles: TilingLayout = TilingLayout::new(screen, 1, min);
+
+    let editor: PaneId = tiles.insert(None, Direction::Horizontal).unwrap();
+    let terminal: PaneId = tiles.insert(Some(editor), Direction::Horizontal)
+        .unwrap();
+    let logs: PaneId = tiles.insert(Some(terminal), Direction::Vertical)
+        .unwrap();
+    tiles.resize(editor, 0.15).unwrap();
+    println!("{}", tiles.to_ascii());
+    println!("verify: {:?}", tiles.verify());
+
+    tiles.swap(editor, logs).unwrap();
+    tiles.remove(terminal).unwrap();
+    println!("{}", tiles.to_ascii());
+    println!("verify: {:?}", tiles.verify());
+
+    // Keep splitting the same pane until it gets too small
+    let mut target: PaneId = editor;
+    loop {
+        match tiles.insert(Some(target), Direction::Vertical) {
+            Ok(id) => target = id,
+            Err(e) => {
+                println!("Split {target}: {e}");
+                break;
+            }
+        }
+    }
+    println!("{} panes, verify: {:?}", tiles.panes().len(), tiles.verify());
+}
+
+
+pub fn register_layouts(registry: &mut Registry) {
+    registry.register("Chapter 5", layout_of!(PaneId));
+    registry.register("Chapter 5", layout_of!(Direction));
+    registry.register("Chapter 5", layout_of!(LayoutError));
+    registry.register("Chapter 5", layout_of!(Node));
+    registry.register("Chapter 5", layout_of!(TilingLayout {
+        screen, gap, min_size, root, next_id
+    }));
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layout(width: u32, height: u32) -> TilingLayout {
+        let min = Rectangle { width: 4, height: 3 };
+        TilingLayout::new(Rect::new(0, 0, width, height), 1, min)
+    }
+
+    fn assert_valid(tiles: &TilingLayout) {
+        assert_eq!(tiles.verify(), Ok(()));
+        let drawing: String = tiles.to_ascii();
+        assert_eq!(drawing.lines().count(), tiles.screen.height as usize);
+    }
+
+    #[test]
+    fn insert_splits_and_stays_valid() {
+        let mut tiles: TilingLayout = layout(40, 12);
+        assert_eq!(
+            tiles.insert(Some(PaneId(9)), Direction::Vertical),
+            Err(LayoutError::UnknownPane(PaneId(9)))
+        );
+        assert_eq!(tiles.insert(None, Direction::Vertical), Ok(PaneId(1)));
+        assert_eq!(
+            tiles.insert(None, Direction::Vertical),
+            Err(LayoutError::MissingTarget)
+        );
+        assert_eq!(
+            tiles.insert(Some(PaneId(9)), Direction::Vertical),
+            Err(LayoutError::UnknownPane(PaneId(9)))
+        );
+        let mut target: PaneId = PaneId(1);
+        let mut direction: Direction = Direction::Horizontal;
+        while let Ok(id) = tiles.insert(Some(target), direction) {
+            assert_valid(&tiles);
+            target = id;
+            direction = match direction {
+                Direction::Horizontal => Direction::Vertical,
+                Direction::Vertical => Direction::Horizontal,
+            };
+        }
+        assert_eq!(
+            tiles.insert(Some(target), direction),
+            Err(LayoutError::NoRoom)
+        );
+        for (_, r) in tiles.panes() {
+            assert!(r.width >= 4 && r.height >= 3, "{r:?}");
+        }
+    }
+
+    #[test]
+    fn remove_and_swap() {
+        let mut tiles: TilingLayout = layout(30, 10);
+        let a: PaneId = tiles.insert(None, Direction::Horizontal).unwrap();
+        let b: PaneId = tiles.insert(Some(a), Direction::Horizontal).unwrap();
+        let c: PaneId = tiles.insert(Some(b), Direction::Vertical).unwrap();
+        let before: Vec<(PaneId, Rect)> = tiles.panes();
+
+        tiles.swap(a, c).unwrap();
+        assert_valid(&tiles);
+        let after: Vec<(PaneId, Rect)> = tiles.panes();
+        assert_eq!(after[0], (c, before[0].1));
+        assert_eq!(after[2], (a, before[2].1));
+        assert_eq!(
+            tiles.swap(a, PaneId(7)),
+            Err(LayoutError::UnknownPane(PaneId(7)))
+        );
+
+        tiles.remove(b).unwrap();
+        assert_valid(&tiles);
+        assert_eq!(tiles.panes().len(), 2);
+        assert_eq!(tiles.remove(b), Err(LayoutError::UnknownPane(b)));
+        tiles.remove(a).unwrap();
+        tiles.remove(c).unwrap();
+        assert!(tiles.panes().is_empty());
+        assert_eq!(tiles.to_ascii().trim(), "");
+    }
+
+    #[test]
+    fn resize_moves_the_split() {
+        let mut tiles: TilingLayout = layout(41, 10);
+        let a: PaneId = tiles.insert(None, Direction::Horizontal).unwrap();
+        assert_eq!(tiles.resize(a, 0.1), Err(LayoutError::NoSplit(a)));
+        let b: PaneId = tiles.insert(Some(a), Direction::Horizontal).unwrap();
+        assert_eq!(tiles.panes()[0].1.width, 20);
+
+        tiles.resize(a, 0.25).unwrap();
+        assert_valid(&tiles);
+        assert_eq!(tiles.panes()[0].1.width, 30);
+        tiles.resize(b, 0.25).unwrap();
+        assert_eq!(tiles.panes()[0].1.width, 20);
+
+        // Clamped, and never below the minimum size
+        tiles.resize(b, 5.0).unwrap();
+        assert_valid(&tiles);
+        assert_eq!(tiles.panes()[0].1.width, 4);
+        assert_eq!(tiles.resize(PaneId(5), 0.1),
+            Err(LayoutError::UnknownPane(PaneId(5))));
+    }
+
+    #[test]
+    fn shrinking_the_screen() {
+        let mut tiles: TilingLayout = layout(30, 10);
+        let a: PaneId = tiles.insert(None, Direction::Horizontal).unwrap();
+        let b: PaneId = tiles.insert(Some(a), Direction::Horizontal).unwrap();
+        let c: PaneId = tiles.insert(Some(b), Direction::Vertical).unwrap();
+        // Side by side needs 4 + 1 + 4 across, stacked 3 + 1 + 3 down
+        for (width, height) in [(12, 8), (9, 7), (30, 10)] {
+            let screen: Rect = Rect::new(3, 2, width, height);
+            assert_eq!(tiles.set_screen(screen), Ok(()));
+            assert_valid(&tiles);
+        }
+        for (width, height) in [(8, 7), (9, 6), (1, 1), (0, 0)] {
+            let screen: Rect = Rect::new(3, 2, width, height);
+            assert_eq!(tiles.set_screen(screen), Err(LayoutError::NoRoom));
+            assert_eq!(tiles.screen, Rect::new(3, 2, 30, 10));
+        }
+        tiles.remove(c).unwrap();
+        assert_eq!(tiles.set_screen(Rect::new(0, 0, 9, 3)), Ok(()));
+        assert_valid(&tiles);
+        tiles.remove(a).unwrap();
+        tiles.remove(b).unwrap();
+        assert_eq!(tiles.set_screen(Rect::new(0, 0, 0, 0)), Ok(()));
+        assert_valid(&tiles);
+
+        // With no minimum size, panes can end up with no area at all
+        let mut tiles: TilingLayout = TilingLayout::new(
+            Rect::new(0, 0, 3, 6),
+            1,
+            Rectangle { width: 0, height: 0 }
+        );
+        let mut target: PaneId = tiles.insert(None, Direction::Vertical)
+            .unwrap();
+        for _ in 0..4 {
+            target = tiles.insert(Some(target), Direction::Vertical).unwrap();
+            assert_valid(&tiles);
+        }
+        assert!(tiles.panes().iter().any(|(_, r)| r.is_empty()));
+    }
+}
@@ -0,0 +1,409 @@
+use std::fmt;
+
+use super::email::{EmailAddress, EmailError};
+use super::person::User;
+use crate::layout::{layout_of, Registry};
+
+
+/*
+* Building a User one field at a time. Nothing is checked until build(),
+* and then everything is checked at once, so a form with a blank name and a
+* bad email reports both problems instead of making the user fix them one
+* round trip at a time.
+*
+*   let user: User = User::builder()
+*       .name("D-Pad")
+*       .email("dpad@dpadllc.com")
+*       .build()?;
+*
+* to_builder() replaces `User { active: false, ..user }`. It copies the
+* fields out, so the original user is still there afterwards.
+*/
+#[derive(Debug, Clone, Default)]
+pub struct UserBuilder {
+    name: Option<String>,
+    email: Option<String>,
+    active: Option<bool>,
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FieldError {
+    MissingName,
+    BlankName,
+    NameTooLong(usize),
+    NameControlChar(char),
+    MissingEmail,
+    InvalidEmail(EmailError),
+}
+
+impl fmt::Display for FieldError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FieldError::MissingName => write!(f, "name is required"),
+            FieldError::BlankName => write!(f, "name is blank"),
+            FieldError::NameTooLong(len) => write!(
+                f,
+                "name is {len} characters, the limit is {MAX_NAME_CHARS}"
+            ),
+            FieldError::NameControlChar(ch) => {
+                write!(f, "name contains the control character {ch:?}")
+            }
+            FieldError::MissingEmail => write!(f, "email is required"),
+            FieldError::InvalidEmail(err) => write!(f, "email: {err}"),
+        }
+    }
+}
+
+
+// Every problem build() found, in field order. Never empty.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BuildError {
+    pub problems: Vec<FieldError>,
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let count: usize = self.problems.len();
+        let plural: &str = if count == 1 { "" } else { "s" };
+        write!(f, "invalid user ({count} problem{plural}): ")?;
+        for (i, problem) in self.problems.iter().enumerate() {
+            if i > 0 {
+                write!(f, "; ")?;
+            }
+            write!(f, "{problem}")?;
+        }
+        Ok(())
+    }
+}
+
+
+const MAX_NAME_CHARS: usize = 64;
+
+
+impl User {
+
+    pub fn builder() -> UserBuilder {
+        UserBuilder::default()
+    }
+
+    pub fn to_builder(&self) -> UserBuilder {
+        UserBuilder {
+            name: Some(self.name.clone()),
+            email: Some(self.email.to_string()),
+            active: Some(self.active),
+        }
+    }
+}
+
+
+impl UserBuilder {
+
+    pub fn name(mut self, name: &str) -> UserBuilder {
+        self.name = Some(name.to_string());
+        self
+    }
+
+    // Kept as text until build(), so a bad email is reported along with
+    // everything else rather than on its own.
+    pub fn email(mut self, email: &str) -> UserBuilder {
+        self.email = Some(email.to_string());
+        self
+    }
+
+    // New users are active unless told otherwise
+    pub fn active(mut self, active: bool) -> UserBuilder {
+        self.active = Some(active);
+        self
+    }
+
+    pub fn build(self) -> Result<User, BuildError> {
+        let mut problems: Vec<FieldError> = Vec::new();
+
+        let name: Option<String> = match &self.name {
+            Some(name) => check_name(name, &mut problems),
+            None => {
+                problems.push(FieldError::MissingName);
+                None
+            }
+        };
+        let email: Option<EmailAddress> = match &self.email {
+            Some(email) => match EmailAddress::parse(email.trim()) {
+                Ok(email) => Some(email),
+                Err(err) => {
+                    problems.push(FieldError::InvalidEmail(err));
+                    None
+                }
+            },
+            None => {
+                problems.push(FieldError::MissingEmail);
+                None
+            }
+        };
+
+        match (name, email) {
+            (Some(name), Some(email)) if problems.is_empty() => Ok(User {
+                active: self.active.unwrap_or(true),
+                name,
+                email,
+            }),
+            _ => Err(BuildError { problems }),
+        }
+    }
+}
+
+
+// Trims the name and records anything wrong with it. Returns the name to
+// use if it passed.
+fn check_name(name: &str, problems: &mut Vec<FieldError>) -> Option<String> {
+    let before: usize = problems.len();
+    let name: &str = name.trim();
+    if name.is_empty() {
+        problems.push(FieldError::BlankName);
+    }
+    let chars: usize = name.chars().count();
+    if chars > MAX_NAME_CHARS {
+        problems.push(FieldError::NameTooLong(chars));
+    }
+    if let Some(ch) = name.chars().find(|c| c.is_control()) {
+        problems.push(FieldError::NameControlChar(ch));
+    }
+    if problems.len() == before {
+        Some(name.to_string())
+    } else {
+        None
+    }
+}
+
+
+// The name checks on their own, for code that takes a name without going
+// through a builder. Gives back the trimmed name or the first problem.
+pub fn validate_name(name: &str) -> Result<String, FieldError> {
+    let mut problems: Vec<FieldError> = Vec::new();
+    check_name(name, &mut problems).ok_or_else(|| problems.remove(0))
+}
+
+
+/*
+* The same builder with the required fields tracked in the type. Name and
+* Email start out as Missing and become Set, holding the value, once their
+* setter is called. build() only exists once both are Set, so this doesn't
+* compile:
+*
+*   let user = TypedUserBuilder::new().name("D-Pad").build();
+*   //                                               ^^^^^ no method `build`
+*
+* The email setter takes an EmailAddress that has already been parsed, so
+* the only thing left for build() to check at run time is the name's text.
+*/
+pub struct Missing;
+pub struct Set<T>(T);
+
+pub struct TypedUserBuilder<Name, Email> {
+    name: Name,
+    email: Email,
+    active: bool,
+}
+
+impl TypedUserBuilder<Missing, Missing> {
+
+    pub fn new() -> TypedUserBuilder<Missing, Missing> {
+        TypedUserBuilder { name: Missing, email: Missing, active: true }
+    }
+}
+
+impl Default for TypedUserBuilder<Missing, Missing> {
+    fn default() -> Self {
+        TypedUserBuilder::new()
+    }
+}
+
+impl<Name, Email> TypedUserBuilder<Name, Email> {
+
+    pub fn name(self, name: &str) -> TypedUserBuilder<Set<String>, Email> {
+        TypedUserBuilder {
+            name: Set(name.to_string()),
+            email: self.email,
+            active: self.active,
+        }
+    }
+
+    pub fn email(self, email: EmailAddress)
+        -> TypedUserBuilder<Name, Set<EmailAddress>>
+    {
+        TypedUserBuilder {
+            name: self.name,
+            email: Set(email),
+            active: self.active,
+        }
+    }
+
+    pub fn active(mut self, active: bool) -> TypedUserBuilder<Name, Email> {
+        self.active = active;
+        self
+    }
+}
+
+impl TypedUserBuilder<Set<String>, Set<EmailAddress>> {
+
+    pub fn build(self) -> Result<User, BuildError> {
+        let mut problems: Vec<FieldError> = Vec::new();
+        match check_name(&self.name.0, &mut problems) {
+            Some(name) => Ok(User {
+                active: self.active,
+                name,
+                email: self.email.0,
+            }),
+            None => Err(BuildError { problems }),
+        }
+    }
+}
+
+
+pub fn run() {
+    println!("\nUser builder:");
+    let user: User = User::builder()
+        .name("D-Pad")
+        .email("dpad@DPadLLC.com")
+        .build()
+        .unwrap();
+    println!("  Built: {} <{}> active: {}", user.name, user.email, user.active);
+
+    // Instead of `User { active: false, ..user }`, which moves user
+    let inactive: User = user.to_builder().active(false).build().unwrap();
+    println!("  Copy: {} active: {}, original still active: {}",
+        inactive.name, inactive.active, user.active);
+
+    let attempts: [UserBuilder; 3] = [
+        User::builder(),
+        User::builder().name("   ").email("dpad@@dpadllc.com"),
+        User::builder().name("Tab\tName").email("alecia@dpadllc.com"),
+    ];
+    for builder in attempts {
+        if let Err(err) = builder.build() {
+            println!("  {err}");
+        }
+    }
+
+    let typed: User = TypedUserBuilder::new()
+        .email("alecia@dpadllc.com".parse().unwrap())
+        .name("Alecia")
+        .build()
+        .unwrap();
+    println!("  Typed: {} <{}>", typed.name, typed.email);
+}
+
+
+pub fn register_layouts(registry: &mut Registry) {
+    registry.register(
+        "Chapter 5",
+        layout_of!(UserBuilder { name, email, active }),
+    );
+    registry.register("Chapter 5", layout_of!(FieldError));
+    registry.register("Chapter 5", layout_of!(BuildError { problems }));
+    registry.register(
+        "Chapter 5",
+        layout_of!(TypedUserBuilder<Set<String>, Set<EmailAddress>> {
+            name, email, active
+        }),
+    );
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn problems(builder: UserBuilder) -> Vec<FieldError> {
+        builder.build().unwrap_err().problems
+    }
+
+    #[test]
+    fn builds_a_user() {
+        let user: User = User::builder()
+            .name("  D-Pad ")
+            .email(" dpad@DPadLLC.com ")
+            .build()
+            .unwrap();
+        assert_eq!(user.name, "D-Pad");
+        assert_eq!(user.email.to_string(), "dpad@dpadllc.com");
+        assert!(user.active);
+
+        let copy: User = user.to_builder().active(false).build().unwrap();
+        assert_eq!((copy.name.as_str(), copy.active), ("D-Pad", false));
+        assert_eq!(copy.email, user.email);
+    }
+
+    #[test]
+    fn build_reports_every_problem() {
+        assert_eq!(
+            problems(User::builder()),
+            [FieldError::MissingName, FieldError::MissingEmail]
+        );
+        assert_eq!(
+            problems(User::builder().name(" \t ").email("dpad@@x.com")),
+            [
+                FieldError::BlankName,
+                FieldError::InvalidEmail(EmailError::InvalidLocalChar('@')),
+            ]
+        );
+        let long: String = format!("{}\u{7}", "n".repeat(70));
+        assert_eq!(
+            problems(User::builder().name(&long)),
+            [
+                FieldError::NameTooLong(71),
+                FieldError::NameControlChar('\u{7}'),
+                FieldError::MissingEmail,
+            ]
+        );
+        assert_eq!(
+            problems(User::builder().email("a@x.com")),
+            [FieldError::MissingName]
+        );
+    }
+
+    #[test]
+    fn build_error_lists_problems() {
+        let err: BuildError = User::builder().build().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid user (2 problems): name is required; email is required"
+        );
+        let err: BuildError = User::builder().name("A").build().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid user (1 problem): email is required"
+        );
+    }
+
+    #[test]
+    fn typed_builder() {
+        let email: EmailAddress = "alecia@dpadllc.com".parse().unwrap();
+        let user: User = TypedUserBuilder::new()
+            .active(false)
+            .email(email.clone())
+            .name(" Alecia ")
+            .build()
+            .unwrap();
+        assert_eq!(user.name, "Alecia");
+        assert_eq!(user.email, email);
+        assert!(!user.active);
+
+        // Setting a field twice keeps the last value
+        let user: User = TypedUserBuilder::default()
+            .name("First")
+            .name("Second")
+            .email(email.clone())
+            .build()
+            .unwrap();
+        assert_eq!(user.name, "Second");
+        assert!(user.active);
+
+        let err: BuildError = TypedUserBuilder::new()
+            .name("")
+            .email(email)
+            .build()
+            .unwrap_err();
+        assert_eq!(err.problems, [FieldError::BlankName]);
+    }
+}
@@ -0,0 +1,575 @@
+use std::ffi::OsString;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process;
+
+use super::email::EmailAddress;
+use super::person::{build_new_user, User};
+use super::user_builder::{validate_name, FieldError};
+use crate::layout::{layout_of, Registry};
+
+
+/*
+* Keeps every User in one place, looked up by email. Emails are unique, so
+* adding a second account with the same address is an error rather than a
+* silent overwrite. Names go through the same checks as UserBuilder's.
+*
+* The file format is one user per line, tab separated:
+*
+*   active<TAB>name<TAB>email
+*
+* where active is 1 or 0. Backslashes, tabs, newlines and carriage returns
+* inside a field are escaped as \\, \t, \n and \r so every user stays on a
+* single line.
+*/
+#[derive(Debug, Default)]
+pub struct UserStore {
+    users: Vec<User>,
+}
+
+
+#[derive(Debug)]
+pub enum StoreError {
+    DuplicateEmail(EmailAddress),
+    UnknownUser(EmailAddress),
+    InvalidName(FieldError),
+    Io(io::Error),
+    Parse { line: usize, reason: String },
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StoreError::DuplicateEmail(email) => {
+                write!(f, "a user with email {email} already exists")
+            }
+            StoreError::UnknownUser(email) => {
+                write!(f, "no user with email {email}")
+            }
+            StoreError::InvalidName(err) => write!(f, "{err}"),
+            StoreError::Io(err) => write!(f, "{err}"),
+            StoreError::Parse { line, reason } => {
+                write!(f, "line {line}: {reason}")
+            }
+        }
+    }
+}
+
+impl From<io::Error> for StoreError {
+    fn from(err: io::Error) -> StoreError {
+        StoreError::Io(err)
+    }
+}
+
+
+impl UserStore {
+
+    pub fn new() -> UserStore {
+        UserStore { users: Vec::new() }
+    }
+
+    // A file that doesn't exist yet is just an empty store, so the first
+    // `notes users add` works without any setup.
+    pub fn load(path: &Path) -> Result<UserStore, StoreError> {
+        let text: String = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                return Ok(UserStore::new());
+            }
+            Err(err) => return Err(err.into()),
+        };
+        UserStore::parse(&text)
+    }
+
+    // Writes to a temporary file next to the real one and renames it over
+    // the top, so a crash halfway through can't leave half a store behind.
+    pub fn save(&self, path: &Path) -> Result<(), StoreError> {
+        let mut temp: OsString = path.as_os_str().to_owned();
+        temp.push(".tmp");
+        fs::write(&temp, self.to_text())?;
+        fs::rename(&temp, path)?;
+        Ok(())
+    }
+
+    pub fn parse(text: &str) -> Result<UserStore, StoreError> {
+        let mut store: UserStore = UserStore::new();
+        for (i, line) in text.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let parse_error = |reason: &str| StoreError::Parse {
+                line: i + 1,
+                reason: reason.to_string(),
+            };
+            let fields: Vec<&str> = line.split('\t').collect();
+            if fields.len() != 3 {
+                return Err(parse_error("expected active, name and email"));
+            }
+            let active: bool = match fields[0] {
+                "1" => true,
+                "0" => false,
+                _ => return Err(parse_error("active must be 1 or 0")),
+            };
+            let name: String = unescape(fields[1])
+                .ok_or_else(|| parse_error("bad escape in name"))?;
+            let email: EmailAddress = unescape(fields[2])
+                .ok_or_else(|| parse_error("bad escape in email"))?
+                .parse()
+                .map_err(|err| parse_error(&format!("{err}")))?;
+            store.add(User { active, name, email })
+                .map_err(|err| parse_error(&err.to_string()))?;
+        }
+        Ok(store)
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut out: String = String::new();
+        for user in &self.users {
+            let active: &str = if user.active { "1" } else { "0" };
+            out.push_str(&format!(
+                "{active}\t{}\t{}\n",
+                escape(&user.name),
+                escape(&user.email.to_string())
+            ));
+        }
+        out
+    }
+
+    // The name is stored trimmed
+    pub fn add(&mut self, mut user: User) -> Result<(), StoreError> {
+        user.name = validate_name(&user.name)
+            .map_err(StoreError::InvalidName)?;
+        if self.position(&user.email).is_some() {
+            return Err(StoreError::DuplicateEmail(user.email));
+        }
+        self.users.push(user);
+        Ok(())
+    }
+
+    pub fn get(&self, email: &EmailAddress) -> Option<&User> {
+        self.position(email).map(|i| &self.users[i])
+    }
+
+    // Changes a user's name, email or both. Moving to an email that another
+    // user already has is refused, the same as adding a duplicate.
+    pub fn update(
+        &mut self,
+        email: &EmailAddress,
+        name: Option<String>,
+        new_email: Option<EmailAddress>,
+    ) -> Result<&User, StoreError> {
+        let i: usize = self.index_of(email)?;
+        let name: Option<String> = name
+            .map(|name| validate_name(&name))
+            .transpose()
+            .map_err(StoreError::InvalidName)?;
+        if let Some(new_email) = &new_email {
+            if self.position(new_email).is_some_and(|j| j != i) {
+                return Err(StoreError::DuplicateEmail(new_email.clone()));
+            }
+        }
+        let user: &mut User = &mut self.users[i];
+        if let Some(name) = name {
+            user.name = name;
+        }
+        if let Some(new_email) = new_email {
+            user.email = new_email;
+        }
+        Ok(user)
+    }
+
+    pub fn deactivate(&mut self, email: &EmailAddress) -> Result<(), StoreError> {
+        let i: usize = self.index_of(email)?;
+        self.users[i].active = false;
+        Ok(())
+    }
+
+    pub fn delete(&mut self, email: &EmailAddress) -> Result<User, StoreError> {
+        let i: usize = self.index_of(email)?;
+        Ok(self.users.remove(i))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &User> {
+        self.users.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.users.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.users.is_empty()
+    }
+
+    fn position(&self, email: &EmailAddress) -> Option<usize> {
+        self.users.iter().position(|u| u.email == *email)
+    }
+
+    fn index_of(&self, email: &EmailAddress) -> Result<usize, StoreError> {
+        self.position(email)
+            .ok_or_else(|| StoreError::UnknownUser(email.clone()))
+    }
+}
+
+
+fn escape(field: &str) -> String {
+    let mut out: String = String::with_capacity(field.len());
+    for ch in field.chars() {
+        match ch {
+            '\\' => out.push_str("\\\\"),
+            '\t' => out.push_str("\\t"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            _ => out.push(ch),
+        }
+    }
+    out
+}
+
+fn unescape(field: &str) -> Option<String> {
+    let mut out: String = String::with_capacity(field.len());
+    let mut chars = field.chars();
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            out.push(ch);
+            continue;
+        }
+        match chars.next()? {
+            '\\' => out.push('\\'),
+            't' => out.push('\t'),
+            'n' => out.push('\n'),
+            'r' => out.push('\r'),
+            _ => return None,
+        }
+    }
+    Some(out)
+}
+
+
+/*
+* `notes users [--file PATH] <command>`
+*
+*   list
+*   add <email> <name...>
+*   get <email>
+*   update <email> [--name NAME] [--email EMAIL]
+*   deactivate <email>
+*   delete <email>
+*
+* The store lives in users.txt unless --file says otherwise, and is only
+* written back when a command changes something. --file has to come before
+* the command, so a name or email that happens to be "--file" is left alone.
+*/
+pub fn cli(args: &[String]) {
+    let (path, rest) = split_file_option(args)
+        .unwrap_or_else(|message| fail(message));
+    let rest: Vec<&str> = rest.iter().map(|s| s.as_str()).collect();
+
+    let path: &Path = Path::new(path);
+    let mut store: UserStore = UserStore::load(path)
+        .unwrap_or_else(|err| fail(&format!("{}: {err}", path.display())));
+
+    let changed: bool = match rest.as_slice() {
+        [] | ["list"] => {
+            if store.is_empty() {
+                println!("No users");
+            }
+            for user in store.iter() {
+                print_user(user);
+            }
+            false
+        }
+        ["add", email, name @ ..] if !name.is_empty() => {
+            let email: EmailAddress = parse_email(email);
+            let user: User = build_new_user(email.clone(), name.join(" "));
+            store.add(user).unwrap_or_else(|err| fail(&err.to_string()));
+            println!("Added {email}");
+            true
+        }
+        ["get", email] => {
+            let email: EmailAddress = parse_email(email);
+            match store.get(&email) {
+                Some(user) => print_user(user),
+                None => fail(&StoreError::UnknownUser(email).to_string()),
+            }
+            false
+        }
+        ["update", email, options @ ..] => {
+            let email: EmailAddress = parse_email(email);
+            let (name, new_email) = update_options(options);
+            match store.update(&email, name, new_email) {
+                Ok(user) => print_user(user),
+                Err(err) => fail(&err.to_string()),
+            }
+            true
+        }
+        ["deactivate", email] => {
+            let email: EmailAddress = parse_email(email);
+            store.deactivate(&email)
+                .unwrap_or_else(|err| fail(&err.to_string()));
+            println!("Deactivated {email}");
+            true
+        }
+        ["delete", email] => {
+            let user: User = store.delete(&parse_email(email))
+                .unwrap_or_else(|err| fail(&err.to_string()));
+            println!("Deleted {}", user.email);
+            true
+        }
+        _ => fail(
+            "usage: notes users [--file PATH] \
+            list | add EMAIL NAME | get EMAIL | \
+            update EMAIL [--name NAME] [--email EMAIL] | \
+            deactivate EMAIL | delete EMAIL"
+        ),
+    };
+
+    if changed {
+        store.save(path)
+            .unwrap_or_else(|err| fail(&format!("{}: {err}", path.display())));
+    }
+}
+
+// Splits a leading `--file PATH` off the arguments
+fn split_file_option(
+    args: &[String],
+) -> Result<(&str, &[String]), &'static str> {
+    match args {
+        [flag, path, rest @ ..] if flag == "--file" => Ok((path, rest)),
+        [flag] if flag == "--file" => Err("--file needs a path"),
+        _ => Ok(("users.txt", args)),
+    }
+}
+
+fn update_options(
+    options: &[&str],
+) -> (Option<String>, Option<EmailAddress>) {
+    let mut name: Option<String> = None;
+    let mut email: Option<EmailAddress> = None;
+    for pair in options.chunks(2) {
+        match pair {
+            ["--name", value] => name = Some(value.to_string()),
+            ["--email", value] => email = Some(parse_email(value)),
+            _ => fail("update takes --name NAME and/or --email EMAIL"),
+        }
+    }
+    if name.is_none() && email.is_none() {
+        fail("update needs --name or --email");
+    }
+    (name, email)
+}
+
+fn parse_email(text: &str) -> EmailAddress {
+    EmailAddress::parse(text)
+        .unwrap_or_else(|err| fail(&format!("{text}: {err}")))
+}
+
+fn print_user(user: &User) {
+    let status: &str = if user.active { "active" } else { "inactive" };
+    println!("{:<28} {:<20} {status}", user.email, user.name);
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("error: {message}");
+    process::exit(1);
+}
+
+
+pub fn run() {
+    println!("\nUser store:");
+    let mut store: UserStore = UserStore::new();
+    for (name, email) in [
+        ("D-Pad", "dpad@dpadllc.com"),
+        ("Alecia", "alecia@dpadllc.com"),
+    ] {
+        let email: EmailAddress = email.parse().unwrap();
+        let user: User = build_new_user(email, name.to_string());
+        store.add(user).unwrap();
+    }
+
+    let duplicate: User = build_new_user(
+        "dpad@DPadLLC.com".parse().unwrap(),
+        String::from("Impostor"),
+    );
+    if let Err(err) = store.add(duplicate) {
+        println!("  Add failed: {err}");
+    }
+    let nobody: EmailAddress = "nobody@dpadllc.com".parse().unwrap();
+    if let Err(err) = store.deactivate(&nobody) {
+        println!("  Deactivate failed: {err}");
+    }
+
+    let dpad: EmailAddress = "dpad@dpadllc.com".parse().unwrap();
+    let alecia: EmailAddress = "alecia@dpadllc.com".parse().unwrap();
+    store.deactivate(&dpad).unwrap();
+    store.update(&alecia, Some(String::from("Alecia B.")), None).unwrap();
+
+    let text: String = store.to_text();
+    println!("  Saved as:\n{}", text.trim_end());
+    let reloaded: UserStore = UserStore::parse(&text).unwrap();
+    println!("  Reloaded {} users, same data: {}",
+        reloaded.len(),
+        reloaded.iter().eq(store.iter()));
+}
+
+
+pub fn register_layouts(registry: &mut Registry) {
+    registry.register("Chapter 5", layout_of!(UserStore { users }));
+    registry.register("Chapter 5", layout_of!(StoreError));
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user(name: &str, email: &str) -> User {
+        build_new_user(email.parse().unwrap(), name.to_string())
+    }
+
+    fn email(text: &str) -> EmailAddress {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn escapes_round_trip() {
+        for field in ["plain", "tab\there", "new\nline", "cr\r\nlf",
+            "back\\slash", "\\t is not a tab", "trailing\r", ""]
+        {
+            let escaped: String = escape(field);
+            assert!(!escaped.contains(['\t', '\n', '\r']), "{escaped:?}");
+            assert_eq!(unescape(&escaped).as_deref(), Some(field));
+        }
+        assert_eq!(unescape("bad\\q"), None);
+        assert_eq!(unescape("dangling\\"), None);
+    }
+
+    #[test]
+    fn text_round_trip() {
+        let mut store: UserStore = UserStore::new();
+        store.add(user("Ada Lovelace", "ada@example.com")).unwrap();
+        store.add(user("Back \\t slash", "tab@example.com")).unwrap();
+        store.deactivate(&email("tab@example.com")).unwrap();
+        let text: String = store.to_text();
+        assert_eq!(text.lines().count(), 2);
+        let reloaded: UserStore = UserStore::parse(&text).unwrap();
+        assert!(reloaded.iter().eq(store.iter()));
+    }
+
+    #[test]
+    fn parse_errors_name_the_line() {
+        for (text, line) in [
+            ("1\tA\ta@example.com\n2\tB\tb@example.com", 2),
+            ("\n1\tonly two", 2),
+            ("1\tA\\x\ta@example.com", 1),
+            ("1\tA\tnot an email", 1),
+        ] {
+            match UserStore::parse(text) {
+                Err(StoreError::Parse { line: found, .. }) => {
+                    assert_eq!(found, line, "{text:?}");
+                }
+                other => panic!("{text:?} gave {other:?}"),
+            }
+        }
+        let twice: &str = "1\tA\ta@example.com\n0\tB\ta@EXAMPLE.com\n";
+        assert!(matches!(
+            UserStore::parse(twice),
+            Err(StoreError::Parse { line: 2, .. })
+        ));
+        let control: &str = "1\tA\ta@example.com\n1\tB\\tC\tb@example.com\n";
+        assert!(matches!(
+            UserStore::parse(control),
+            Err(StoreError::Parse { line: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn names_are_checked() {
+        let mut store: UserStore = UserStore::new();
+        assert!(matches!(
+            store.add(user("Tab\there", "a@example.com")),
+            Err(StoreError::InvalidName(FieldError::NameControlChar('\t')))
+        ));
+        assert!(matches!(
+            store.add(user("   ", "a@example.com")),
+            Err(StoreError::InvalidName(FieldError::BlankName))
+        ));
+        store.add(user("  Ann  ", "a@example.com")).unwrap();
+        let ann: EmailAddress = email("a@example.com");
+        assert_eq!(store.get(&ann).unwrap().name, "Ann");
+        assert!(matches!(
+            store.update(&ann, Some(String::from("A\nB")), None),
+            Err(StoreError::InvalidName(_))
+        ));
+        assert_eq!(store.get(&ann).unwrap().name, "Ann");
+    }
+
+    #[test]
+    fn file_option_comes_first() {
+        let args = |text: &str| -> Vec<String> {
+            text.split(' ').map(String::from).collect()
+        };
+        let given: Vec<String> = args("--file a.txt list");
+        assert_eq!(
+            split_file_option(&given),
+            Ok(("a.txt", &given[2..]))
+        );
+        let given: Vec<String> = args("add x@example.com --file");
+        assert_eq!(split_file_option(&given), Ok(("users.txt", &given[..])));
+        let given: Vec<String> = args("--file");
+        assert!(split_file_option(&given).is_err());
+    }
+
+    #[test]
+    fn duplicate_emails_are_refused() {
+        let mut store: UserStore = UserStore::new();
+        store.add(user("A", "a@example.com")).unwrap();
+        store.add(user("B", "b@example.com")).unwrap();
+        assert!(matches!(
+            store.add(user("Again", "a@EXAMPLE.COM")),
+            Err(StoreError::DuplicateEmail(_))
+        ));
+        assert!(matches!(
+            store.update(&email("b@example.com"), None,
+                Some(email("a@example.com"))),
+            Err(StoreError::DuplicateEmail(_))
+        ));
+        // Keeping your own email isn't a clash
+        store.update(&email("a@example.com"), Some(String::from("Ann")),
+            Some(email("a@example.com"))).unwrap();
+        assert_eq!(store.len(), 2);
+        assert_eq!(store.get(&email("a@example.com")).unwrap().name, "Ann");
+    }
+
+    #[test]
+    fn unknown_users_are_errors() {
+        let mut store: UserStore = UserStore::new();
+        store.add(user("A", "a@example.com")).unwrap();
+        let nobody: EmailAddress = email("nobody@example.com");
+        assert!(store.get(&nobody).is_none());
+        let unknown = |result: Result<(), StoreError>| {
+            matches!(result, Err(StoreError::UnknownUser(e)) if e == nobody)
+        };
+        assert!(unknown(store.deactivate(&nobody)));
+        assert!(unknown(store.delete(&nobody).map(|_| ())));
+        assert!(unknown(store.update(&nobody, None, None).map(|_| ())));
+        assert_eq!(store.len(), 1);
+    }
+
+    #[test]
+    fn save_and_load() {
+        let path = std::env::temp_dir()
+            .join(format!("notes-users-{}.txt", process::id()));
+        assert!(UserStore::load(&path).unwrap().is_empty());
+        let mut store: UserStore = UserStore::new();
+        store.add(user("A", "a@example.com")).unwrap();
+        store.save(&path).unwrap();
+        store.add(user("B", "b@example.com")).unwrap();
+        store.save(&path).unwrap();
+        let loaded: UserStore = UserStore::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(loaded.iter().eq(store.iter()));
+    }
+}
@@ -0,0 +1,524 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use super::msg::{Message, State};
+use crate::layout::{layout_of, Registry};
+
+
+/*
+* A turtle that draws what the messages tell it to. The pen is always
+* down, so every Move draws a line from the old cursor to the new one in
+* the current color, and Write stamps its text at the cursor using a small
+* bitmap font. Coordinates are pixels with (0, 0) in the top left corner
+* and y growing downwards, the same as the image files.
+*
+* The cursor, color and running flag are the msg::State the messages
+* already update. The canvas only adds the pixels, plus the same drawing
+* kept as SVG elements, so both exports show the same picture.
+*/
+pub struct Canvas {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+    svg: Vec<String>,
+    state: State,
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CanvasError {
+    Empty { width: u32, height: u32 },
+    TooBig { width: u32, height: u32 },
+}
+
+impl fmt::Display for CanvasError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CanvasError::Empty { width, height } => {
+                write!(f, "a {width}x{height} canvas has no pixels")
+            }
+            CanvasError::TooBig { width, height } => {
+                write!(f, "a {width}x{height} canvas doesn't fit in memory")
+            }
+        }
+    }
+}
+
+
+const BACKGROUND: (u8, u8, u8) = (255, 255, 255);
+
+// Each glyph is 5 pixels wide and 7 tall, one byte per row with the
+// leftmost pixel in bit 4. Text advances 6 pixels per character and 8 per
+// line. Lowercase letters use the capitals, and anything else missing
+// gets an empty box.
+const GLYPH_WIDTH: i32 = 5;
+const GLYPH_HEIGHT: i32 = 7;
+const ADVANCE: i32 = 6;
+const LINE_HEIGHT: i32 = 8;
+const MISSING: [u8; 7] = [0x1F, 0x11, 0x11, 0x11, 0x11, 0x11, 0x1F];
+
+const FONT: [(char, [u8; 7]); 60] = [
+    (' ', [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]),
+    ('!', [0x04, 0x04, 0x04, 0x04, 0x04, 0x00, 0x04]),
+    ('"', [0x0A, 0x0A, 0x0A, 0x00, 0x00, 0x00, 0x00]),
+    ('#', [0x0A, 0x0A, 0x1F, 0x0A, 0x1F, 0x0A, 0x0A]),
+    ('\'', [0x04, 0x04, 0x08, 0x00, 0x00, 0x00, 0x00]),
+    ('(', [0x02, 0x04, 0x08, 0x08, 0x08, 0x04, 0x02]),
+    (')', [0x08, 0x04, 0x02, 0x02, 0x02, 0x04, 0x08]),
+    ('*', [0x00, 0x04, 0x15, 0x0E, 0x15, 0x04, 0x00]),
+    ('+', [0x00, 0x04, 0x04, 0x1F, 0x04, 0x04, 0x00]),
+    (',', [0x00, 0x00, 0x00, 0x00, 0x0C, 0x04, 0x08]),
+    ('-', [0x00, 0x00, 0x00, 0x1F, 0x00, 0x00, 0x00]),
+    ('.', [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C]),
+    ('/', [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00]),
+    ('0', [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E]),
+    ('1', [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E]),
+    ('2', [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F]),
+    ('3', [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E]),
+    ('4', [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02]),
+    ('5', [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E]),
+    ('6', [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E]),
+    ('7', [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08]),
+    ('8', [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E]),
+    ('9', [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C]),
+    (':', [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x0C, 0x00]),
+    (';', [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x04, 0x08]),
+    ('<', [0x02, 0x04, 0x08, 0x10, 0x08, 0x04, 0x02]),
+    ('=', [0x00, 0x00, 0x1F, 0x00, 0x1F, 0x00, 0x00]),
+    ('>', [0x08, 0x04, 0x02, 0x01, 0x02, 0x04, 0x08]),
+    ('?', [0x0E, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04]),
+    ('@', [0x0E, 0x11, 0x01, 0x0D, 0x15, 0x15, 0x0E]),
+    ('A', [0x0E, 0x11, 0x11, 0x11, 0x1F, 0x11, 0x11]),
+    ('B', [0x1E, 0x11, 0x11, 0x1E, 0x11, 0x11, 0x1E]),
+    ('C', [0x0E, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0E]),
+    ('D', [0x1C, 0x12, 0x11, 0x11, 0x11, 0x12, 0x1C]),
+    ('E', [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x1F]),
+    ('F', [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x10]),
+    ('G', [0x0E, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0F]),
+    ('H', [0x11, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11]),
+    ('I', [0x0E, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E]),
+    ('J', [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0C]),
+    ('K', [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11]),
+    ('L', [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1F]),
+    ('M', [0x11, 0x1B, 0x15, 0x15, 0x11, 0x11, 0x11]),
+    ('N', [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11]),
+    ('O', [0x0E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E]),
+    ('P', [0x1E, 0x11, 0x11, 0x1E, 0x10, 0x10, 0x10]),
+    ('Q', [0x0E, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0D]),
+    ('R', [0x1E, 0x11, 0x11, 0x1E, 0x14, 0x12, 0x11]),
+    ('S', [0x0F, 0x10, 0x10, 0x0E, 0x01, 0x01, 0x1E]),
+    ('T', [0x1F, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04]),
+    ('U', [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E]),
+    ('V', [0x11, 0x11, 0x11, 0x11, 0x11, 0x0A, 0x04]),
+    ('W', [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0A]),
+    ('X', [0x11, 0x11, 0x0A, 0x04, 0x0A, 0x11, 0x11]),
+    ('Y', [0x11, 0x11, 0x11, 0x0A, 0x04, 0x04, 0x04]),
+    ('Z', [0x1F, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1F]),
+    ('[', [0x0E, 0x08, 0x08, 0x08, 0x08, 0x08, 0x0E]),
+    (']', [0x0E, 0x02, 0x02, 0x02, 0x02, 0x02, 0x0E]),
+    ('_', [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1F]),
+    ('~', [0x00, 0x00, 0x08, 0x15, 0x02, 0x00, 0x00]),
+];
+
+fn glyph(ch: char) -> [u8; 7] {
+    let ch: char = ch.to_ascii_uppercase();
+    FONT.iter()
+        .find(|(c, _)| *c == ch)
+        .map(|(_, rows)| *rows)
+        .unwrap_or(MISSING)
+}
+
+
+impl Canvas {
+
+    pub fn new(width: u32, height: u32) -> Result<Canvas, CanvasError> {
+        if width == 0 || height == 0 {
+            return Err(CanvasError::Empty { width, height });
+        }
+        let count: usize = (width as usize)
+            .checked_mul(height as usize)
+            .filter(|count| count.checked_mul(3).is_some())
+            .ok_or(CanvasError::TooBig { width, height })?;
+        let (r, g, b) = BACKGROUND;
+        Ok(Canvas {
+            width,
+            height,
+            pixels: [r, g, b].repeat(count),
+            svg: Vec::new(),
+            state: State::new(),
+        })
+    }
+
+    pub fn state(&self) -> &State {
+        &self.state
+    }
+
+    // Draws whatever the message draws, then lets it update the state
+    pub fn apply(&mut self, message: &Message) {
+        if !self.state.running {
+            return;
+        }
+        let (x, y) = self.state.cursor;
+        match message {
+            Message::Move { x: dx, y: dy } => {
+                let to: (i32, i32) =
+                    (x.saturating_add(*dx), y.saturating_add(*dy));
+                self.line((x, y), to);
+            }
+            Message::Write(text) => self.text((x, y), text),
+            Message::ChangeColor(..) | Message::Quit => {}
+        }
+        message.call(&mut self.state);
+    }
+
+    pub fn run(&mut self, messages: &[Message]) {
+        for message in messages {
+            self.apply(message);
+        }
+    }
+
+    fn set(&mut self, x: i64, y: i64) {
+        let inside: bool = (0..self.width as i64).contains(&x)
+            && (0..self.height as i64).contains(&y);
+        if !inside {
+            return;
+        }
+        let i: usize = (y as usize * self.width as usize + x as usize) * 3;
+        let (r, g, b) = self.state.color;
+        self.pixels[i..i + 3].copy_from_slice(&[r, g, b]);
+    }
+
+    /*
+    * Bresenham's line, both ends included. Rather than stepping from one
+    * end to the other, pixel k along the longer axis is worked out
+    * directly: it sits floor((2kb + a) / 2a) pixels along the shorter axis,
+    * where a and b are the line's lengths along the two axes. That picks
+    * the same pixels stepping would, and lets the loop cover only the k
+    * that land on the canvas, so a line out to (i32::MAX, 0) costs no more
+    * than one across it. Works on i64 (i128 for the product) so lines
+    * between far-off points can't overflow.
+    */
+    fn line(&mut self, from: (i32, i32), to: (i32, i32)) {
+        let (x0, y0) = (from.0 as i64, from.1 as i64);
+        let (dx, dy) = (to.0 as i64 - x0, to.1 as i64 - y0);
+        let (sx, sy) = (dx.signum(), dy.signum());
+        let x_major: bool = dx.abs() >= dy.abs();
+        let (a, b, start, step, size) = if x_major {
+            (dx.abs(), dy.abs(), x0, sx, self.width as i64)
+        } else {
+            (dy.abs(), dx.abs(), y0, sy, self.height as i64)
+        };
+        let first: i64 = match step {
+            1 => (-start).max(0),
+            -1 => (start - size + 1).max(0),
+            _ => 0,
+        };
+        let last: i64 = match step {
+            1 => (size - 1 - start).min(a),
+            -1 => start.min(a),
+            _ if (0..size).contains(&start) => 0,
+            _ => -1,
+        };
+        for k in first..=last {
+            let minor: i64 = if a == 0 {
+                0
+            } else {
+                ((2 * k as i128 * b as i128 + a as i128) / (2 * a as i128))
+                    as i64
+            };
+            if x_major {
+                self.set(x0 + sx * k, y0 + sy * minor);
+            } else {
+                self.set(x0 + sx * minor, y0 + sy * k);
+            }
+        }
+
+        // Pixel centers are at +0.5, which keeps the SVG strokes on the
+        // same pixels as the bitmap.
+        let svg: String = if from == to {
+            format!(
+                r#"<rect x="{}" y="{}" width="1" height="1" fill="{}"/>"#,
+                from.0, from.1, self.hex_color()
+            )
+        } else {
+            format!(
+                concat!(
+                    r#"<line x1="{}.5" y1="{}.5" x2="{}.5" y2="{}.5" "#,
+                    r#"stroke="{}" stroke-linecap="square"/>"#
+                ),
+                from.0, from.1, to.0, to.1, self.hex_color()
+            )
+        };
+        self.svg.push(svg);
+    }
+
+    // Stamps the glyphs with their top left corner at `at`. In the SVG the
+    // lit pixels become one path of unit squares.
+    fn text(&mut self, at: (i32, i32), text: &str) {
+        let mut path: String = String::new();
+        let (mut left, mut top): (i64, i64) = (at.0 as i64, at.1 as i64);
+        for ch in text.chars() {
+            if ch == '\n' {
+                left = at.0 as i64;
+                top += LINE_HEIGHT as i64;
+                continue;
+            }
+            let rows: [u8; 7] = glyph(ch);
+            for (row, bits) in (0..GLYPH_HEIGHT).zip(rows) {
+                for col in 0..GLYPH_WIDTH {
+                    if bits & (0x10 >> col) == 0 {
+                        continue;
+                    }
+                    let (x, y) = (left + col as i64, top + row as i64);
+                    self.set(x, y);
+                    path.push_str(&format!("M{x} {y}h1v1h-1z"));
+                }
+            }
+            left += ADVANCE as i64;
+        }
+        if !path.is_empty() {
+            self.svg.push(format!(
+                r#"<path fill="{}" d="{path}"/>"#,
+                self.hex_color()
+            ));
+        }
+    }
+
+    fn hex_color(&self) -> String {
+        let (r, g, b) = self.state.color;
+        format!("#{r:02x}{g:02x}{b:02x}")
+    }
+
+    // Binary PPM: a short text header, then three bytes per pixel
+    pub fn to_ppm(&self) -> Vec<u8> {
+        let mut out: Vec<u8> =
+            format!("P6\n{} {}\n255\n", self.width, self.height).into_bytes();
+        out.extend_from_slice(&self.pixels);
+        out
+    }
+
+    pub fn to_svg(&self) -> String {
+        let (w, h) = (self.width, self.height);
+        let (r, g, b) = BACKGROUND;
+        let mut out: String = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" \
+            height=\"{h}\" viewBox=\"0 0 {w} {h}\" \
+            shape-rendering=\"crispEdges\">\n"
+        );
+        out.push_str(&format!(
+            "<rect width=\"{w}\" height=\"{h}\" \
+            fill=\"#{r:02x}{g:02x}{b:02x}\"/>\n"
+        ));
+        for element in &self.svg {
+            out.push_str(element);
+            out.push('\n');
+        }
+        out.push_str("</svg>\n");
+        out
+    }
+
+    // Picks the format from the extension: .ppm or .svg
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("ppm") => fs::write(path, self.to_ppm()),
+            Some("svg") => fs::write(path, self.to_svg()),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "image file should end in .ppm or .svg",
+            )),
+        }
+    }
+
+    // A rough text preview, one character per pixel
+    pub fn to_ascii(&self) -> String {
+        let mut out: String = String::new();
+        for row in self.pixels.chunks(self.width as usize * 3) {
+            for pixel in row.chunks(3) {
+                let (r, g, b) = BACKGROUND;
+                out.push(if pixel == [r, g, b] { '.' } else { '#' });
+            }
+            out.push('\n');
+        }
+        out
+    }
+}
+
+
+pub const DEMO_SCRIPT: &str = "# a box with a label
+move 2 2
+color 200 0 0
+move 27 0
+move 0 13
+move -27 0
+move 0 -13
+color 0 0 160
+move 3 3
+write Hi!
+quit
+";
+
+
+pub fn run() {
+    println!("\nCanvas:");
+    let messages: Vec<Message> = super::msg::parse_script(DEMO_SCRIPT).unwrap();
+    let mut canvas: Canvas = Canvas::new(32, 18).unwrap();
+    canvas.run(&messages);
+    for line in canvas.to_ascii().lines() {
+        println!("  {line}");
+    }
+    println!("  PPM is {} bytes, SVG has {} elements",
+        canvas.to_ppm().len(), canvas.svg.len());
+}
+
+
+pub fn register_layouts(registry: &mut Registry) {
+    registry.register("Chapter 6", layout_of!(Canvas {
+        width, height, pixels, svg, state
+    }));
+    registry.register("Chapter 6", layout_of!(CanvasError));
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::the_book::chapter_6::msg::parse_script;
+
+    fn draw(script: &str, width: u32, height: u32) -> Canvas {
+        let mut canvas: Canvas = Canvas::new(width, height).unwrap();
+        canvas.run(&parse_script(script).unwrap());
+        canvas
+    }
+
+    // The golden files were checked by eye (the PPM in an image viewer).
+    // If the drawing code changes on purpose, regenerate them with
+    // Canvas::save and look at them again before committing.
+    #[test]
+    fn demo_matches_golden_ppm() {
+        let canvas: Canvas = draw(DEMO_SCRIPT, 32, 18);
+        assert_eq!(canvas.to_ppm(), include_bytes!("golden/demo.ppm"));
+    }
+
+    #[test]
+    fn demo_matches_golden_svg() {
+        let canvas: Canvas = draw(DEMO_SCRIPT, 32, 18);
+        assert_eq!(canvas.to_svg(), include_str!("golden/demo.svg"));
+    }
+
+    #[test]
+    fn lines_include_both_ends() {
+        let canvas: Canvas = draw("move 4 2\n", 6, 4);
+        assert_eq!(
+            canvas.to_ascii(),
+            "#.....\n\
+             .##...\n\
+             ...##.\n\
+             ......\n"
+        );
+    }
+
+    #[test]
+    fn text_uses_the_bitmap_font() {
+        let canvas: Canvas = draw("write i1\n", 12, 7);
+        assert_eq!(
+            canvas.to_ascii(),
+            ".###....#...\n\
+             ..#....##...\n\
+             ..#.....#...\n\
+             ..#.....#...\n\
+             ..#.....#...\n\
+             ..#.....#...\n\
+             .###...###..\n"
+        );
+    }
+
+    #[test]
+    fn drawing_off_the_edge_is_clipped() {
+        let canvas: Canvas = draw("move -5 -5\nmove 20 20\n", 4, 4);
+        assert_eq!(canvas.to_ascii(), "#...\n.#..\n..#.\n...#\n");
+        assert_eq!(canvas.state().cursor, (15, 15));
+    }
+
+    #[test]
+    fn nothing_is_drawn_after_quit() {
+        let canvas: Canvas = draw("quit\nmove 3 0\nwrite x\n", 4, 2);
+        assert_eq!(canvas.to_ascii(), "....\n....\n");
+        assert_eq!(canvas.to_svg().lines().count(), 3);
+    }
+
+    #[test]
+    fn colors_reach_both_formats() {
+        let canvas: Canvas = draw("color 255 0 0\nmove 1 0\n", 2, 1);
+        assert_eq!(canvas.to_ppm(), b"P6\n2 1\n255\n\xff\x00\x00\xff\x00\x00");
+        assert!(canvas.to_svg().contains(r##"stroke="#ff0000""##));
+    }
+
+    #[test]
+    fn canvas_size_must_fit() {
+        assert_eq!(
+            Canvas::new(0, 5).err(),
+            Some(CanvasError::Empty { width: 0, height: 5 })
+        );
+        assert_eq!(
+            Canvas::new(u32::MAX, u32::MAX).err(),
+            Some(CanvasError::TooBig { width: u32::MAX, height: u32::MAX })
+        );
+    }
+
+    // The old loop, stepping one pixel at a time with no clipping
+    fn stepped(from: (i64, i64), to: (i64, i64)) -> Vec<(i64, i64)> {
+        let ((mut x, mut y), (x1, y1)) = (from, to);
+        let (dx, dy): (i64, i64) = ((x1 - x).abs(), -(y1 - y).abs());
+        let (sx, sy): (i64, i64) = ((x1 - x).signum(), (y1 - y).signum());
+        let mut err: i64 = dx + dy;
+        let mut pixels: Vec<(i64, i64)> = vec![(x, y)];
+        while (x, y) != (x1, y1) {
+            let e2: i64 = 2 * err;
+            if e2 >= dy {
+                err += dy;
+                x += sx;
+            }
+            if e2 <= dx {
+                err += dx;
+                y += sy;
+            }
+            pixels.push((x, y));
+        }
+        pixels
+    }
+
+    #[test]
+    fn lines_match_stepping() {
+        for (x0, y0, x1, y1) in (0..9_i32.pow(4)).map(|i| {
+            (i % 9 - 4, i / 9 % 9 - 2, i / 81 % 9 - 4, i / 729 - 2)
+        }) {
+            let mut canvas: Canvas = Canvas::new(3, 5).unwrap();
+            canvas.line((x0, y0), (x1, y1));
+            let mut expected: Canvas = Canvas::new(3, 5).unwrap();
+            let (from, to) = ((x0 as i64, y0 as i64), (x1 as i64, y1 as i64));
+            for (x, y) in stepped(from, to) {
+                expected.set(x, y);
+            }
+            assert_eq!(
+                canvas.to_ascii(),
+                expected.to_ascii(),
+                "({x0}, {y0}) to ({x1}, {y1})"
+            );
+        }
+    }
+
+    #[test]
+    fn far_off_points_are_cheap() {
+        let canvas: Canvas = draw(
+            "move 2147483647 0\nmove -2147483647 2147483647\n\
+            write far away\n",
+            4,
+            3,
+        );
+        assert_eq!(canvas.to_ascii(), "####\n....\n....\n");
+        assert_eq!(canvas.state().cursor, (0, 2147483647));
+        assert!(canvas.to_svg().contains("M0 2147483647h1v1h-1z"));
+    }
+}
@@ -0,0 +1,643 @@
+use std::fmt;
+use std::process;
+use std::str::FromStr;
+
+use super::ip_addr::{IpAddr, ParseError};
+use crate::layout::{layout_of, Registry};
+
+
+/*
+* A block of addresses written as network/prefix, like 10.0.0.0/8 or
+* 2001:db8::/32. The prefix is how many leading bits every address in the
+* block shares. The rest are host bits.
+*
+* All the math happens on the address as a u128 (IpAddr::to_bits), so IPv4
+* and IPv6 share one implementation and only differ in their bit width.
+* The network address is stored with its host bits cleared.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Cidr {
+    network: IpAddr,
+    prefix: u8,
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CidrError {
+    Address(ParseError),
+    InvalidPrefix(String),
+    PrefixTooLong { prefix: u8, max: u32 },
+    SubnetTooLarge { prefix: u8, new_prefix: u8 },
+}
+
+impl fmt::Display for CidrError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CidrError::Address(err) => write!(f, "{err}"),
+            CidrError::InvalidPrefix(text) => {
+                write!(f, "{text:?} is not a prefix length")
+            }
+            CidrError::PrefixTooLong { prefix, max } => {
+                write!(f, "prefix /{prefix} is longer than /{max}")
+            }
+            CidrError::SubnetTooLarge { prefix, new_prefix } => write!(
+                f,
+                "a /{prefix} can't be split into /{new_prefix}s, they're \
+                bigger than it is"
+            ),
+        }
+    }
+}
+
+impl From<ParseError> for CidrError {
+    fn from(err: ParseError) -> CidrError {
+        CidrError::Address(err)
+    }
+}
+
+
+// Every bit of an address of this width set
+fn all_ones(width: u32) -> u128 {
+    if width == 128 {
+        u128::MAX
+    } else {
+        (1 << width) - 1
+    }
+}
+
+// The host bits of a prefix, e.g. 0.0.0.255 for a /24
+fn host_bits(prefix: u8, width: u32) -> u128 {
+    if prefix as u32 >= width {
+        0
+    } else {
+        all_ones(width) >> prefix
+    }
+}
+
+
+impl Cidr {
+
+    // Any address in the block will do. Its host bits are cleared, so
+    // 192.168.1.77/24 becomes 192.168.1.0/24.
+    pub fn new(address: IpAddr, prefix: u8) -> Result<Cidr, CidrError> {
+        let max: u32 = address.bit_len();
+        if prefix as u32 > max {
+            return Err(CidrError::PrefixTooLong { prefix, max });
+        }
+        let bits: u128 = address.to_bits() & !host_bits(prefix, max);
+        Ok(Cidr {
+            network: address.with_bits(bits),
+            prefix,
+        })
+    }
+
+    // A block holding only this address: a /32 or a /128
+    pub fn host(address: IpAddr) -> Cidr {
+        Cidr {
+            network: address,
+            prefix: address.bit_len() as u8,
+        }
+    }
+
+    // "10.0.0.0/8", or a bare address for a single host
+    pub fn parse(text: &str) -> Result<Cidr, CidrError> {
+        Cidr::parse_with_address(text).map(|(_, cidr)| cidr)
+    }
+
+    // Like parse, but also hands back the address as written, host bits
+    // and all, which the calculator report shows.
+    pub fn parse_with_address(
+        text: &str,
+    ) -> Result<(IpAddr, Cidr), CidrError> {
+        let (address, prefix) = match text.split_once('/') {
+            Some((address, prefix)) => (address, Some(prefix)),
+            None => (text, None),
+        };
+        let address: IpAddr = IpAddr::parse(address.trim())?;
+        let cidr: Cidr = match prefix {
+            None => Cidr::host(address),
+            Some(text) => {
+                // u8's parser takes "+8" too, so check for digits first
+                let digits: &str = text.trim();
+                let bad = || CidrError::InvalidPrefix(text.to_string());
+                if digits.is_empty()
+                    || !digits.bytes().all(|b| b.is_ascii_digit())
+                {
+                    return Err(bad());
+                }
+                let prefix: u8 = digits.parse().map_err(|_| bad())?;
+                Cidr::new(address, prefix)?
+            }
+        };
+        Ok((address, cidr))
+    }
+
+    pub fn network(&self) -> IpAddr {
+        self.network
+    }
+
+    pub fn prefix(&self) -> u8 {
+        self.prefix
+    }
+
+    fn width(&self) -> u32 {
+        self.network.bit_len()
+    }
+
+    fn start(&self) -> u128 {
+        self.network.to_bits()
+    }
+
+    fn end(&self) -> u128 {
+        self.start() | host_bits(self.prefix, self.width())
+    }
+
+    pub fn netmask(&self) -> IpAddr {
+        let width: u32 = self.width();
+        let mask: u128 = all_ones(width) & !host_bits(self.prefix, width);
+        self.network.with_bits(mask)
+    }
+
+    pub fn wildcard(&self) -> IpAddr {
+        self.network.with_bits(host_bits(self.prefix, self.width()))
+    }
+
+    // The last address in the block, whether or not it's a broadcast
+    pub fn last(&self) -> IpAddr {
+        self.network.with_bits(self.end())
+    }
+
+    // IPv6 has no broadcast, and a /31 or /32 has no room for one
+    pub fn broadcast(&self) -> Option<IpAddr> {
+        match self.network {
+            IpAddr::V4(_) if self.prefix <= 30 => Some(self.last()),
+            _ => None,
+        }
+    }
+
+    // 2 to the power of the host bits. A whole IPv6 space (::/0) has one
+    // more address than a u128 can count, so that one saturates.
+    pub fn address_count(&self) -> u128 {
+        host_bits(self.prefix, self.width()).saturating_add(1)
+    }
+
+    // Usable host addresses. An IPv4 block loses its network and broadcast
+    // addresses, except a /31 point-to-point link (RFC 3021) and a /32.
+    pub fn host_count(&self) -> u128 {
+        match self.broadcast() {
+            Some(_) => self.address_count() - 2,
+            None => self.address_count(),
+        }
+    }
+
+    pub fn first_host(&self) -> IpAddr {
+        match self.broadcast() {
+            Some(_) => self.network.with_bits(self.start() + 1),
+            None => self.network,
+        }
+    }
+
+    pub fn last_host(&self) -> IpAddr {
+        match self.broadcast() {
+            Some(_) => self.network.with_bits(self.end() - 1),
+            None => self.last(),
+        }
+    }
+
+    pub fn contains(&self, address: IpAddr) -> bool {
+        address.is_ipv4() == self.network.is_ipv4()
+            && (self.start()..=self.end()).contains(&address.to_bits())
+    }
+
+    pub fn contains_cidr(&self, other: &Cidr) -> bool {
+        other.prefix >= self.prefix && self.contains(other.network)
+    }
+
+    // The usable hosts, one at a time. Nothing is collected, so iterating
+    // the first few hosts of a /8 or a /64 is as cheap as for a /24.
+    pub fn hosts(&self) -> Hosts {
+        Hosts {
+            template: self.network,
+            next: self.first_host().to_bits(),
+            last: self.last_host().to_bits(),
+            done: false,
+        }
+    }
+
+    // The block cut into equal pieces with a longer prefix, in order:
+    // 10.0.0.0/8 into /10s gives 10.0.0.0/10, 10.64.0.0/10 and so on.
+    pub fn subnets(&self, new_prefix: u8) -> Result<Subnets, CidrError> {
+        let max: u32 = self.width();
+        if new_prefix as u32 > max {
+            return Err(CidrError::PrefixTooLong { prefix: new_prefix, max });
+        }
+        if new_prefix < self.prefix {
+            return Err(CidrError::SubnetTooLarge {
+                prefix: self.prefix,
+                new_prefix,
+            });
+        }
+        Ok(Subnets {
+            template: self.network,
+            prefix: new_prefix,
+            step: host_bits(new_prefix, max),
+            next: self.start(),
+            end: self.end(),
+            done: false,
+        })
+    }
+}
+
+
+/*
+* Collapses a list of blocks (or single addresses, via Cidr::host) into the
+* fewest blocks covering exactly the same addresses. Overlapping and
+* adjacent blocks are joined into ranges first, and then each range is
+* covered greedily with the biggest aligned block that fits at its start.
+*
+*   10.0.0.0/24, 10.0.1.0/24              -> 10.0.0.0/23
+*   10.0.0.0/24, 10.0.1.0/24, 10.0.2.0/24 -> 10.0.0.0/23, 10.0.2.0/24
+*
+* IPv4 blocks come out before IPv6 ones.
+*/
+pub fn merge(blocks: impl IntoIterator<Item = Cidr>) -> Vec<Cidr> {
+    let mut blocks: Vec<Cidr> = blocks.into_iter().collect();
+    blocks.sort();
+    let mut out: Vec<Cidr> = Vec::new();
+    let mut range: Option<(IpAddr, u128, u128)> = None;
+    for block in blocks {
+        match &mut range {
+            Some((template, _, end))
+                if template.is_ipv4() == block.network.is_ipv4()
+                    && block.start() <= end.saturating_add(1) =>
+            {
+                *end = (*end).max(block.end());
+            }
+            _ => {
+                if let Some((template, start, end)) = range {
+                    cover(template, start, end, &mut out);
+                }
+                range = Some((block.network, block.start(), block.end()));
+            }
+        }
+    }
+    if let Some((template, start, end)) = range {
+        cover(template, start, end, &mut out);
+    }
+    out
+}
+
+fn cover(template: IpAddr, mut start: u128, end: u128, out: &mut Vec<Cidr>) {
+    let width: u32 = template.bit_len();
+    loop {
+        // Grow the block while it stays aligned and inside the range
+        let mut prefix: u32 = width;
+        while prefix > 0 {
+            let host: u128 = host_bits(prefix as u8 - 1, width);
+            if start & host != 0 || start | host > end {
+                break;
+            }
+            prefix -= 1;
+        }
+        let block: Cidr = Cidr {
+            network: template.with_bits(start),
+            prefix: prefix as u8,
+        };
+        out.push(block);
+        if block.end() >= end {
+            break;
+        }
+        start = block.end() + 1;
+    }
+}
+
+
+pub struct Hosts {
+    template: IpAddr,
+    next: u128,
+    last: u128,
+    done: bool,
+}
+
+impl Iterator for Hosts {
+    type Item = IpAddr;
+
+    fn next(&mut self) -> Option<IpAddr> {
+        if self.done {
+            return None;
+        }
+        let host: IpAddr = self.template.with_bits(self.next);
+        if self.next == self.last {
+            self.done = true;
+        } else {
+            self.next += 1;
+        }
+        Some(host)
+    }
+}
+
+
+pub struct Subnets {
+    template: IpAddr,
+    prefix: u8,
+    step: u128,
+    next: u128,
+    end: u128,
+    done: bool,
+}
+
+impl Iterator for Subnets {
+    type Item = Cidr;
+
+    fn next(&mut self) -> Option<Cidr> {
+        if self.done {
+            return None;
+        }
+        let subnet: Cidr = Cidr {
+            network: self.template.with_bits(self.next),
+            prefix: self.prefix,
+        };
+        let subnet_end: u128 = self.next | self.step;
+        if subnet_end >= self.end {
+            self.done = true;
+        } else {
+            self.next = subnet_end + 1;
+        }
+        Some(subnet)
+    }
+}
+
+
+impl FromStr for Cidr {
+    type Err = CidrError;
+
+    fn from_str(text: &str) -> Result<Cidr, CidrError> {
+        Cidr::parse(text)
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.network, self.prefix)
+    }
+}
+
+
+// What `notes ip calc` prints for one block
+pub fn report(address: IpAddr, cidr: &Cidr) -> String {
+    let kind: &str = if address.is_loopback() {
+        "loopback"
+    } else if address.is_private() {
+        "private"
+    } else if address.is_link_local() {
+        "link-local"
+    } else if address.is_multicast() {
+        "multicast"
+    } else if address.is_documentation() {
+        "documentation"
+    } else {
+        "public"
+    };
+    let broadcast: String = match cidr.broadcast() {
+        Some(ip) => ip.to_string(),
+        None => String::from("none"),
+    };
+    let rows: [(&str, String); 10] = [
+        ("Address", address.to_string()),
+        ("Network", cidr.to_string()),
+        ("Netmask", format!("{} = /{}", cidr.netmask(), cidr.prefix())),
+        ("Wildcard", cidr.wildcard().to_string()),
+        ("Broadcast", broadcast),
+        ("First host", cidr.first_host().to_string()),
+        ("Last host", cidr.last_host().to_string()),
+        ("Hosts", cidr.host_count().to_string()),
+        ("Addresses", cidr.address_count().to_string()),
+        ("Type", kind.to_string()),
+    ];
+    let mut out: String = String::new();
+    for (label, value) in rows {
+        out.push_str(&format!("{:<11} {value}\n", format!("{label}:")));
+    }
+    out
+}
+
+
+// `notes ip calc <cidr>`
+pub fn cli(args: &[String]) {
+    let strs: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
+    match strs.as_slice() {
+        ["calc", text] => match Cidr::parse_with_address(text) {
+            Ok((address, cidr)) => print!("{}", report(address, &cidr)),
+            Err(err) => {
+                eprintln!("error: {text}: {err}");
+                process::exit(1);
+            }
+        },
+        _ => {
+            eprintln!("usage: notes ip calc <address>/<prefix>");
+            process::exit(1);
+        }
+    }
+}
+
+
+pub fn run() {
+    println!("\nCIDR blocks:");
+    let (address, cidr) = Cidr::parse_with_address("192.168.1.77/26").unwrap();
+    for line in report(address, &cidr).lines() {
+        println!("  {line}");
+    }
+
+    let office: Cidr = "10.20.0.0/16".parse().unwrap();
+    let lab: Cidr = "10.20.32.0/20".parse().unwrap();
+    let printer: IpAddr = "10.20.40.9".parse().unwrap();
+    println!("  {office} contains {lab}: {}", office.contains_cidr(&lab));
+    println!("  {lab} contains {printer}: {}", lab.contains(printer));
+
+    let first: Vec<String> = office.hosts()
+        .take(3)
+        .map(|ip| ip.to_string())
+        .collect();
+    println!("  First hosts of {office}: {}", first.join(", "));
+    let split: Vec<String> = lab.subnets(22).unwrap()
+        .map(|c| c.to_string())
+        .collect();
+    println!("  {lab} as /22s: {}", split.join(", "));
+
+    let v6: Cidr = "2001:db8:abcd::/48".parse().unwrap();
+    let sites: Vec<String> = v6.subnets(50).unwrap()
+        .map(|c| c.to_string())
+        .collect();
+    println!("  {v6} as /50s: {}", sites.join(", "));
+
+    let scattered: Vec<Cidr> = [
+        "10.0.0.0/24", "10.0.1.0/24", "10.0.2.0/25", "10.0.2.128/25",
+        "10.0.3.0", "10.0.0.64/26", "2001:db8::/33", "2001:db8:8000::/33",
+    ].iter().map(|s| s.parse().unwrap()).collect();
+    let merged: Vec<String> = merge(scattered).iter()
+        .map(|c| c.to_string())
+        .collect();
+    println!("  Merged: {}", merged.join(", "));
+}
+
+
+pub fn register_layouts(registry: &mut Registry) {
+    registry.register("Chapter 6", layout_of!(Cidr { network, prefix }));
+    registry.register("Chapter 6", layout_of!(CidrError));
+    registry.register(
+        "Chapter 6",
+        layout_of!(Hosts { template, next, last, done }),
+    );
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cidr(text: &str) -> Cidr {
+        text.parse().unwrap()
+    }
+
+    fn strings(blocks: impl IntoIterator<Item = Cidr>) -> Vec<String> {
+        blocks.into_iter().map(|c| c.to_string()).collect()
+    }
+
+    #[test]
+    fn parse_clears_host_bits() {
+        let (address, block) =
+            Cidr::parse_with_address("192.168.1.77/26").unwrap();
+        assert_eq!(address.to_string(), "192.168.1.77");
+        assert_eq!(block.to_string(), "192.168.1.64/26");
+        assert_eq!(cidr("2001:db8::1/32").to_string(), "2001:db8::/32");
+        assert_eq!(cidr(" 10.1.2.3 ").to_string(), "10.1.2.3/32");
+        assert_eq!(cidr("::1").to_string(), "::1/128");
+    }
+
+    #[test]
+    fn parse_rejects() {
+        for (text, err) in [
+            ("10.0.0.0/+8", CidrError::InvalidPrefix(String::from("+8"))),
+            ("10.0.0.0/-0", CidrError::InvalidPrefix(String::from("-0"))),
+            ("10.0.0.0/", CidrError::InvalidPrefix(String::new())),
+            ("10.0.0.0/8/8", CidrError::InvalidPrefix(String::from("8/8"))),
+            ("10.0.0.0/256", CidrError::InvalidPrefix(String::from("256"))),
+            ("10.0.0.0/33", CidrError::PrefixTooLong { prefix: 33, max: 32 }),
+            ("::/129", CidrError::PrefixTooLong { prefix: 129, max: 128 }),
+            ("10.0.0/8", CidrError::Address(ParseError::WrongOctetCount(3))),
+        ] {
+            assert_eq!(Cidr::parse(text), Err(err), "{text}");
+        }
+    }
+
+    #[test]
+    fn edge_prefixes() {
+        let all: Cidr = cidr("0.0.0.0/0");
+        assert_eq!(all.netmask().to_string(), "0.0.0.0");
+        assert_eq!(all.broadcast().unwrap().to_string(), "255.255.255.255");
+        assert_eq!(all.address_count(), 1 << 32);
+        assert_eq!(all.host_count(), (1 << 32) - 2);
+        assert!(all.contains("203.0.113.9".parse().unwrap()));
+        assert!(!all.contains("::1".parse().unwrap()));
+
+        let link: Cidr = cidr("10.0.0.1/31");
+        assert_eq!(link.broadcast(), None);
+        assert_eq!(link.host_count(), 2);
+        assert_eq!(strings(link.hosts().map(Cidr::host)),
+            ["10.0.0.0/32", "10.0.0.1/32"]);
+
+        let one: Cidr = cidr("10.0.0.7/32");
+        assert_eq!(one.netmask().to_string(), "255.255.255.255");
+        assert_eq!(one.host_count(), 1);
+        assert_eq!(one.first_host(), one.last_host());
+        assert_eq!(one.hosts().count(), 1);
+
+        let every: Cidr = cidr("::/0");
+        assert_eq!(every.address_count(), u128::MAX);
+        assert_eq!(every.last().to_string(),
+            "ffff:ffff:ffff:ffff:ffff:ffff:ffff:ffff");
+        assert_eq!(every.hosts().take(2).count(), 2);
+
+        let pair: Cidr = cidr("2001:db8::1/127");
+        assert_eq!(pair.to_string(), "2001:db8::/127");
+        assert_eq!(pair.broadcast(), None);
+        assert_eq!(pair.hosts().count(), 2);
+
+        let single: Cidr = cidr("2001:db8::1/128");
+        assert_eq!(single.address_count(), 1);
+        assert_eq!(single.hosts().count(), 1);
+        assert!(single.contains("2001:db8::1".parse().unwrap()));
+        assert!(!single.contains("2001:db8::2".parse().unwrap()));
+    }
+
+    #[test]
+    fn hosts_skip_network_and_broadcast() {
+        let block: Cidr = cidr("192.168.1.0/30");
+        assert_eq!(strings(block.hosts().map(Cidr::host)),
+            ["192.168.1.1/32", "192.168.1.2/32"]);
+        assert_eq!(cidr("10.0.0.0/8").hosts().nth(1_000).unwrap().to_string(),
+            "10.0.3.233");
+    }
+
+    #[test]
+    fn subnets() {
+        assert_eq!(strings(cidr("10.20.32.0/20").subnets(22).unwrap()), [
+            "10.20.32.0/22", "10.20.36.0/22", "10.20.40.0/22", "10.20.44.0/22",
+        ]);
+        assert_eq!(strings(cidr("10.0.0.0/24").subnets(24).unwrap()),
+            ["10.0.0.0/24"]);
+        assert_eq!(cidr("10.0.0.0/16").subnets(32).unwrap().count(), 65_536);
+        assert_eq!(strings(cidr("::/0").subnets(1).unwrap()),
+            ["::/1", "8000::/1"]);
+        assert_eq!(cidr("::/0").subnets(128).unwrap().nth(3).unwrap(),
+            cidr("::3"));
+        assert!(matches!(
+            cidr("10.0.0.0/24").subnets(16),
+            Err(CidrError::SubnetTooLarge { prefix: 24, new_prefix: 16 })
+        ));
+        assert!(matches!(
+            cidr("10.0.0.0/24").subnets(33),
+            Err(CidrError::PrefixTooLong { prefix: 33, max: 32 })
+        ));
+    }
+
+    #[test]
+    fn merge_and_cover() {
+        let merged = |texts: &[&str]| {
+            strings(merge(texts.iter().map(|t| cidr(t))))
+        };
+        assert_eq!(merged(&["10.0.0.0/24", "10.0.1.0/24"]), ["10.0.0.0/23"]);
+        assert_eq!(
+            merged(&["10.0.2.0/24", "10.0.0.0/24", "10.0.1.0/24"]),
+            ["10.0.0.0/23", "10.0.2.0/24"]
+        );
+        // Contained, duplicate and single addresses
+        assert_eq!(
+            merged(&["10.0.0.0/16", "10.0.5.0/24", "10.0.0.0/16", "10.1.0.0"]),
+            ["10.0.0.0/16", "10.1.0.0/32"]
+        );
+        // An unaligned range needs several blocks
+        assert_eq!(
+            merged(&["10.0.0.1", "10.0.0.2/31", "10.0.0.4/30"]),
+            ["10.0.0.1/32", "10.0.0.2/31", "10.0.0.4/30"]
+        );
+        assert_eq!(
+            merged(&["0.0.0.0/1", "128.0.0.0/1", "::/1", "8000::/1"]),
+            ["0.0.0.0/0", "::/0"]
+        );
+        // The two families never join, even where the bits line up
+        assert_eq!(merged(&["0.0.0.0/32", "::1"]), ["0.0.0.0/32", "::1/128"]);
+        assert_eq!(merged(&[]), Vec::<String>::new());
+    }
+
+    #[test]
+    fn report_rows() {
+        let (address, block) =
+            Cidr::parse_with_address("192.168.1.77/26").unwrap();
+        let text: String = report(address, &block);
+        assert!(text.contains("Netmask:    255.255.255.192 = /26\n"));
+        assert!(text.contains("Broadcast:  192.168.1.127\n"));
+        assert!(text.contains("Hosts:      62\n"));
+        assert!(text.contains("Type:       private\n"));
+    }
+}
@@ -0,0 +1,576 @@
+use std::fmt;
+use std::io::{self, Read, Write};
+
+use super::msg::Message;
+use crate::layout::{layout_of, Registry};
+
+/*
+* A compact binary form of Message for sending between processes:
+*
+*   quit          0x00
+*   move x y      0x01 varint(x) varint(y)
+*   write text    0x02 varint(len) utf-8 bytes
+*   color r g b   0x03 varint(r) varint(g) varint(b)
+*
+* Integers are zigzag encoded (0, -1, 1, -2, ... become 0, 1, 2, 3, ...)
+* and then written as LEB128 varints: seven bits per byte, low bits first,
+* with the top bit set on every byte but the last. Small numbers of either
+* sign take a single byte, so `move 1 -1` is three bytes in all.
+*
+* A varint may not end in a zero byte (0x81 0x00 for 1, say), so every
+* message has exactly one encoding.
+*/
+const TAG_QUIT: u8 = 0;
+const TAG_MOVE: u8 = 1;
+const TAG_WRITE: u8 = 2;
+const TAG_COLOR: u8 = 3;
+
+// An i32 never needs more than five varint bytes
+const MAX_VARINT_BYTES: usize = 5;
+
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    Truncated,
+    UnknownTag(u8),
+    VarintTooLong,
+    OverlongVarint,
+    IntegerOutOfRange(u64),
+    InvalidUtf8 { valid_up_to: usize },
+    TrailingBytes(usize),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DecodeError::Truncated => write!(f, "message is truncated"),
+            DecodeError::UnknownTag(tag) => {
+                write!(f, "unknown message tag {tag:#04x}")
+            }
+            DecodeError::VarintTooLong => write!(
+                f,
+                "varint runs past {MAX_VARINT_BYTES} bytes"
+            ),
+            DecodeError::OverlongVarint => {
+                write!(f, "varint ends in a redundant zero byte")
+            }
+            DecodeError::IntegerOutOfRange(value) => {
+                write!(f, "varint {value} does not fit in an i32")
+            }
+            DecodeError::InvalidUtf8 { valid_up_to } => write!(
+                f,
+                "write text is not UTF-8 after byte {valid_up_to}"
+            ),
+            DecodeError::TrailingBytes(count) => {
+                write!(f, "{count} byte(s) left over after the message")
+            }
+        }
+    }
+}
+
+
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn write_int(out: &mut Vec<u8>, value: i32) {
+    let zigzag: u32 = ((value << 1) ^ (value >> 31)) as u32;
+    write_varint(out, zigzag as u64);
+}
+
+// Appends the encoding of `message` to `out`
+pub fn encode_into(message: &Message, out: &mut Vec<u8>) {
+    match message {
+        Message::Quit => out.push(TAG_QUIT),
+        Message::Move { x, y } => {
+            out.push(TAG_MOVE);
+            write_int(out, *x);
+            write_int(out, *y);
+        }
+        Message::Write(text) => {
+            out.push(TAG_WRITE);
+            write_varint(out, text.len() as u64);
+            out.extend_from_slice(text.as_bytes());
+        }
+        Message::ChangeColor(r, g, b) => {
+            out.push(TAG_COLOR);
+            write_int(out, *r);
+            write_int(out, *g);
+            write_int(out, *b);
+        }
+    }
+}
+
+pub fn encode(message: &Message) -> Vec<u8> {
+    let mut out: Vec<u8> = Vec::new();
+    encode_into(message, &mut out);
+    out
+}
+
+
+// Reads from the front of a byte slice, moving past what it has read
+struct Cursor<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Cursor<'a> {
+
+    fn byte(&mut self) -> Result<u8, DecodeError> {
+        let (&first, rest) = self.bytes
+            .split_first()
+            .ok_or(DecodeError::Truncated)?;
+        self.bytes = rest;
+        Ok(first)
+    }
+
+    fn take(&mut self, count: usize) -> Result<&'a [u8], DecodeError> {
+        if count > self.bytes.len() {
+            return Err(DecodeError::Truncated);
+        }
+        let (taken, rest) = self.bytes.split_at(count);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn varint(&mut self) -> Result<u64, DecodeError> {
+        let mut value: u64 = 0;
+        for i in 0..MAX_VARINT_BYTES {
+            let byte: u8 = self.byte()?;
+            value |= ((byte & 0x7f) as u64) << (7 * i);
+            if byte & 0x80 == 0 {
+                return match (i, byte) {
+                    (1.., 0) => Err(DecodeError::OverlongVarint),
+                    _ => Ok(value),
+                };
+            }
+        }
+        Err(DecodeError::VarintTooLong)
+    }
+
+    fn int(&mut self) -> Result<i32, DecodeError> {
+        let raw: u64 = self.varint()?;
+        let zigzag: u32 = u32::try_from(raw)
+            .map_err(|_| DecodeError::IntegerOutOfRange(raw))?;
+        Ok((zigzag >> 1) as i32 ^ -((zigzag & 1) as i32))
+    }
+
+    fn message(&mut self) -> Result<Message, DecodeError> {
+        match self.byte()? {
+            TAG_QUIT => Ok(Message::Quit),
+            TAG_MOVE => Ok(Message::Move { x: self.int()?, y: self.int()? }),
+            TAG_WRITE => {
+                let len: u64 = self.varint()?;
+                let len: usize = usize::try_from(len)
+                    .map_err(|_| DecodeError::Truncated)?;
+                let bytes: &[u8] = self.take(len)?;
+                match std::str::from_utf8(bytes) {
+                    Ok(text) => Ok(Message::Write(text.to_string())),
+                    Err(err) => Err(DecodeError::InvalidUtf8 {
+                        valid_up_to: err.valid_up_to(),
+                    }),
+                }
+            }
+            TAG_COLOR => Ok(Message::ChangeColor(
+                self.int()?,
+                self.int()?,
+                self.int()?,
+            )),
+            tag => Err(DecodeError::UnknownTag(tag)),
+        }
+    }
+}
+
+// Decodes one message from the front of `bytes`, returning it along with
+// how many bytes it took up
+pub fn decode_prefix(bytes: &[u8]) -> Result<(Message, usize), DecodeError> {
+    let mut cursor: Cursor = Cursor { bytes };
+    let message: Message = cursor.message()?;
+    Ok((message, bytes.len() - cursor.bytes.len()))
+}
+
+// Decodes exactly one message; anything after it is an error
+pub fn decode(bytes: &[u8]) -> Result<Message, DecodeError> {
+    let (message, used) = decode_prefix(bytes)?;
+    match bytes.len() - used {
+        0 => Ok(message),
+        left => Err(DecodeError::TrailingBytes(left)),
+    }
+}
+
+
+/*
+* On a stream each message goes in a frame: a varint byte count followed
+* by that many bytes of encoded message. The count lets a reader pull in
+* a whole message before decoding it and refuse absurd sizes up front.
+*/
+pub const MAX_FRAME_LEN: u64 = 1 << 20;
+
+#[derive(Debug)]
+pub enum FrameError {
+    Io(io::Error),
+    // The stream ended partway through a frame
+    Truncated,
+    TooLarge(u64),
+    Decode(DecodeError),
+}
+
+impl fmt::Display for FrameError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FrameError::Io(err) => write!(f, "{err}"),
+            FrameError::Truncated => {
+                write!(f, "stream ended in the middle of a frame")
+            }
+            FrameError::TooLarge(len) => write!(
+                f,
+                "frame of {len} bytes is over the {MAX_FRAME_LEN} byte limit"
+            ),
+            FrameError::Decode(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl From<io::Error> for FrameError {
+    fn from(err: io::Error) -> FrameError {
+        FrameError::Io(err)
+    }
+}
+
+impl From<DecodeError> for FrameError {
+    fn from(err: DecodeError) -> FrameError {
+        FrameError::Decode(err)
+    }
+}
+
+
+pub struct FrameWriter<W: Write> {
+    inner: W,
+    buffer: Vec<u8>,
+}
+
+impl<W: Write> FrameWriter<W> {
+
+    pub fn new(inner: W) -> FrameWriter<W> {
+        FrameWriter { inner, buffer: Vec::new() }
+    }
+
+    pub fn write(&mut self, message: &Message) -> io::Result<()> {
+        let body: Vec<u8> = encode(message);
+        self.buffer.clear();
+        write_varint(&mut self.buffer, body.len() as u64);
+        self.buffer.extend_from_slice(&body);
+        self.inner.write_all(&self.buffer)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+
+pub struct FrameReader<R: Read> {
+    inner: R,
+    buffer: Vec<u8>,
+    failed: bool,
+}
+
+impl<R: Read> FrameReader<R> {
+
+    pub fn new(inner: R) -> FrameReader<R> {
+        FrameReader { inner, buffer: Vec::new(), failed: false }
+    }
+
+    // Ok(None) when the stream ends cleanly between frames
+    pub fn read(&mut self) -> Result<Option<Message>, FrameError> {
+        let Some(len) = self.frame_len()? else {
+            return Ok(None);
+        };
+        if len > MAX_FRAME_LEN {
+            return Err(FrameError::TooLarge(len));
+        }
+        self.buffer.resize(len as usize, 0);
+        self.inner.read_exact(&mut self.buffer).map_err(eof_is_truncated)?;
+        Ok(Some(decode(&self.buffer)?))
+    }
+
+    fn frame_len(&mut self) -> Result<Option<u64>, FrameError> {
+        let mut value: u64 = 0;
+        for i in 0..MAX_VARINT_BYTES {
+            let mut byte: [u8; 1] = [0];
+            if let Err(err) = self.inner.read_exact(&mut byte) {
+                if i == 0 && err.kind() == io::ErrorKind::UnexpectedEof {
+                    return Ok(None);
+                }
+                return Err(eof_is_truncated(err));
+            }
+            value |= ((byte[0] & 0x7f) as u64) << (7 * i);
+            if byte[0] & 0x80 == 0 {
+                return match (i, byte[0]) {
+                    (1.., 0) => Err(DecodeError::OverlongVarint.into()),
+                    _ => Ok(Some(value)),
+                };
+            }
+        }
+        Err(FrameError::Decode(DecodeError::VarintTooLong))
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+fn eof_is_truncated(err: io::Error) -> FrameError {
+    match err.kind() {
+        io::ErrorKind::UnexpectedEof => FrameError::Truncated,
+        _ => FrameError::Io(err),
+    }
+}
+
+// Reads frames until the stream ends; stops after the first error
+impl<R: Read> Iterator for FrameReader<R> {
+    type Item = Result<Message, FrameError>;
+
+    fn next(&mut self) -> Option<Result<Message, FrameError>> {
+        if self.failed {
+            return None;
+        }
+        let next = self.read().transpose();
+        self.failed = matches!(next, Some(Err(_)));
+        next
+    }
+}
+
+
+pub fn run() {
+    println!("\nWire encoding:");
+    let messages: [Message; 4] = [
+        Message::Move { x: 1, y: -1 },
+        Message::ChangeColor(255, 0, 128),
+        Message::Write(String::from("héllo")),
+        Message::Quit,
+    ];
+    for message in &messages {
+        let bytes: Vec<u8> = encode(message);
+        let hex: Vec<String> =
+            bytes.iter().map(|b| format!("{b:02x}")).collect();
+        println!("  {:<22} {}", message.to_string(), hex.join(" "));
+    }
+
+    let mut writer: FrameWriter<Vec<u8>> = FrameWriter::new(Vec::new());
+    for message in &messages {
+        writer.write(message).unwrap();
+    }
+    let stream: Vec<u8> = writer.into_inner();
+    let back: Vec<Message> = FrameReader::new(stream.as_slice())
+        .collect::<Result<_, _>>()
+        .unwrap();
+    println!("  {} frames in {} bytes, read back equal: {}",
+        back.len(), stream.len(), back == messages);
+
+    for bad in [&[0x09][..], &[0x01, 0x80], &[0x02, 0x02, 0xc3, 0x28]] {
+        println!("  {bad:02x?}: {}", decode(bad).unwrap_err());
+    }
+}
+
+
+pub fn register_layouts(registry: &mut Registry) {
+    registry.register("Chapter 6", layout_of!(DecodeError));
+    registry.register("Chapter 6", layout_of!(FrameError));
+    registry.register(
+        "Chapter 6",
+        layout_of!(FrameWriter<Vec<u8>> { inner, buffer }),
+    );
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    // Fuzz-style tests: random messages must survive a round trip, and
+    // random bytes must come back as an error rather than a panic.
+    const CASES: usize = 2_000;
+
+    fn random_int(rng: &mut StdRng) -> i32 {
+        match rng.gen_range(0..3) {
+            0 => rng.gen_range(-70..70),
+            1 => rng.gen(),
+            _ => *[i32::MIN, i32::MAX, -1, 0].get(rng.gen_range(0..4)).unwrap(),
+        }
+    }
+
+    fn random_text(rng: &mut StdRng) -> String {
+        let len: usize = rng.gen_range(0..40);
+        (0..len)
+            .map(|_| match rng.gen_range(0..4) {
+                0 => rng.gen::<char>(),
+                _ => rng.gen_range(' '..='~'),
+            })
+            .collect()
+    }
+
+    fn random_message(rng: &mut StdRng) -> Message {
+        match rng.gen_range(0..4) {
+            0 => Message::Quit,
+            1 => Message::Move { x: random_int(rng), y: random_int(rng) },
+            2 => Message::Write(random_text(rng)),
+            _ => Message::ChangeColor(
+                random_int(rng),
+                random_int(rng),
+                random_int(rng),
+            ),
+        }
+    }
+
+    #[test]
+    fn known_encodings() {
+        assert_eq!(encode(&Message::Quit), [0x00]);
+        assert_eq!(encode(&Message::Move { x: 1, y: -1 }), [0x01, 0x02, 0x01]);
+        assert_eq!(
+            encode(&Message::ChangeColor(255, 0, 64)),
+            [0x03, 0xfe, 0x03, 0x00, 0x80, 0x01]
+        );
+        assert_eq!(
+            encode(&Message::Write(String::from("hi"))),
+            [0x02, 0x02, b'h', b'i']
+        );
+        assert_eq!(
+            encode(&Message::Move { x: i32::MIN, y: i32::MAX }),
+            [0x01, 0xff, 0xff, 0xff, 0xff, 0x0f, 0xfe, 0xff, 0xff, 0xff, 0x0f]
+        );
+    }
+
+    #[test]
+    fn random_messages_round_trip() {
+        let mut rng: StdRng = StdRng::seed_from_u64(1);
+        for _ in 0..CASES {
+            let message: Message = random_message(&mut rng);
+            let bytes: Vec<u8> = encode(&message);
+            assert_eq!(decode(&bytes), Ok(message));
+        }
+    }
+
+    #[test]
+    fn every_cut_short_encoding_is_truncated() {
+        let mut rng: StdRng = StdRng::seed_from_u64(2);
+        for _ in 0..CASES {
+            let bytes: Vec<u8> = encode(&random_message(&mut rng));
+            for end in 0..bytes.len() {
+                assert_eq!(decode(&bytes[..end]), Err(DecodeError::Truncated));
+            }
+        }
+    }
+
+    #[test]
+    fn random_bytes_never_panic() {
+        let mut rng: StdRng = StdRng::seed_from_u64(3);
+        for _ in 0..CASES * 5 {
+            let len: usize = rng.gen_range(0..16);
+            let mut bytes: Vec<u8> = (0..len).map(|_| rng.gen()).collect();
+            if let Some(tag) = bytes.first_mut() {
+                *tag %= 5;
+            }
+            // Whatever decodes must encode back to the same bytes
+            if let Ok((message, used)) = decode_prefix(&bytes) {
+                assert_eq!(encode(&message), bytes[..used]);
+            }
+        }
+    }
+
+    #[test]
+    fn typed_errors() {
+        assert_eq!(decode(&[]), Err(DecodeError::Truncated));
+        assert_eq!(decode(&[0x07]), Err(DecodeError::UnknownTag(7)));
+        assert_eq!(
+            decode(&[0x01, 0x80, 0x80, 0x80, 0x80, 0x80, 0x00]),
+            Err(DecodeError::VarintTooLong)
+        );
+        assert_eq!(
+            decode(&[0x01, 0x81, 0x00, 0x00]),
+            Err(DecodeError::OverlongVarint)
+        );
+        assert_eq!(
+            decode(&[0x02, 0x80, 0x00]),
+            Err(DecodeError::OverlongVarint)
+        );
+        assert_eq!(decode(&[0x01, 0x80, 0x01, 0x00]), Ok(Message::Move {
+            x: 64,
+            y: 0,
+        }));
+        assert_eq!(
+            decode(&[0x01, 0x80, 0x80, 0x80, 0x80, 0x10, 0x00]),
+            Err(DecodeError::IntegerOutOfRange(1 << 32))
+        );
+        assert_eq!(
+            decode(&[0x02, 0x03, b'a', 0xc3, 0x28]),
+            Err(DecodeError::InvalidUtf8 { valid_up_to: 1 })
+        );
+        assert_eq!(decode(&[0x00, 0x00]), Err(DecodeError::TrailingBytes(1)));
+    }
+
+    #[test]
+    fn random_streams_round_trip() {
+        let mut rng: StdRng = StdRng::seed_from_u64(4);
+        for _ in 0..CASES / 10 {
+            let count: usize = rng.gen_range(0..20);
+            let messages: Vec<Message> =
+                (0..count).map(|_| random_message(&mut rng)).collect();
+            let mut writer: FrameWriter<Vec<u8>> = FrameWriter::new(Vec::new());
+            for message in &messages {
+                writer.write(message).unwrap();
+            }
+            let stream: Vec<u8> = writer.into_inner();
+            let back: Vec<Message> = FrameReader::new(stream.as_slice())
+                .collect::<Result<_, _>>()
+                .unwrap();
+            assert_eq!(back, messages);
+        }
+    }
+
+    #[test]
+    fn stream_cut_mid_frame_is_truncated() {
+        let mut writer: FrameWriter<Vec<u8>> = FrameWriter::new(Vec::new());
+        writer.write(&Message::Quit).unwrap();
+        writer.write(&Message::Write(String::from("hello"))).unwrap();
+        let stream: Vec<u8> = writer.into_inner();
+        for end in 3..stream.len() {
+            let mut reader = FrameReader::new(&stream[..end]);
+            assert_eq!(reader.read().unwrap(), Some(Message::Quit));
+            assert!(matches!(reader.read(), Err(FrameError::Truncated)));
+        }
+        let mut reader = FrameReader::new(&stream[..2]);
+        assert_eq!(reader.read().unwrap(), Some(Message::Quit));
+        assert!(reader.read().unwrap().is_none());
+    }
+
+    #[test]
+    fn bad_frames_are_reported() {
+        let huge: [u8; 4] = [0x80, 0x80, 0x80, 0x01];
+        let mut reader = FrameReader::new(&huge[..]);
+        assert!(matches!(reader.read(), Err(FrameError::TooLarge(_))));
+
+        let unknown: [u8; 2] = [0x01, 0x09];
+        let mut reader = FrameReader::new(&unknown[..]);
+        assert!(matches!(
+            reader.read(),
+            Err(FrameError::Decode(DecodeError::UnknownTag(9)))
+        ));
+
+        let overlong: [u8; 3] = [0x81, 0x00, 0x00];
+        let mut reader = FrameReader::new(&overlong[..]);
+        assert!(matches!(
+            reader.read(),
+            Err(FrameError::Decode(DecodeError::OverlongVarint))
+        ));
+    }
+}
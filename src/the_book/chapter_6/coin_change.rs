@@ -0,0 +1,535 @@
+use std::fmt;
+
+use super::coin_sorting::{value_in_cents, Coin, UsState};
+use crate::layout::{layout_of, Registry};
+
+
+/*
+* Making change from a set of coins, each either unlimited or with a
+* fixed number on hand. Answers come back largest coin first as
+* (coin, how many) pairs, leaving out coins that aren't used.
+*
+* Greedy change (take the biggest coin that fits, repeat) is what a
+* cashier does, and for US coins it always gives the fewest coins. It
+* doesn't for every set: with only pennies, dimes and quarters, greedy
+* makes 30 cents as a quarter and five pennies where three dimes would
+* do. `fewest` never gets this wrong, since it works up through every
+* smaller amount first.
+*
+* A set is made of Coins, so a custom set is some of the six US values
+* (1, 5, 10, 25, 50 and 100 cents), with or without limits. Made-up
+* systems like {1, 3, 4} can't be built. Coins of the same value are one
+* denomination: the answers name whichever quarter went in first.
+*
+* `fewest` and `ways` keep a table entry for every amount up to the one
+* asked for, so they refuse amounts over MAX_AMOUNT. The one exception is
+* `fewest` on a set with no limits that greedy always gets right, which
+* just uses greedy.
+*/
+pub type Change = Vec<(Coin, u32)>;
+
+// $1,000 in cents
+pub const MAX_AMOUNT: u32 = 100_000;
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChangeError {
+    TooLarge(u32),
+    Overflow,
+}
+
+impl fmt::Display for ChangeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ChangeError::TooLarge(amount) => write!(
+                f,
+                "{amount} cents is over the {MAX_AMOUNT} cent limit"
+            ),
+            ChangeError::Overflow => {
+                write!(f, "there are more ways than fit in a u128")
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CoinSet {
+    // Largest value first; None means as many as needed
+    coins: Vec<(Coin, Option<u32>)>,
+}
+
+impl CoinSet {
+
+    pub fn new() -> CoinSet {
+        CoinSet { coins: Vec::new() }
+    }
+
+    // Every coin in circulation, with a Delaware quarter standing in for
+    // the quarters since it was the first one out
+    pub fn us() -> CoinSet {
+        CoinSet::new()
+            .coin(Coin::Dollar)
+            .coin(Coin::HalfDollar)
+            .coin(Coin::Quarter(UsState::Delaware))
+            .coin(Coin::Dime)
+            .coin(Coin::Nickel)
+            .coin(Coin::Penny)
+    }
+
+    pub fn coin(self, coin: Coin) -> CoinSet {
+        self.add(coin, None)
+    }
+
+    pub fn limited(self, coin: Coin, count: u32) -> CoinSet {
+        self.add(coin, Some(count))
+    }
+
+    // Adding a coin of a value that's already there adds to how many of
+    // that value there are
+    fn add(mut self, coin: Coin, count: Option<u32>) -> CoinSet {
+        let value: u8 = value_in_cents(coin);
+        match self.coins.iter_mut().find(|(c, _)| value_in_cents(*c) == value)
+        {
+            Some((_, limit)) => {
+                *limit = limit.zip(count).map(|(a, b)| a.saturating_add(b));
+            }
+            None => {
+                self.coins.push((coin, count));
+                self.coins.sort_by_key(|(c, _)| {
+                    std::cmp::Reverse(value_in_cents(*c))
+                });
+            }
+        }
+        self
+    }
+
+    fn value(&self, i: usize) -> u32 {
+        value_in_cents(self.coins[i].0) as u32
+    }
+
+    // How many of coin i can go toward `amount`
+    fn usable(&self, i: usize, amount: u32) -> u32 {
+        let most: u32 = amount / self.value(i);
+        self.coins[i].1.map_or(most, |limit| limit.min(most))
+    }
+
+    fn change(&self, counts: &[u32]) -> Change {
+        self.coins
+            .iter()
+            .zip(counts)
+            .filter(|(_, &count)| count > 0)
+            .map(|(&(coin, _), &count)| (coin, count))
+            .collect()
+    }
+
+    pub fn greedy(&self, amount: u32) -> Option<Change> {
+        let mut left: u32 = amount;
+        let mut counts: Vec<u32> = vec![0; self.coins.len()];
+        for (i, count) in counts.iter_mut().enumerate() {
+            *count = self.usable(i, left);
+            left -= *count * self.value(i);
+        }
+        if left == 0 {
+            Some(self.change(&counts))
+        } else {
+            None
+        }
+    }
+
+    /*
+    * Fewest coins, by dynamic programming. best[a] is the fewest coins
+    * making a from the coins looked at so far, and each coin can lower it
+    * by being used on top of best[a - value]. A limited coin is split
+    * into lots of 1, 2, 4, ... (plus what's left over), each of which is
+    * used at most once, so any count up to the limit can be made from
+    * the lots. `took` records which lots improved which amounts so the
+    * answer can be walked back afterwards.
+    */
+    pub fn fewest(
+        &self,
+        amount: u32,
+    ) -> Result<Option<Change>, ChangeError> {
+        if amount > MAX_AMOUNT {
+            let unlimited: bool =
+                self.coins.iter().all(|(_, limit)| limit.is_none());
+            if unlimited && self.is_canonical() {
+                return Ok(self.greedy(amount));
+            }
+            return Err(ChangeError::TooLarge(amount));
+        }
+        let size: usize = amount as usize + 1;
+        let mut best: Vec<u32> = vec![u32::MAX; size];
+        best[0] = 0;
+        // (coin, lot size or None for unlimited, amounts it improved)
+        let mut lots: Vec<(usize, Option<u32>, Vec<bool>)> = Vec::new();
+
+        for i in 0..self.coins.len() {
+            let value: usize = self.value(i) as usize;
+            let mut sizes: Vec<Option<u32>> = Vec::new();
+            match self.coins[i].1 {
+                None => sizes.push(None),
+                Some(_) => {
+                    let mut left: u32 = self.usable(i, amount);
+                    let mut lot: u32 = 1;
+                    while left > 0 {
+                        sizes.push(Some(lot.min(left)));
+                        left -= lot.min(left);
+                        lot *= 2;
+                    }
+                }
+            }
+            for lot in sizes {
+                let mut took: Vec<bool> = vec![false; size];
+                match lot {
+                    // Going up lets the coin be used again and again
+                    None => {
+                        for a in value..size {
+                            let with: u32 = best[a - value].saturating_add(1);
+                            if with < best[a] {
+                                best[a] = with;
+                                took[a] = true;
+                            }
+                        }
+                    }
+                    // Going down uses the lot at most once
+                    Some(n) => {
+                        let span: usize = value * n as usize;
+                        for a in (span..size).rev() {
+                            let with: u32 = best[a - span].saturating_add(n);
+                            if with < best[a] {
+                                best[a] = with;
+                                took[a] = true;
+                            }
+                        }
+                    }
+                }
+                lots.push((i, lot, took));
+            }
+        }
+
+        if best[amount as usize] == u32::MAX {
+            return Ok(None);
+        }
+        let mut counts: Vec<u32> = vec![0; self.coins.len()];
+        let mut a: usize = amount as usize;
+        for (i, lot, took) in lots.iter().rev() {
+            let value: usize = self.value(*i) as usize;
+            match lot {
+                None => {
+                    while took[a] {
+                        counts[*i] += 1;
+                        a -= value;
+                    }
+                }
+                Some(n) => {
+                    if took[a] {
+                        counts[*i] += n;
+                        a -= value * *n as usize;
+                    }
+                }
+            }
+        }
+        Ok(Some(self.change(&counts)))
+    }
+
+    /*
+    * How many different handfuls of coins add up to `amount`; order
+    * doesn't matter, so a dime and a nickel is the same as a nickel and
+    * a dime. Same idea as `fewest`, counting instead of minimizing: after
+    * each coin, ways[a] is the number of handfuls of the coins so far.
+    * With a limit of k, the new ways[a] is the old ways[a - c * value]
+    * summed over c = 0..=k, kept as a running sum along each stride.
+    */
+    pub fn ways(&self, amount: u32) -> Result<u128, ChangeError> {
+        if amount > MAX_AMOUNT {
+            return Err(ChangeError::TooLarge(amount));
+        }
+        let size: usize = amount as usize + 1;
+        let mut ways: Vec<u128> = vec![0; size];
+        ways[0] = 1;
+        for i in 0..self.coins.len() {
+            let value: usize = self.value(i) as usize;
+            let before: Vec<u128> = ways.clone();
+            let window: Option<usize> = self.coins[i].1
+                .map(|limit| (limit as usize + 1).saturating_mul(value));
+            for a in value..size {
+                ways[a] = ways[a].checked_add(ways[a - value])
+                    .ok_or(ChangeError::Overflow)?;
+                if let Some(window) = window.filter(|&w| w <= a) {
+                    ways[a] -= before[a - window];
+                }
+            }
+        }
+        Ok(ways[amount as usize])
+    }
+
+    /*
+    * The smallest amount where greedy uses more coins than it has to (or
+    * gets stuck when change can be made), or None if greedy always gets
+    * it right. Limits are ignored: this is about the coin values. When
+    * the set has a penny, it's enough to look below the sum of the two
+    * largest coins (Kozen and Zaks, 1994), and the search goes that far
+    * either way.
+    */
+    pub fn greedy_counterexample(&self) -> Option<u32> {
+        let unlimited: CoinSet = CoinSet {
+            coins: self.coins.iter().map(|&(coin, _)| (coin, None)).collect(),
+        };
+        let bound: u32 = match unlimited.coins.len() {
+            0 | 1 => return None,
+            _ => unlimited.value(0) + unlimited.value(1),
+        };
+        let coins = |change: Option<Change>| {
+            change.map(|change| change.iter().map(|(_, n)| n).sum::<u32>())
+        };
+        (1..bound).find(|&amount| {
+            // Values are at most 100 cents, so amount is far under the limit
+            let best: Option<u32> = coins(unlimited.fewest(amount).unwrap());
+            best.is_some() && coins(unlimited.greedy(amount)) != best
+        })
+    }
+
+    pub fn is_canonical(&self) -> bool {
+        self.greedy_counterexample().is_none()
+    }
+}
+
+impl Default for CoinSet {
+    fn default() -> CoinSet {
+        CoinSet::new()
+    }
+}
+
+
+fn describe(change: &Result<Option<Change>, ChangeError>) -> String {
+    match change {
+        Err(err) => err.to_string(),
+        Ok(None) => String::from("can't be done"),
+        Ok(Some(change)) => change
+            .iter()
+            .map(|(coin, n)| format!("{n} x {coin}"))
+            .collect::<Vec<String>>()
+            .join(", "),
+    }
+}
+
+pub fn run() {
+    println!("\nMaking change:");
+    let us: CoinSet = CoinSet::us();
+    println!("  63 cents: {}", describe(&us.fewest(63)));
+    println!("  ways to make a dollar: {}", us.ways(100).unwrap());
+    println!("  US coins greedy-safe: {}", us.is_canonical());
+
+    let odd: CoinSet = CoinSet::new()
+        .coin(Coin::Quarter(UsState::Ohio))
+        .coin(Coin::Dime)
+        .coin(Coin::Penny);
+    if let Some(amount) = odd.greedy_counterexample() {
+        println!("  without nickels, greedy fails at {amount} cents:");
+        println!("    greedy: {}", describe(&Ok(odd.greedy(amount))));
+        println!("    fewest: {}", describe(&odd.fewest(amount)));
+    }
+
+    let drawer: CoinSet = CoinSet::new()
+        .limited(Coin::Quarter(UsState::Texas), 1)
+        .limited(Coin::Dime, 4)
+        .limited(Coin::Penny, 3);
+    println!("  65 cents from 1 quarter, 4 dimes, 3 pennies: {}",
+        describe(&drawer.fewest(65)));
+    println!("  66 cents from the same: {}", describe(&drawer.fewest(66)));
+    println!("  $10,000 in US coins: {}", describe(&us.fewest(1_000_000)));
+    println!("  $10,000 from the drawer: {}",
+        describe(&drawer.fewest(1_000_000)));
+}
+
+
+pub fn register_layouts(registry: &mut Registry) {
+    registry.register("Chapter 6", layout_of!(CoinSet { coins }));
+    registry.register("Chapter 6", layout_of!(ChangeError));
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    const CASES: usize = 300;
+
+    const QUARTER: Coin = Coin::Quarter(UsState::Delaware);
+
+    fn total(change: &Change) -> u32 {
+        change.iter().map(|&(coin, n)| value_in_cents(coin) as u32 * n).sum()
+    }
+
+    fn count(change: &Change) -> u32 {
+        change.iter().map(|(_, n)| n).sum()
+    }
+
+    fn random_set(rng: &mut StdRng) -> CoinSet {
+        let all: [Coin; 6] = [
+            Coin::Penny,
+            Coin::Nickel,
+            Coin::Dime,
+            QUARTER,
+            Coin::HalfDollar,
+            Coin::Dollar,
+        ];
+        let mut set: CoinSet = CoinSet::new();
+        for coin in all {
+            set = match rng.gen_range(0..3) {
+                0 => set,
+                1 => set.coin(coin),
+                _ => set.limited(coin, rng.gen_range(0..5)),
+            };
+        }
+        set
+    }
+
+    // Tries every handful within the limits: (fewest coins, number of ways)
+    fn brute_force(set: &CoinSet, amount: u32) -> (Option<u32>, u128) {
+        fn go(set: &CoinSet, i: usize, left: u32, used: u32,
+              best: &mut Option<u32>, ways: &mut u128) {
+            if i == set.coins.len() {
+                if left == 0 {
+                    *ways += 1;
+                    *best = Some(best.map_or(used, |b| b.min(used)));
+                }
+                return;
+            }
+            for n in 0..=set.usable(i, left) {
+                go(set, i + 1, left - n * set.value(i), used + n, best, ways);
+            }
+        }
+        let (mut best, mut ways) = (None, 0);
+        go(set, 0, amount, 0, &mut best, &mut ways);
+        (best, ways)
+    }
+
+    #[test]
+    fn us_coins() {
+        let us: CoinSet = CoinSet::us();
+        assert_eq!(
+            us.fewest(191),
+            Ok(Some(vec![
+                (Coin::Dollar, 1),
+                (Coin::HalfDollar, 1),
+                (QUARTER, 1),
+                (Coin::Dime, 1),
+                (Coin::Nickel, 1),
+                (Coin::Penny, 1),
+            ]))
+        );
+        assert_eq!(us.fewest(0), Ok(Some(vec![])));
+        assert_eq!(us.ways(100), Ok(293));
+        assert_eq!(us.ways(0), Ok(1));
+        assert!(us.is_canonical());
+    }
+
+    #[test]
+    fn same_value_coins_are_one_denomination() {
+        let mut set: CoinSet = CoinSet::new().coin(Coin::Penny);
+        for state in UsState::all() {
+            set = set.coin(Coin::Quarter(state));
+        }
+        assert_eq!(set.coins.len(), 2);
+        assert_eq!(set.ways(100), Ok(5));
+        assert_eq!(set.ways(2500), Ok(101));
+        let first: Coin = Coin::Quarter(UsState::Alabama);
+        assert_eq!(set.fewest(50), Ok(Some(vec![(first, 2)])));
+
+        let drawer: CoinSet = CoinSet::new()
+            .limited(Coin::Quarter(UsState::Ohio), 1)
+            .limited(QUARTER, 2);
+        assert_eq!(
+            drawer.fewest(75),
+            Ok(Some(vec![(Coin::Quarter(UsState::Ohio), 3)]))
+        );
+    }
+
+    #[test]
+    fn big_amounts() {
+        let us: CoinSet = CoinSet::us();
+        assert_eq!(
+            us.fewest(1_000_000_000),
+            Ok(Some(vec![(Coin::Dollar, 10_000_000)]))
+        );
+        assert_eq!(
+            us.ways(1_000_000_000),
+            Err(ChangeError::TooLarge(1_000_000_000))
+        );
+        let drawer: CoinSet = CoinSet::new().limited(Coin::Dime, 3);
+        assert_eq!(
+            drawer.fewest(MAX_AMOUNT + 1),
+            Err(ChangeError::TooLarge(MAX_AMOUNT + 1))
+        );
+        let odd: CoinSet = CoinSet::new().coin(QUARTER).coin(Coin::Dime);
+        assert!(odd.fewest(MAX_AMOUNT + 1).is_err());
+        assert_eq!(odd.fewest(MAX_AMOUNT), Ok(Some(vec![(QUARTER, 4000)])));
+        assert!(us.ways(MAX_AMOUNT).unwrap() > 0);
+    }
+
+    #[test]
+    fn greedy_can_be_wrong() {
+        let set: CoinSet = CoinSet::new()
+            .coin(QUARTER)
+            .coin(Coin::Dime)
+            .coin(Coin::Penny);
+        assert_eq!(set.greedy_counterexample(), Some(30));
+        assert_eq!(set.fewest(30), Ok(Some(vec![(Coin::Dime, 3)])));
+        assert_eq!(
+            set.greedy(30),
+            Some(vec![(QUARTER, 1), (Coin::Penny, 5)])
+        );
+
+        // Greedy gets stuck on 30 with a quarter first and no pennies
+        let stuck: CoinSet = CoinSet::new().coin(QUARTER).coin(Coin::Dime);
+        assert_eq!(stuck.greedy(30), None);
+        assert_eq!(stuck.greedy_counterexample(), Some(30));
+    }
+
+    #[test]
+    fn limits_are_respected() {
+        let drawer: CoinSet = CoinSet::new()
+            .limited(QUARTER, 1)
+            .limited(Coin::Dime, 4)
+            .limited(Coin::Penny, 3);
+        assert_eq!(
+            drawer.fewest(65),
+            Ok(Some(vec![(QUARTER, 1), (Coin::Dime, 4)]))
+        );
+        assert_eq!(drawer.fewest(66), Ok(Some(vec![
+            (QUARTER, 1),
+            (Coin::Dime, 4),
+            (Coin::Penny, 1),
+        ])));
+        assert_eq!(drawer.fewest(69), Ok(None));
+        assert_eq!(drawer.ways(30), Ok(1));
+
+        let doubled: CoinSet = CoinSet::new()
+            .limited(Coin::Dime, 1)
+            .limited(Coin::Dime, 2);
+        assert_eq!(doubled.fewest(30), Ok(Some(vec![(Coin::Dime, 3)])));
+    }
+
+    #[test]
+    fn random_sets_match_brute_force() {
+        let mut rng: StdRng = StdRng::seed_from_u64(1);
+        for _ in 0..CASES {
+            let set: CoinSet = random_set(&mut rng);
+            let amount: u32 = rng.gen_range(0..120);
+            let (best, ways) = brute_force(&set, amount);
+            let fewest: Option<Change> = set.fewest(amount).unwrap();
+            assert_eq!(fewest.as_ref().map(count), best, "{set:?} {amount}");
+            if let Some(change) = &fewest {
+                assert_eq!(total(change), amount);
+            }
+            assert_eq!(set.ways(amount), Ok(ways), "{set:?} {amount}");
+            if let Some(greedy) = set.greedy(amount) {
+                assert_eq!(total(&greedy), amount);
+                assert!(count(&greedy) >= best.unwrap());
+            }
+        }
+    }
+}
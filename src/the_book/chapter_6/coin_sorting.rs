@@ -1,38 +1,294 @@
-#[derive(Debug)]
-enum UsState {
+use std::fmt;
+use std::str::FromStr;
+
+use crate::layout::{layout_of, Registry};
+
+// The 50 states in alphabetical order, then DC and the five territories
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum UsState {
     Alabama,
     Alaska,
     Arizona,
     Arkansas,
     California,
-    Colorodo,
-    Conneticut
+    Colorado,
+    Connecticut,
+    Delaware,
+    Florida,
+    Georgia,
+    Hawaii,
+    Idaho,
+    Illinois,
+    Indiana,
+    Iowa,
+    Kansas,
+    Kentucky,
+    Louisiana,
+    Maine,
+    Maryland,
+    Massachusetts,
+    Michigan,
+    Minnesota,
+    Mississippi,
+    Missouri,
+    Montana,
+    Nebraska,
+    Nevada,
+    NewHampshire,
+    NewJersey,
+    NewMexico,
+    NewYork,
+    NorthCarolina,
+    NorthDakota,
+    Ohio,
+    Oklahoma,
+    Oregon,
+    Pennsylvania,
+    RhodeIsland,
+    SouthCarolina,
+    SouthDakota,
+    Tennessee,
+    Texas,
+    Utah,
+    Vermont,
+    Virginia,
+    Washington,
+    WestVirginia,
+    Wisconsin,
+    Wyoming,
+    DistrictOfColumbia,
+    AmericanSamoa,
+    Guam,
+    NorthernMarianaIslands,
+    PuertoRico,
+    VirginIslands,
+}
+
+/*
+* Postal code, full name and the year the state's quarter came out. The
+* 50 State Quarters ran five states a year from 1999 to 2008, and DC and
+* the territories followed in 2009. Rows are in the same order as the
+* enum, so a state's row is at `state as usize`.
+*/
+const STATES: [(UsState, &str, &str, u16); 56] = [
+    (UsState::Alabama, "AL", "Alabama", 2003),
+    (UsState::Alaska, "AK", "Alaska", 2008),
+    (UsState::Arizona, "AZ", "Arizona", 2008),
+    (UsState::Arkansas, "AR", "Arkansas", 2003),
+    (UsState::California, "CA", "California", 2005),
+    (UsState::Colorado, "CO", "Colorado", 2006),
+    (UsState::Connecticut, "CT", "Connecticut", 1999),
+    (UsState::Delaware, "DE", "Delaware", 1999),
+    (UsState::Florida, "FL", "Florida", 2004),
+    (UsState::Georgia, "GA", "Georgia", 1999),
+    (UsState::Hawaii, "HI", "Hawaii", 2008),
+    (UsState::Idaho, "ID", "Idaho", 2007),
+    (UsState::Illinois, "IL", "Illinois", 2003),
+    (UsState::Indiana, "IN", "Indiana", 2002),
+    (UsState::Iowa, "IA", "Iowa", 2004),
+    (UsState::Kansas, "KS", "Kansas", 2005),
+    (UsState::Kentucky, "KY", "Kentucky", 2001),
+    (UsState::Louisiana, "LA", "Louisiana", 2002),
+    (UsState::Maine, "ME", "Maine", 2003),
+    (UsState::Maryland, "MD", "Maryland", 2000),
+    (UsState::Massachusetts, "MA", "Massachusetts", 2000),
+    (UsState::Michigan, "MI", "Michigan", 2004),
+    (UsState::Minnesota, "MN", "Minnesota", 2005),
+    (UsState::Mississippi, "MS", "Mississippi", 2002),
+    (UsState::Missouri, "MO", "Missouri", 2003),
+    (UsState::Montana, "MT", "Montana", 2007),
+    (UsState::Nebraska, "NE", "Nebraska", 2006),
+    (UsState::Nevada, "NV", "Nevada", 2006),
+    (UsState::NewHampshire, "NH", "New Hampshire", 2000),
+    (UsState::NewJersey, "NJ", "New Jersey", 1999),
+    (UsState::NewMexico, "NM", "New Mexico", 2008),
+    (UsState::NewYork, "NY", "New York", 2001),
+    (UsState::NorthCarolina, "NC", "North Carolina", 2001),
+    (UsState::NorthDakota, "ND", "North Dakota", 2006),
+    (UsState::Ohio, "OH", "Ohio", 2002),
+    (UsState::Oklahoma, "OK", "Oklahoma", 2008),
+    (UsState::Oregon, "OR", "Oregon", 2005),
+    (UsState::Pennsylvania, "PA", "Pennsylvania", 1999),
+    (UsState::RhodeIsland, "RI", "Rhode Island", 2001),
+    (UsState::SouthCarolina, "SC", "South Carolina", 2000),
+    (UsState::SouthDakota, "SD", "South Dakota", 2006),
+    (UsState::Tennessee, "TN", "Tennessee", 2002),
+    (UsState::Texas, "TX", "Texas", 2004),
+    (UsState::Utah, "UT", "Utah", 2007),
+    (UsState::Vermont, "VT", "Vermont", 2001),
+    (UsState::Virginia, "VA", "Virginia", 2000),
+    (UsState::Washington, "WA", "Washington", 2007),
+    (UsState::WestVirginia, "WV", "West Virginia", 2005),
+    (UsState::Wisconsin, "WI", "Wisconsin", 2004),
+    (UsState::Wyoming, "WY", "Wyoming", 2007),
+    (UsState::DistrictOfColumbia, "DC", "District of Columbia", 2009),
+    (UsState::AmericanSamoa, "AS", "American Samoa", 2009),
+    (UsState::Guam, "GU", "Guam", 2009),
+    (UsState::NorthernMarianaIslands, "MP", "Northern Mariana Islands", 2009),
+    (UsState::PuertoRico, "PR", "Puerto Rico", 2009),
+    (UsState::VirginIslands, "VI", "U.S. Virgin Islands", 2009),
+];
+
+impl UsState {
+
+    pub fn all() -> impl Iterator<Item = UsState> {
+        STATES.iter().map(|row| row.0)
+    }
+
+    fn row(self) -> &'static (UsState, &'static str, &'static str, u16) {
+        &STATES[self as usize]
+    }
+
+    pub fn postal_code(self) -> &'static str {
+        self.row().1
+    }
+
+    pub fn name(self) -> &'static str {
+        self.row().2
+    }
+
+    pub fn quarter_year(self) -> u16 {
+        self.row().3
+    }
+
+    pub fn is_state(self) -> bool {
+        (self as usize) < 50
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseStateError(String);
+
+impl fmt::Display for ParseStateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?} is not a US state, territory or postal code", self.0)
+    }
+}
+
+// Takes a postal code or a full name, in any case: "co", "Colorado"
+impl FromStr for UsState {
+    type Err = ParseStateError;
+
+    fn from_str(text: &str) -> Result<UsState, ParseStateError> {
+        let wanted: String = text
+            .split_whitespace()
+            .collect::<Vec<&str>>()
+            .join(" ");
+        STATES
+            .iter()
+            .find(|(_, code, name, _)| {
+                wanted.eq_ignore_ascii_case(code)
+                    || wanted.eq_ignore_ascii_case(name)
+            })
+            .map(|row| row.0)
+            .ok_or_else(|| ParseStateError(text.to_string()))
+    }
+}
+
+impl fmt::Display for UsState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
 }
 
-enum Coin {
+
+// A state quarter carries its state; the year it came out follows from
+// that, see UsState::quarter_year
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Coin {
     Penny,
     Nickel,
     Dime,
-    Quarter(UsState)
+    Quarter(UsState),
+    HalfDollar,
+    Dollar,
 }
 
-fn value_in_cents(coin: Coin) -> u8 {
-    match coin {
-        Coin::Penny => {
-            println!("Lucky penny");
-            1
+impl fmt::Display for Coin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Coin::Penny => write!(f, "penny"),
+            Coin::Nickel => write!(f, "nickel"),
+            Coin::Dime => write!(f, "dime"),
+            Coin::Quarter(state) => {
+                write!(f, "{} {state} quarter", state.quarter_year())
+            }
+            Coin::HalfDollar => write!(f, "half dollar"),
+            Coin::Dollar => write!(f, "dollar coin"),
         }
+    }
+}
+
+pub fn value_in_cents(coin: Coin) -> u8 {
+    match coin {
+        Coin::Penny => 1,
         Coin::Nickel => 5,
         Coin::Dime => 10,
-        Coin::Quarter(state) => {
-            println!("State quarter from {state:?}!");
-            25
-        }
+        Coin::Quarter(..) => 25,
+        Coin::HalfDollar => 50,
+        Coin::Dollar => 100,
     }
 }
 
 pub fn run() {
     let coin: Coin = Coin::Quarter(UsState::Arkansas);
+    if let Coin::Quarter(state) = coin {
+        let code: &str = state.postal_code();
+        let year: u16 = state.quarter_year();
+        println!("State quarter from {state} ({code}), {year}!");
+    }
     println!("Value: {}", value_in_cents(coin));
+
+    for text in ["co", "Connecticut", "puerto  rico", "Colorodo"] {
+        match text.parse::<UsState>() {
+            Ok(state) => println!("  {text:?} -> {state}"),
+            Err(err) => println!("  {err}"),
+        }
+    }
 }
 
+
+pub fn register_layouts(registry: &mut Registry) {
+    registry.register("Chapter 6", layout_of!(Coin));
+    registry.register("Chapter 6", layout_of!(UsState));
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn table_matches_the_enum() {
+        for (i, state) in UsState::all().enumerate() {
+            assert_eq!(state as usize, i);
+            assert_eq!(state.postal_code().parse(), Ok(state));
+            assert_eq!(state.to_string().to_uppercase().parse(), Ok(state));
+        }
+        assert_eq!(UsState::all().filter(|s| s.is_state()).count(), 50);
+        for year in 1999..=2008 {
+            let count: usize = UsState::all()
+                .filter(|s| s.is_state() && s.quarter_year() == year)
+                .count();
+            assert_eq!(count, 5, "{year}");
+        }
+    }
+
+    #[test]
+    fn misspellings_are_rejected() {
+        assert!("Colorodo".parse::<UsState>().is_err());
+        assert!("Conneticut".parse::<UsState>().is_err());
+        assert_eq!("new   york".parse(), Ok(UsState::NewYork));
+    }
+
+    #[test]
+    fn quarters_show_their_year() {
+        let coin: Coin = Coin::Quarter(UsState::DistrictOfColumbia);
+        assert_eq!(coin.to_string(), "2009 District of Columbia quarter");
+        assert_eq!(
+            Coin::Quarter(UsState::Delaware).to_string(),
+            "1999 Delaware quarter"
+        );
+        assert_eq!(value_in_cents(coin), 25);
+    }
+}
@@ -0,0 +1,807 @@
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::cidr::{self, Cidr, CidrError};
+use super::ip_addr::IpAddr;
+use crate::layout::{layout_of, Registry};
+
+
+/*
+* A DHCP-style address pool. Clients (identified by a MAC address or any
+* other string) ask for an address and get a lease on one for a fixed
+* number of seconds. A client that comes back gets the same address again
+* if it can, and an address whose lease ran out can go to someone else.
+*
+*   ranges        the blocks addresses are handed out from
+*   exclusions    addresses inside the ranges that are never handed out
+*                 (routers, printers with hand-set addresses...)
+*   reservations  addresses that only ever go to one particular client
+*
+* Time comes from a Clock, so tests can move it forward by hand instead of
+* sleeping.
+*/
+pub trait Clock {
+    // Seconds since the Unix epoch
+    fn now(&self) -> u64;
+}
+
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0)
+    }
+}
+
+
+// A clock that only moves when told to. Clones share the same time, so a
+// test can keep one and hand the other to the pool.
+#[derive(Debug, Clone, Default)]
+pub struct ManualClock {
+    now: Rc<Cell<u64>>,
+}
+
+impl ManualClock {
+
+    pub fn new(now: u64) -> ManualClock {
+        ManualClock { now: Rc::new(Cell::new(now)) }
+    }
+
+    pub fn advance(&self, seconds: u64) {
+        self.now.set(self.now.get().saturating_add(seconds));
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> u64 {
+        self.now.get()
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lease {
+    pub address: IpAddr,
+    pub client: String,
+    pub expires: u64,
+}
+
+
+#[derive(Debug)]
+pub enum PoolError {
+    NotInPool(IpAddr),
+    Excluded(IpAddr),
+    Reserved { address: IpAddr, client: String },
+    Exhausted,
+    UnknownClient(String),
+    Io(io::Error),
+    Parse { line: usize, reason: String },
+}
+
+impl fmt::Display for PoolError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PoolError::NotInPool(address) => {
+                write!(f, "{address} isn't in any of the pool's ranges")
+            }
+            PoolError::Excluded(address) => {
+                write!(f, "{address} is excluded from the pool")
+            }
+            PoolError::Reserved { address, client } => {
+                write!(f, "{address} is reserved for {client}")
+            }
+            PoolError::Exhausted => write!(f, "no free addresses left"),
+            PoolError::UnknownClient(client) => {
+                write!(f, "{client} has no lease")
+            }
+            PoolError::Io(err) => write!(f, "{err}"),
+            PoolError::Parse { line, reason } => {
+                write!(f, "line {line}: {reason}")
+            }
+        }
+    }
+}
+
+impl From<io::Error> for PoolError {
+    fn from(err: io::Error) -> PoolError {
+        PoolError::Io(err)
+    }
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Stats {
+    pub total: u128,
+    pub excluded: u128,
+    pub reserved: u128,
+    pub leased: u128,
+    pub free: u128,
+}
+
+impl Stats {
+
+    // The share of usable addresses that are reserved or leased, 0.0..=1.0
+    pub fn utilization(&self) -> f64 {
+        let usable: u128 = self.total.saturating_sub(self.excluded);
+        if usable == 0 {
+            return 1.0;
+        }
+        (self.reserved + self.leased) as f64 / usable as f64
+    }
+}
+
+
+pub struct Pool<C: Clock> {
+    ranges: Vec<Cidr>,
+    exclusions: Vec<Cidr>,
+    reservations: HashMap<String, IpAddr>,
+    leases: HashMap<IpAddr, Lease>,
+    by_client: HashMap<String, IpAddr>,
+    lease_time: u64,
+    clock: C,
+}
+
+
+impl<C: Clock> Pool<C> {
+
+    // Overlapping ranges are merged, so no address is counted twice
+    pub fn new(ranges: &[Cidr], lease_time: u64, clock: C) -> Pool<C> {
+        Pool {
+            ranges: cidr::merge(ranges.iter().copied()),
+            exclusions: Vec::new(),
+            reservations: HashMap::new(),
+            leases: HashMap::new(),
+            by_client: HashMap::new(),
+            lease_time,
+            clock,
+        }
+    }
+
+    // Reservations and leases on addresses inside the block are dropped,
+    // since those addresses can't be handed out any more
+    pub fn exclude(&mut self, block: Cidr) {
+        self.exclusions.push(block);
+        self.exclusions = cidr::merge(self.exclusions.iter().copied());
+        self.reservations.retain(|_, address| !block.contains(*address));
+        let evicted: Vec<String> = self.leases.values()
+            .filter(|lease| block.contains(lease.address))
+            .map(|lease| lease.client.clone())
+            .collect();
+        for client in evicted {
+            self.remove_lease(&client);
+        }
+    }
+
+    // Whoever held a lease on the address loses it at once
+    pub fn reserve(
+        &mut self,
+        client: &str,
+        address: IpAddr,
+    ) -> Result<(), PoolError> {
+        self.check_usable(address)?;
+        if let Some(owner) = self.reserved_for(address) {
+            if owner != client {
+                return Err(PoolError::Reserved {
+                    address,
+                    client: owner.to_string(),
+                });
+            }
+        }
+        if let Some(lease) = self.leases.get(&address) {
+            if lease.client != client {
+                let other: String = lease.client.clone();
+                self.remove_lease(&other);
+            }
+        }
+        self.reservations.insert(client.to_string(), address);
+        Ok(())
+    }
+
+    /*
+    * Hands the client an address, in this order of preference:
+    *
+    *   1. its reservation
+    *   2. the address it had last time, if nobody else has taken it since
+    *   3. the lowest free address in the pool
+    *
+    * Asking again before the lease runs out just renews it.
+    */
+    pub fn allocate(&mut self, client: &str) -> Result<Lease, PoolError> {
+        let now: u64 = self.clock.now();
+        let address: IpAddr = match self.reservations.get(client) {
+            Some(address) => *address,
+            None => match self.by_client.get(client) {
+                Some(address) if self.is_free_for(*address, client, now) => {
+                    *address
+                }
+                _ => self.find_free(client, now)?,
+            },
+        };
+        self.remove_lease(client);
+        if let Some(stale) = self.leases.get(&address) {
+            let stale: String = stale.client.clone();
+            self.remove_lease(&stale);
+        }
+        let lease: Lease = Lease {
+            address,
+            client: client.to_string(),
+            expires: now.saturating_add(self.lease_time),
+        };
+        self.leases.insert(address, lease.clone());
+        self.by_client.insert(client.to_string(), address);
+        Ok(lease)
+    }
+
+    // Extends a lease the client already holds. An expired lease can still
+    // be renewed as long as no one else took the address in the meantime.
+    pub fn renew(&mut self, client: &str) -> Result<Lease, PoolError> {
+        let now: u64 = self.clock.now();
+        let address: IpAddr = *self.by_client.get(client)
+            .ok_or_else(|| PoolError::UnknownClient(client.to_string()))?;
+        let lease: &mut Lease = self.leases.get_mut(&address)
+            .ok_or_else(|| PoolError::UnknownClient(client.to_string()))?;
+        lease.expires = now.saturating_add(self.lease_time);
+        Ok(lease.clone())
+    }
+
+    pub fn release(&mut self, client: &str) -> Result<IpAddr, PoolError> {
+        self.remove_lease(client)
+            .ok_or_else(|| PoolError::UnknownClient(client.to_string()))
+    }
+
+    // The lease on an address, if it hasn't run out
+    pub fn lease_of(&self, address: IpAddr) -> Option<&Lease> {
+        let now: u64 = self.clock.now();
+        self.leases.get(&address).filter(|lease| lease.expires > now)
+    }
+
+    // Drops every lease that has run out and returns them, oldest first
+    pub fn expire(&mut self) -> Vec<Lease> {
+        let now: u64 = self.clock.now();
+        let mut expired: Vec<Lease> = self.leases.values()
+            .filter(|lease| lease.expires <= now)
+            .cloned()
+            .collect();
+        expired.sort_by_key(|lease| (lease.expires, lease.address));
+        for lease in &expired {
+            self.remove_lease(&lease.client);
+        }
+        expired
+    }
+
+    pub fn stats(&self) -> Stats {
+        let now: u64 = self.clock.now();
+        let total: u128 = self.ranges.iter().map(|r| r.host_count()).sum();
+        let excluded: u128 = self.ranges.iter()
+            .map(|range| self.excluded_in(range))
+            .sum();
+        let reserved: u128 = self.reservations.len() as u128;
+        let leased: u128 = self.leases.values()
+            .filter(|lease| lease.expires > now)
+            .filter(|lease| !self.reservations.contains_key(&lease.client))
+            .count() as u128;
+        Stats {
+            total,
+            excluded,
+            reserved,
+            leased,
+            free: total
+                .saturating_sub(excluded)
+                .saturating_sub(reserved)
+                .saturating_sub(leased),
+        }
+    }
+
+    fn excluded_in(&self, range: &Cidr) -> u128 {
+        let mut count: u128 = 0;
+        for block in &self.exclusions {
+            if range.contains_cidr(block) {
+                count += block.address_count();
+                // The network and broadcast addresses weren't usable
+                // to begin with
+                if range.broadcast().is_some() {
+                    count -= block.contains(range.network()) as u128;
+                    count -= block.contains(range.last()) as u128;
+                }
+            } else if block.contains_cidr(range) {
+                count += range.host_count();
+            }
+        }
+        count
+    }
+
+    fn check_usable(&self, address: IpAddr) -> Result<(), PoolError> {
+        let usable: bool = self.ranges.iter().any(|range| {
+            range.contains(address)
+                && (range.first_host()..=range.last_host()).contains(&address)
+        });
+        if !usable {
+            return Err(PoolError::NotInPool(address));
+        }
+        if self.exclusions.iter().any(|block| block.contains(address)) {
+            return Err(PoolError::Excluded(address));
+        }
+        Ok(())
+    }
+
+    fn reserved_for(&self, address: IpAddr) -> Option<&str> {
+        self.reservations.iter()
+            .find(|(_, reserved)| **reserved == address)
+            .map(|(client, _)| client.as_str())
+    }
+
+    fn is_free_for(&self, address: IpAddr, client: &str, now: u64) -> bool {
+        if self.check_usable(address).is_err() {
+            return false;
+        }
+        if self.reserved_for(address).is_some_and(|owner| owner != client) {
+            return false;
+        }
+        match self.leases.get(&address) {
+            Some(lease) => lease.client == client || lease.expires <= now,
+            None => true,
+        }
+    }
+
+    // Hosts are walked in order, jumping straight past any excluded block,
+    // so a big range with a few leases and big exclusions doesn't cost
+    // more than a small one.
+    fn find_free(&self, client: &str, now: u64) -> Result<IpAddr, PoolError> {
+        for range in &self.ranges {
+            let last: u128 = range.last_host().to_bits();
+            let mut bits: u128 = range.first_host().to_bits();
+            loop {
+                let address: IpAddr = range.network().with_bits(bits);
+                let skip_to: u128 = match self.exclusions.iter()
+                    .find(|block| block.contains(address))
+                {
+                    Some(block) => block.last().to_bits(),
+                    None if self.is_free_for(address, client, now) => {
+                        return Ok(address);
+                    }
+                    None => bits,
+                };
+                if skip_to >= last {
+                    break;
+                }
+                bits = skip_to + 1;
+            }
+        }
+        Err(PoolError::Exhausted)
+    }
+
+    // Whether a lease read back from a file fits the pool as it stands
+    fn check_lease(&self, lease: &Lease) -> Result<(), String> {
+        self.check_usable(lease.address).map_err(|err| err.to_string())?;
+        if let Some(owner) = self.reserved_for(lease.address) {
+            if owner != lease.client {
+                return Err(format!("{} is reserved for {owner}",
+                    lease.address));
+            }
+        }
+        if self.by_client.contains_key(&lease.client) {
+            return Err(format!("{} has two leases", lease.client));
+        }
+        if let Some(other) = self.leases.get(&lease.address) {
+            return Err(format!("{} is leased to both {} and {}",
+                lease.address, other.client, lease.client));
+        }
+        Ok(())
+    }
+
+    fn remove_lease(&mut self, client: &str) -> Option<IpAddr> {
+        let address: IpAddr = self.by_client.remove(client)?;
+        self.leases.remove(&address);
+        Some(address)
+    }
+
+
+    /*
+    * One setting per line, sorted so the file diffs nicely:
+    *
+    *   lease-time 3600
+    *   range 192.168.1.0/24
+    *   exclude 192.168.1.0/28
+    *   reserve aa:bb:cc:dd:ee:01 192.168.1.50
+    *   lease 192.168.1.16 aa:bb:cc:dd:ee:02 1700003600
+    */
+    pub fn to_text(&self) -> String {
+        let mut out: String = format!("lease-time {}\n", self.lease_time);
+        for range in &self.ranges {
+            out.push_str(&format!("range {range}\n"));
+        }
+        for block in &self.exclusions {
+            out.push_str(&format!("exclude {block}\n"));
+        }
+        let mut reservations: Vec<(&String, &IpAddr)> =
+            self.reservations.iter().collect();
+        reservations.sort_by_key(|(_, address)| **address);
+        for (client, address) in reservations {
+            out.push_str(&format!("reserve {client} {address}\n"));
+        }
+        let mut leases: Vec<&Lease> = self.leases.values().collect();
+        leases.sort_by_key(|lease| lease.address);
+        for lease in leases {
+            out.push_str(&format!(
+                "lease {} {} {}\n",
+                lease.address, lease.client, lease.expires
+            ));
+        }
+        out
+    }
+
+    pub fn parse(text: &str, clock: C) -> Result<Pool<C>, PoolError> {
+        let mut pool: Pool<C> = Pool::new(&[], 0, clock);
+        for (i, line) in text.lines().enumerate() {
+            let parse_error = |reason: String| PoolError::Parse {
+                line: i + 1,
+                reason,
+            };
+            let cidr = |text: &str| {
+                text.parse::<Cidr>()
+                    .map_err(|err: CidrError| parse_error(err.to_string()))
+            };
+            let address = |text: &str| {
+                text.parse::<IpAddr>()
+                    .map_err(|err| parse_error(err.to_string()))
+            };
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words.as_slice() {
+                [] => {}
+                ["lease-time", seconds] => {
+                    pool.lease_time = seconds.parse()
+                        .map_err(|_| parse_error(format!("bad lease time \
+                            {seconds:?}")))?;
+                }
+                ["range", block] => {
+                    pool.ranges.push(cidr(block)?);
+                    pool.ranges = cidr::merge(pool.ranges.iter().copied());
+                }
+                ["exclude", block] => pool.exclude(cidr(block)?),
+                ["reserve", client, ip] => {
+                    if pool.reservations.contains_key(*client) {
+                        return Err(parse_error(format!("{client} is \
+                            reserved twice")));
+                    }
+                    pool.reserve(client, address(ip)?)
+                        .map_err(|err| parse_error(err.to_string()))?;
+                }
+                ["lease", ip, client, expires] => {
+                    let lease: Lease = Lease {
+                        address: address(ip)?,
+                        client: client.to_string(),
+                        expires: expires.parse()
+                            .map_err(|_| parse_error(format!("bad expiry \
+                                time {expires:?}")))?,
+                    };
+                    pool.check_lease(&lease).map_err(parse_error)?;
+                    pool.by_client.insert(lease.client.clone(), lease.address);
+                    pool.leases.insert(lease.address, lease);
+                }
+                _ => return Err(parse_error(format!("can't read {line:?}"))),
+            }
+        }
+        Ok(pool)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), PoolError> {
+        fs::write(path, self.to_text())?;
+        Ok(())
+    }
+
+    pub fn load(path: &Path, clock: C) -> Result<Pool<C>, PoolError> {
+        let text: String = fs::read_to_string(path)?;
+        Pool::parse(&text, clock)
+    }
+}
+
+
+pub fn run() {
+    println!("\nAddress pool:");
+    let clock: ManualClock = ManualClock::new(1_700_000_000);
+    let range: Cidr = "192.168.1.0/28".parse().unwrap();
+    let mut pool: Pool<ManualClock> = Pool::new(&[range], 3600, clock.clone());
+    pool.exclude("192.168.1.0/30".parse().unwrap());
+    pool.reserve("aa:bb:cc:00:00:01", "192.168.1.10".parse().unwrap())
+        .unwrap();
+
+    for client in ["aa:bb:cc:00:00:01", "laptop", "phone", "tv"] {
+        let lease: Lease = pool.allocate(client).unwrap();
+        println!("  {client:<18} -> {}", lease.address);
+    }
+    let stats: Stats = pool.stats();
+    println!("  {stats:?}, {:.0}% used", stats.utilization() * 100.0);
+
+    clock.advance(3600);
+    pool.renew("laptop").unwrap();
+    let expired: Vec<String> = pool.expire().iter()
+        .map(|lease| lease.client.clone())
+        .collect();
+    println!("  After an hour, expired: {expired:?}");
+    let printer: Lease = pool.allocate("printer").unwrap();
+    println!("  New client gets: {}", printer.address);
+
+    let text: String = pool.to_text();
+    println!("  Saved state:");
+    for line in text.lines() {
+        println!("    {line}");
+    }
+}
+
+
+pub fn register_layouts(registry: &mut Registry) {
+    registry.register(
+        "Chapter 6",
+        layout_of!(Lease { address, client, expires }),
+    );
+    registry.register("Chapter 6", layout_of!(PoolError));
+    registry.register("Chapter 6", layout_of!(Stats {
+        total, excluded, reserved, leased, free
+    }));
+    registry.register("Chapter 6", layout_of!(Pool<ManualClock> {
+        ranges, exclusions, reservations, leases, by_client, lease_time,
+        clock
+    }));
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const START: u64 = 1_700_000_000;
+
+    fn ip(text: &str) -> IpAddr {
+        text.parse().unwrap()
+    }
+
+    fn pool(range: &str) -> (Pool<ManualClock>, ManualClock) {
+        let clock: ManualClock = ManualClock::new(START);
+        let pool: Pool<ManualClock> =
+            Pool::new(&[range.parse().unwrap()], 600, clock.clone());
+        (pool, clock)
+    }
+
+    #[test]
+    fn hands_out_lowest_free_hosts() {
+        let (mut pool, _) = pool("10.0.0.0/29");
+        pool.exclude(Cidr::host(ip("10.0.0.2")));
+        let got: Vec<IpAddr> = ["a", "b", "c"].iter()
+            .map(|c| pool.allocate(c).unwrap().address)
+            .collect();
+        assert_eq!(got, [ip("10.0.0.1"), ip("10.0.0.3"), ip("10.0.0.4")]);
+        assert_eq!(pool.lease_of(ip("10.0.0.3")).unwrap().client, "b");
+    }
+
+    #[test]
+    fn same_client_keeps_its_address() {
+        let (mut pool, clock) = pool("10.0.0.0/29");
+        let first: Lease = pool.allocate("a").unwrap();
+        pool.allocate("b").unwrap();
+        clock.advance(100);
+        let again: Lease = pool.allocate("a").unwrap();
+        assert_eq!(again.address, first.address);
+        assert_eq!(again.expires, START + 700);
+        assert_eq!(pool.stats().leased, 2);
+    }
+
+    #[test]
+    fn reservations_go_only_to_their_client() {
+        let (mut pool, _) = pool("10.0.0.0/30");
+        pool.reserve("printer", ip("10.0.0.1")).unwrap();
+        assert_eq!(pool.allocate("laptop").unwrap().address, ip("10.0.0.2"));
+        assert!(matches!(pool.allocate("phone"), Err(PoolError::Exhausted)));
+        assert_eq!(pool.allocate("printer").unwrap().address, ip("10.0.0.1"));
+        assert!(matches!(
+            pool.reserve("phone", ip("10.0.0.1")),
+            Err(PoolError::Reserved { .. })
+        ));
+    }
+
+    #[test]
+    fn reserving_takes_over_a_leased_address() {
+        let (mut pool, _) = pool("10.0.0.0/29");
+        pool.allocate("laptop").unwrap();
+        pool.reserve("printer", ip("10.0.0.1")).unwrap();
+        assert!(pool.lease_of(ip("10.0.0.1")).is_none());
+        assert_eq!(pool.allocate("laptop").unwrap().address, ip("10.0.0.2"));
+    }
+
+    #[test]
+    fn rejects_addresses_outside_the_pool() {
+        let (mut pool, _) = pool("10.0.0.0/29");
+        pool.exclude("10.0.0.4/30".parse().unwrap());
+        assert!(matches!(
+            pool.reserve("x", ip("10.0.1.1")),
+            Err(PoolError::NotInPool(_))
+        ));
+        assert!(matches!(
+            pool.reserve("x", ip("10.0.0.0")),
+            Err(PoolError::NotInPool(_))
+        ));
+        assert!(matches!(
+            pool.reserve("x", ip("10.0.0.5")),
+            Err(PoolError::Excluded(_))
+        ));
+    }
+
+    #[test]
+    fn expired_leases_are_reclaimed() {
+        let (mut pool, clock) = pool("10.0.0.0/30");
+        pool.allocate("a").unwrap();
+        pool.allocate("b").unwrap();
+        assert!(matches!(pool.allocate("c"), Err(PoolError::Exhausted)));
+
+        clock.advance(300);
+        pool.renew("b").unwrap();
+        clock.advance(300);
+        assert!(pool.lease_of(ip("10.0.0.1")).is_none());
+        assert_eq!(pool.allocate("c").unwrap().address, ip("10.0.0.1"));
+        assert!(matches!(pool.renew("a"), Err(PoolError::UnknownClient(_))));
+
+        clock.advance(600);
+        let expired: Vec<Lease> = pool.expire();
+        assert_eq!(expired.len(), 2);
+        assert_eq!(pool.stats().leased, 0);
+    }
+
+    #[test]
+    fn release_frees_the_address() {
+        let (mut pool, _) = pool("10.0.0.0/30");
+        pool.allocate("a").unwrap();
+        assert_eq!(pool.release("a").unwrap(), ip("10.0.0.1"));
+        assert!(matches!(pool.release("a"), Err(PoolError::UnknownClient(_))));
+        assert_eq!(pool.allocate("b").unwrap().address, ip("10.0.0.1"));
+    }
+
+    #[test]
+    fn stats_and_utilization() {
+        let (mut pool, _) = pool("10.0.0.0/24");
+        pool.exclude("10.0.0.0/28".parse().unwrap());
+        pool.reserve("printer", ip("10.0.0.100")).unwrap();
+        for client in ["a", "b", "c"] {
+            pool.allocate(client).unwrap();
+        }
+        let stats: Stats = pool.stats();
+        // 254 hosts, 15 of them excluded (the network address already
+        // wasn't one)
+        assert_eq!(stats, Stats {
+            total: 254,
+            excluded: 15,
+            reserved: 1,
+            leased: 3,
+            free: 235,
+        });
+        assert!((stats.utilization() - 4.0 / 239.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn several_ranges_and_ipv6() {
+        let clock: ManualClock = ManualClock::new(START);
+        let ranges: [Cidr; 2] = [
+            "10.0.0.0/31".parse().unwrap(),
+            "2001:db8::/126".parse().unwrap(),
+        ];
+        let mut pool: Pool<ManualClock> = Pool::new(&ranges, 60, clock);
+        let got: Vec<String> = ["a", "b", "c", "d"].iter()
+            .map(|c| pool.allocate(c).unwrap().address.to_string())
+            .collect();
+        assert_eq!(got, ["10.0.0.0", "10.0.0.1", "2001:db8::", "2001:db8::1"]);
+        assert_eq!(pool.stats().total, 6);
+    }
+
+    #[test]
+    fn saves_and_loads() {
+        let (mut pool, clock) = pool("192.168.1.0/28");
+        pool.exclude("192.168.1.0/30".parse().unwrap());
+        pool.reserve("printer", ip("192.168.1.10")).unwrap();
+        pool.allocate("laptop").unwrap();
+        pool.allocate("phone").unwrap();
+
+        let path = std::env::temp_dir()
+            .join(format!("notes-dhcp-{}.txt", std::process::id()));
+        pool.save(&path).unwrap();
+        let mut loaded: Pool<ManualClock> =
+            Pool::load(&path, clock.clone()).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.to_text(), pool.to_text());
+        assert_eq!(loaded.stats(), pool.stats());
+        assert_eq!(
+            loaded.allocate("laptop").unwrap().address,
+            pool.allocate("laptop").unwrap().address
+        );
+    }
+
+    #[test]
+    fn excluding_drops_reservations_and_leases() {
+        let (mut pool, _) = pool("10.0.0.0/30");
+        pool.reserve("p", ip("10.0.0.1")).unwrap();
+        pool.allocate("b").unwrap();
+        pool.exclude("10.0.0.0/30".parse().unwrap());
+        assert_eq!(pool.stats(), Stats {
+            total: 2,
+            excluded: 2,
+            reserved: 0,
+            leased: 0,
+            free: 0,
+        });
+        assert_eq!(pool.stats().utilization(), 1.0);
+        assert!(pool.lease_of(ip("10.0.0.2")).is_none());
+        assert!(matches!(pool.renew("b"), Err(PoolError::UnknownClient(_))));
+        assert!(matches!(pool.allocate("p"), Err(PoolError::Exhausted)));
+    }
+
+    #[test]
+    fn big_exclusions_are_skipped() {
+        let (mut split, _) = pool("2001:db8::/63");
+        split.exclude("2001:db8::/65".parse().unwrap());
+        split.exclude("2001:db8:0:0:8000::/65".parse().unwrap());
+        split.exclude("2001:db8:0:1::/64".parse().unwrap());
+        assert!(matches!(split.allocate("a"), Err(PoolError::Exhausted)));
+
+        let (mut nested, _) = pool("2001:db8::/63");
+        nested.exclude("2001:db8::/64".parse().unwrap());
+        nested.exclude("2001:db8::/65".parse().unwrap());
+        nested.exclude("2001:db8:0:1::/66".parse().unwrap());
+        assert_eq!(
+            nested.allocate("a").unwrap().address,
+            ip("2001:db8:0:1:4000::")
+        );
+    }
+
+    #[test]
+    fn huge_lease_times_saturate() {
+        let text: &str = "lease-time 18446744073709551615\n\
+            range 10.0.0.0/30\n";
+        let clock: ManualClock = ManualClock::new(START);
+        let mut pool: Pool<ManualClock> =
+            Pool::parse(text, clock.clone()).unwrap();
+        assert_eq!(pool.allocate("a").unwrap().expires, u64::MAX);
+        clock.advance(u64::MAX);
+        assert_eq!(pool.renew("a").unwrap().expires, u64::MAX);
+        assert!(pool.lease_of(ip("10.0.0.1")).is_none());
+    }
+
+    #[test]
+    fn bad_state_file() {
+        let err = Pool::parse("range 10.0.0.0/8\nlease 10.0.0.1 a soon\n",
+            ManualClock::new(0));
+        assert!(matches!(err, Err(PoolError::Parse { line: 2, .. })));
+    }
+
+    #[test]
+    fn state_file_must_fit_the_pool() {
+        let bad: [&str; 7] = [
+            "range 10.0.0.0/30\nreserve a 10.0.1.1\n",
+            "range 10.0.0.0/30\nexclude 10.0.0.1/32\nreserve a 10.0.0.1\n",
+            "range 10.0.0.0/30\nreserve a 10.0.0.1\nreserve a 10.0.0.2\n",
+            "range 10.0.0.0/30\nlease 10.0.0.3 a 10\n",
+            "range 10.0.0.0/30\nlease 10.0.0.1 a 10\nlease 10.0.0.2 a 10\n",
+            "range 10.0.0.0/30\nlease 10.0.0.1 a 10\nlease 10.0.0.1 b 10\n",
+            "range 10.0.0.0/30\nreserve a 10.0.0.1\nlease 10.0.0.1 b 10\n",
+        ];
+        for text in bad {
+            let err = Pool::parse(text, ManualClock::new(0));
+            assert!(
+                matches!(err, Err(PoolError::Parse { .. })),
+                "{text:?}"
+            );
+        }
+        let text: &str = "range 10.0.0.0/30\nreserve a 10.0.0.1\n\
+            lease 10.0.0.1 a 10\nlease 10.0.0.2 b 10\n";
+        let pool: Pool<ManualClock> =
+            Pool::parse(text, ManualClock::new(0)).unwrap();
+        assert_eq!(pool.stats().leased, 1);
+    }
+}
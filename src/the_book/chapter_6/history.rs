@@ -0,0 +1,559 @@
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+
+use super::msg::{Message, ParseError, State};
+use crate::layout::{layout_of, Registry};
+
+
+/*
+* Undo and redo for messages applied to a State. Every message is stored
+* with the inverse that puts the state back the way it was, worked out
+* from the state just before the message ran:
+*
+*   move          put the cursor back (moves saturate, so -x, -y would
+*                 not always land in the same place)
+*   color         put the old color back
+*   write         cut the text back to its old length
+*   quit          start running again
+*
+* A message sent after a Quit does nothing, so its inverse does nothing.
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub enum Inverse {
+    SetCursor((i32, i32)),
+    SetColor((u8, u8, u8)),
+    Truncate(usize),
+    Resume,
+    Nothing,
+}
+
+impl Inverse {
+
+    pub fn of(message: &Message, state: &State) -> Inverse {
+        if !state.running {
+            return Inverse::Nothing;
+        }
+        match message {
+            Message::Quit => Inverse::Resume,
+            Message::Move { .. } => Inverse::SetCursor(state.cursor),
+            Message::Write(_) => Inverse::Truncate(state.text.len()),
+            Message::ChangeColor(..) => Inverse::SetColor(state.color),
+        }
+    }
+
+    pub fn apply(&self, state: &mut State) {
+        match self {
+            Inverse::SetCursor(cursor) => state.cursor = *cursor,
+            Inverse::SetColor(color) => state.color = *color,
+            Inverse::Truncate(len) => state.text.truncate(*len),
+            Inverse::Resume => state.running = true,
+            Inverse::Nothing => {}
+        }
+    }
+}
+
+
+/*
+* The history is a tree rather than a list: sending a message after an
+* undo starts a new branch, and the undone messages stay behind on the old
+* one. Node 0 is the starting point and has no message. Each node
+* remembers which child redo should go to (the newest one, or the one
+* most recently undone out of), and `switch_branch` changes that.
+*
+* Undo and redo each cost one message. Jumping back a long way at once
+* would mean running a long line of inverses, so every `interval`
+* messages deep a copy of the state is kept, and `undo_many` rebuilds the
+* state from the nearest copy instead.
+*/
+#[derive(Debug, Clone)]
+struct Node {
+    entry: Option<(Message, Inverse)>,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    redo: Option<usize>,
+    depth: usize,
+}
+
+pub struct History {
+    nodes: Vec<Node>,
+    current: usize,
+    state: State,
+    snapshots: HashMap<usize, State>,
+    interval: usize,
+}
+
+impl History {
+
+    // A snapshot is kept every `interval` messages; 0 is taken as 1
+    pub fn new(state: State, interval: usize) -> History {
+        let root = Node {
+            entry: None,
+            parent: None,
+            children: Vec::new(),
+            redo: None,
+            depth: 0,
+        };
+        History {
+            nodes: vec![root],
+            current: 0,
+            snapshots: HashMap::from([(0, state.clone())]),
+            state,
+            interval: interval.max(1),
+        }
+    }
+
+    pub fn state(&self) -> &State {
+        &self.state
+    }
+
+    // How many messages deep the current state is
+    pub fn depth(&self) -> usize {
+        self.nodes[self.current].depth
+    }
+
+    pub fn can_undo(&self) -> bool {
+        self.current != 0
+    }
+
+    pub fn can_redo(&self) -> bool {
+        self.nodes[self.current].redo.is_some()
+    }
+
+    // How many branches redo could follow from here
+    pub fn branches(&self) -> usize {
+        self.nodes[self.current].children.len()
+    }
+
+    pub fn apply(&mut self, message: Message) {
+        let inverse: Inverse = Inverse::of(&message, &self.state);
+        message.call(&mut self.state);
+
+        let id: usize = self.nodes.len();
+        let depth: usize = self.depth() + 1;
+        self.nodes.push(Node {
+            entry: Some((message, inverse)),
+            parent: Some(self.current),
+            children: Vec::new(),
+            redo: None,
+            depth,
+        });
+        let parent: &mut Node = &mut self.nodes[self.current];
+        parent.children.push(id);
+        parent.redo = Some(id);
+        self.current = id;
+
+        if depth.is_multiple_of(self.interval) {
+            self.snapshots.insert(id, self.state.clone());
+        }
+    }
+
+    // Returns the message that was undone
+    pub fn undo(&mut self) -> Option<&Message> {
+        let id: usize = self.current;
+        let node: &Node = &self.nodes[id];
+        let (parent, (_, inverse)) = (node.parent?, node.entry.as_ref()?);
+        inverse.apply(&mut self.state);
+        self.nodes[parent].redo = Some(id);
+        self.current = parent;
+        self.nodes[id].entry.as_ref().map(|(message, _)| message)
+    }
+
+    // Returns the message that was redone
+    pub fn redo(&mut self) -> Option<&Message> {
+        let id: usize = self.nodes[self.current].redo?;
+        let (message, _) = self.nodes[id].entry.as_ref()?;
+        message.call(&mut self.state);
+        self.current = id;
+        Some(message)
+    }
+
+    // Steps back `count` messages (or as far as possible) and returns how
+    // many that was. Long jumps start over from the nearest snapshot.
+    pub fn undo_many(&mut self, count: usize) -> usize {
+        let count: usize = count.min(self.depth());
+        if count <= self.interval {
+            for _ in 0..count {
+                self.undo();
+            }
+            return count;
+        }
+
+        let mut target: usize = self.current;
+        for _ in 0..count {
+            let parent: usize = self.nodes[target].parent.unwrap();
+            self.nodes[parent].redo = Some(target);
+            target = parent;
+        }
+        self.state = self.state_at(target);
+        self.current = target;
+        count
+    }
+
+    pub fn redo_many(&mut self, count: usize) -> usize {
+        (0..count).take_while(|_| self.redo().is_some()).count()
+    }
+
+    // Points redo at the `which`th branch from here, oldest first
+    pub fn switch_branch(&mut self, which: usize) -> bool {
+        let node: &mut Node = &mut self.nodes[self.current];
+        match node.children.get(which) {
+            Some(&child) => {
+                node.redo = Some(child);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Rebuilds the state at a node from the nearest snapshot above it
+    fn state_at(&self, id: usize) -> State {
+        let mut path: Vec<usize> = Vec::new();
+        let mut at: usize = id;
+        while !self.snapshots.contains_key(&at) {
+            path.push(at);
+            at = self.nodes[at].parent.unwrap();
+        }
+        let mut state: State = self.snapshots[&at].clone();
+        for &step in path.iter().rev() {
+            if let Some((message, _)) = &self.nodes[step].entry {
+                message.call(&mut state);
+            }
+        }
+        state
+    }
+
+    /*
+    * One line per message from the start, through the current position
+    * (marked with >) and on along the redo path. Messages past the
+    * current position are marked as undone, and a node with other
+    * branches beside it says how many.
+    */
+    pub fn lines(&self) -> Vec<String> {
+        let mut path: Vec<usize> = Vec::new();
+        let mut at: usize = self.current;
+        while let Some(parent) = self.nodes[at].parent {
+            path.push(at);
+            at = parent;
+        }
+        path.reverse();
+        let mut at: usize = self.current;
+        while let Some(next) = self.nodes[at].redo {
+            path.push(next);
+            at = next;
+        }
+
+        let mut lines: Vec<String> = Vec::new();
+        if self.current == 0 {
+            lines.push(String::from(">    0  (start)"));
+        }
+        for id in path {
+            let node: &Node = &self.nodes[id];
+            let Some((message, _)) = &node.entry else { continue };
+            let marker: char = if id == self.current { '>' } else { ' ' };
+            let mut line: String =
+                format!("{marker} {:>4}  {message}", node.depth);
+            if node.depth > self.depth() {
+                line.push_str("  (undone)");
+            }
+            let siblings: usize = node.parent
+                .map_or(0, |parent| self.nodes[parent].children.len());
+            if siblings > 1 {
+                line.push_str(&format!("  [{} branches]", siblings));
+            }
+            lines.push(line);
+        }
+        lines
+    }
+}
+
+
+/*
+* What the shell reads, one per line: any message, or
+*
+*   undo [N]      step back one (or N) messages
+*   redo [N]      step forward again along the current branch
+*   branch I      make redo follow the Ith branch from here (0 is oldest)
+*   history       list the messages with the current position marked
+*   state         print the current state
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Message(Message),
+    Undo(usize),
+    Redo(usize),
+    Branch(usize),
+    History,
+    State,
+}
+
+impl Command {
+
+    pub fn parse(line: &str) -> Result<Command, ParseError> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let count = |command: &'static str, default: Option<usize>| {
+            match (&words[1..], default) {
+                ([], Some(default)) => Ok(default),
+                ([n], _) => n
+                    .parse()
+                    .map_err(|_| ParseError::InvalidNumber(n.to_string())),
+                (args, _) => Err(ParseError::WrongArgCount {
+                    command,
+                    expected: 1,
+                    found: args.len(),
+                }),
+            }
+        };
+        match words.first() {
+            Some(&"undo") => count("undo", Some(1)).map(Command::Undo),
+            Some(&"redo") => count("redo", Some(1)).map(Command::Redo),
+            Some(&"branch") => count("branch", None).map(Command::Branch),
+            Some(&"history") if words.len() == 1 => Ok(Command::History),
+            Some(&"state") if words.len() == 1 => Ok(Command::State),
+            _ => Message::parse(line).map(Command::Message),
+        }
+    }
+}
+
+
+// Runs commands from `input` until it runs out, writing what each one
+// says to `output`. Bad lines are reported and skipped.
+pub fn shell(
+    input: impl BufRead,
+    mut output: impl Write,
+    interval: usize,
+) -> io::Result<History> {
+    let mut history: History = History::new(State::new(), interval);
+    for (i, line) in input.lines().enumerate() {
+        let line: String = line?;
+        let trimmed: &str = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        let command: Command = match Command::parse(&line) {
+            Ok(command) => command,
+            Err(err) => {
+                writeln!(output, "line {}: {err}", i + 1)?;
+                continue;
+            }
+        };
+        match command {
+            Command::Message(message) => history.apply(message),
+            Command::Undo(count) => {
+                let done: usize = history.undo_many(count);
+                writeln!(output, "undid {done}")?;
+            }
+            Command::Redo(count) => {
+                let done: usize = history.redo_many(count);
+                writeln!(output, "redid {done}")?;
+            }
+            Command::Branch(which) => {
+                if !history.switch_branch(which) {
+                    writeln!(
+                        output,
+                        "line {}: no branch {which} (there are {})",
+                        i + 1,
+                        history.branches()
+                    )?;
+                }
+            }
+            Command::History => {
+                for entry in history.lines() {
+                    writeln!(output, "{entry}")?;
+                }
+            }
+            Command::State => {
+                let state: &State = history.state();
+                let (r, g, b) = state.color;
+                writeln!(
+                    output,
+                    "cursor {:?}, color #{r:02x}{g:02x}{b:02x}, text {:?}{}",
+                    state.cursor,
+                    state.text,
+                    if state.running { "" } else { ", stopped" }
+                )?;
+            }
+        }
+    }
+    Ok(history)
+}
+
+
+pub fn run() {
+    println!("\nHistory:");
+    let script: &str = "move 3 4\n\
+        write hello\n\
+        color 255 0 0\n\
+        undo 2\n\
+        write howdy\n\
+        history\n\
+        undo\n\
+        branch 0\n\
+        redo 2\n\
+        history\n\
+        state\n";
+    let mut out: Vec<u8> = Vec::new();
+    shell(script.as_bytes(), &mut out, 4).unwrap();
+    for line in String::from_utf8(out).unwrap().lines() {
+        println!("  {line}");
+    }
+}
+
+
+pub fn register_layouts(registry: &mut Registry) {
+    registry.register("Chapter 6", layout_of!(Inverse));
+    registry.register(
+        "Chapter 6",
+        layout_of!(History { nodes, current, state, snapshots, interval }),
+    );
+    registry.register("Chapter 6", layout_of!(Command));
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    fn msg(line: &str) -> Message {
+        line.parse().unwrap()
+    }
+
+    fn random_message(rng: &mut StdRng) -> Message {
+        match rng.gen_range(0..10) {
+            0 => Message::Quit,
+            1..=4 => Message::Move {
+                x: rng.gen_range(-5..5),
+                y: if rng.gen_bool(0.1) { i32::MAX } else { rng.gen() },
+            },
+            5..=7 => Message::Write(String::from("ab")),
+            _ => Message::ChangeColor(
+                rng.gen_range(0..256),
+                rng.gen_range(0..256),
+                rng.gen_range(0..256),
+            ),
+        }
+    }
+
+    #[test]
+    fn undo_restores_each_earlier_state() {
+        let mut history: History = History::new(State::new(), 3);
+        let mut states: Vec<State> = vec![history.state().clone()];
+        let lines: [&str; 5] =
+            ["move 3 4", "color 1 2 3", "write hi", "quit", "move 1 1"];
+        for line in lines {
+            history.apply(msg(line));
+            states.push(history.state().clone());
+        }
+        assert!(!history.state().running);
+        while history.can_undo() {
+            states.pop();
+            history.undo();
+            assert_eq!(history.state(), states.last().unwrap());
+        }
+        assert!(history.undo().is_none());
+        assert_eq!(history.redo(), Some(&msg("move 3 4")));
+        assert_eq!(history.redo_many(10), 4);
+        assert!(!history.state().running);
+    }
+
+    #[test]
+    fn saturated_moves_undo_exactly() {
+        let mut history: History = History::new(State::new(), 10);
+        history.apply(Message::Move { x: i32::MAX, y: 0 });
+        history.apply(Message::Move { x: 5, y: 0 });
+        assert_eq!(history.state().cursor, (i32::MAX, 0));
+        history.undo();
+        history.undo();
+        assert_eq!(history.state().cursor, (0, 0));
+    }
+
+    #[test]
+    fn new_message_after_undo_starts_a_branch() {
+        let mut history: History = History::new(State::new(), 10);
+        history.apply(msg("write a"));
+        history.apply(msg("write b"));
+        history.undo();
+        history.apply(msg("write c"));
+        assert_eq!(history.state().text, "ac");
+        assert!(!history.can_redo());
+
+        history.undo();
+        assert_eq!(history.branches(), 2);
+        assert_eq!(history.redo(), Some(&msg("write c")));
+        history.undo();
+        assert!(history.switch_branch(0));
+        assert_eq!(history.redo(), Some(&msg("write b")));
+        assert_eq!(history.state().text, "ab");
+        assert!(!history.switch_branch(2));
+    }
+
+    #[test]
+    fn long_undo_from_snapshot_matches_step_by_step() {
+        let mut rng: StdRng = StdRng::seed_from_u64(1);
+        let messages: Vec<Message> =
+            (0..200).map(|_| random_message(&mut rng)).collect();
+        for interval in [1, 7, 50, 1000] {
+            let mut jumping: History = History::new(State::new(), interval);
+            let mut stepping: History = History::new(State::new(), 1000);
+            for message in &messages {
+                jumping.apply(message.clone());
+                stepping.apply(message.clone());
+            }
+            for count in [1, 20, 64, 3, 200] {
+                assert_eq!(jumping.undo_many(count), stepping.undo_many(count));
+                assert_eq!(jumping.state(), stepping.state());
+                assert_eq!(jumping.redo_many(count / 2), count / 2);
+                stepping.redo_many(count / 2);
+                assert_eq!(jumping.state(), stepping.state());
+            }
+        }
+    }
+
+    #[test]
+    fn random_sessions_match_a_fresh_replay() {
+        let mut rng: StdRng = StdRng::seed_from_u64(2);
+        let mut history: History = History::new(State::new(), 5);
+        for _ in 0..2_000 {
+            match rng.gen_range(0..6) {
+                0 => {
+                    history.undo();
+                }
+                1 => {
+                    history.redo();
+                }
+                2 => {
+                    history.undo_many(rng.gen_range(0..30));
+                }
+                3 => {
+                    let which: usize = rng.gen_range(0..3);
+                    history.switch_branch(which);
+                }
+                _ => history.apply(random_message(&mut rng)),
+            }
+            assert_eq!(history.state(), &history.state_at(history.current));
+        }
+    }
+
+    #[test]
+    fn shell_commands() {
+        let script: &str = "write a\nwrite b\nundo\nwrite c\nundo\n\
+            history\nbranch 0\nredo\nstate\nundo x\nbranch 5\n";
+        let mut out: Vec<u8> = Vec::new();
+        let history: History = shell(script.as_bytes(), &mut out, 2).unwrap();
+        assert_eq!(history.state().text, "ab");
+        let expected: [&str; 7] = [
+            "undid 1",
+            "undid 1",
+            ">    1  write a",
+            "     2  write c  (undone)  [2 branches]",
+            "redid 1",
+            "cursor (0, 0), color #000000, text \"ab\"",
+            "line 10: \"x\" is not a whole number",
+        ];
+        let out: String = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines[..7], expected);
+        assert_eq!(lines[7], "line 11: no branch 5 (there are 0)");
+    }
+}
@@ -1,11 +1,582 @@
-enum IpAddr {
-    V4(String),
-    V6(String)
+use std::fmt::{self, Write};
+use std::net;
+use std::str::FromStr;
+
+use crate::layout::{layout_of, Registry};
+
+
+/*
+* The variants hold the address itself rather than its text, so an IpAddr
+* can only ever be a real address. "banana" fails to parse instead of being
+* stored.
+*
+* V6 keeps the eight 16-bit groups in the order they're written.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum IpAddr {
+    V4([u8; 4]),
+    V6([u16; 8]),
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseError {
+    Empty,
+    InvalidChar(char),
+    WrongOctetCount(usize),
+    EmptyOctet,
+    OctetTooLarge(String),
+    LeadingZero(String),
+    EmptyGroup,
+    GroupTooLong(String),
+    TooManyGroups,
+    TooFewGroups,
+    MultipleDoubleColons,
+    MisplacedIpv4,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::Empty => write!(f, "address is empty"),
+            ParseError::InvalidChar(ch) => {
+                write!(f, "{ch:?} can't appear in an IP address")
+            }
+            ParseError::WrongOctetCount(count) => {
+                write!(f, "IPv4 needs 4 octets, found {count}")
+            }
+            ParseError::EmptyOctet => {
+                write!(f, "IPv4 address has an empty octet")
+            }
+            ParseError::OctetTooLarge(octet) => {
+                write!(f, "octet {octet} is larger than 255")
+            }
+            ParseError::LeadingZero(octet) => write!(
+                f,
+                "octet {octet} has a leading zero, which some tools read as \
+                octal"
+            ),
+            ParseError::EmptyGroup => {
+                write!(f, "IPv6 address has an empty group (a stray colon?)")
+            }
+            ParseError::GroupTooLong(group) => {
+                write!(f, "group {group} has more than 4 hex digits")
+            }
+            ParseError::TooManyGroups => {
+                write!(f, "IPv6 address has more than 8 groups")
+            }
+            ParseError::TooFewGroups => {
+                write!(f, "IPv6 address has fewer than 8 groups and no ::")
+            }
+            ParseError::MultipleDoubleColons => {
+                write!(f, ":: can only appear once")
+            }
+            ParseError::MisplacedIpv4 => write!(
+                f,
+                "an embedded IPv4 address can only be the last 32 bits"
+            ),
+        }
+    }
+}
+
+
+impl IpAddr {
+
+    pub fn parse(text: &str) -> Result<IpAddr, ParseError> {
+        if text.is_empty() {
+            return Err(ParseError::Empty);
+        }
+        if text.contains(':') {
+            parse_v6(text).map(IpAddr::V6)
+        } else {
+            parse_v4(text).map(IpAddr::V4)
+        }
+    }
+
+    pub fn is_ipv4(&self) -> bool {
+        matches!(self, IpAddr::V4(_))
+    }
+
+    pub fn is_ipv6(&self) -> bool {
+        matches!(self, IpAddr::V6(_))
+    }
+
+    // 0.0.0.0 and ::
+    pub fn is_unspecified(&self) -> bool {
+        match self {
+            IpAddr::V4(octets) => *octets == [0; 4],
+            IpAddr::V6(groups) => *groups == [0; 8],
+        }
+    }
+
+    // 127.0.0.0/8 and ::1
+    pub fn is_loopback(&self) -> bool {
+        match self {
+            IpAddr::V4(octets) => octets[0] == 127,
+            IpAddr::V6(groups) => *groups == [0, 0, 0, 0, 0, 0, 0, 1],
+        }
+    }
+
+    // RFC 1918 for IPv4, and unique local addresses (fc00::/7) for IPv6
+    pub fn is_private(&self) -> bool {
+        match self {
+            IpAddr::V4([10, ..]) => true,
+            IpAddr::V4([172, b, ..]) => (16..=31).contains(b),
+            IpAddr::V4([192, 168, ..]) => true,
+            IpAddr::V4(_) => false,
+            IpAddr::V6(groups) => groups[0] & 0xfe00 == 0xfc00,
+        }
+    }
+
+    // 169.254.0.0/16 and fe80::/10
+    pub fn is_link_local(&self) -> bool {
+        match self {
+            IpAddr::V4(octets) => octets[0] == 169 && octets[1] == 254,
+            IpAddr::V6(groups) => groups[0] & 0xffc0 == 0xfe80,
+        }
+    }
+
+    // 224.0.0.0/4 and ff00::/8
+    pub fn is_multicast(&self) -> bool {
+        match self {
+            IpAddr::V4(octets) => octets[0] & 0xf0 == 224,
+            IpAddr::V6(groups) => groups[0] & 0xff00 == 0xff00,
+        }
+    }
+
+    // Ranges reserved for examples: the three IPv4 TEST-NETs (RFC 5737),
+    // and 2001:db8::/32 (RFC 3849) and 3fff::/20 (RFC 9637) for IPv6.
+    pub fn is_documentation(&self) -> bool {
+        match self {
+            IpAddr::V4([192, 0, 2, _]) => true,
+            IpAddr::V4([198, 51, 100, _]) => true,
+            IpAddr::V4([203, 0, 113, _]) => true,
+            IpAddr::V4(_) => false,
+            IpAddr::V6(groups) => {
+                (groups[0] == 0x2001 && groups[1] == 0x0db8)
+                    || (groups[0] == 0x3fff && groups[1] & 0xf000 == 0)
+            }
+        }
+    }
+
+    // The IPv4 address inside an IPv4-mapped IPv6 address (::ffff:a.b.c.d)
+    pub fn to_ipv4_mapped(self) -> Option<[u8; 4]> {
+        match self {
+            IpAddr::V6([0, 0, 0, 0, 0, 0xffff, high, low]) => {
+                let [a, b] = high.to_be_bytes();
+                let [c, d] = low.to_be_bytes();
+                Some([a, b, c, d])
+            }
+            _ => None,
+        }
+    }
+
+    // 32 or 128
+    pub fn bit_len(self) -> u32 {
+        match self {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        }
+    }
+
+    // The address as one number, which is what subnet math works on
+    pub fn to_bits(self) -> u128 {
+        match self {
+            IpAddr::V4(octets) => u32::from_be_bytes(octets) as u128,
+            IpAddr::V6(groups) => {
+                groups.iter().fold(0, |bits, g| bits << 16 | *g as u128)
+            }
+        }
+    }
+
+    // Builds an address of the same family as self. Bits above the
+    // family's width are ignored.
+    pub fn with_bits(self, bits: u128) -> IpAddr {
+        match self {
+            IpAddr::V4(_) => IpAddr::V4((bits as u32).to_be_bytes()),
+            IpAddr::V6(_) => {
+                let mut groups: [u16; 8] = [0; 8];
+                for (i, group) in groups.iter_mut().enumerate() {
+                    *group = (bits >> (112 - 16 * i)) as u16;
+                }
+                IpAddr::V6(groups)
+            }
+        }
+    }
+}
+
+
+fn parse_v4(text: &str) -> Result<[u8; 4], ParseError> {
+    if let Some(ch) = text.chars()
+        .find(|c| !(c.is_ascii_digit() || *c == '.'))
+    {
+        return Err(ParseError::InvalidChar(ch));
+    }
+    let parts: Vec<&str> = text.split('.').collect();
+    if parts.len() != 4 {
+        return Err(ParseError::WrongOctetCount(parts.len()));
+    }
+    let mut octets: [u8; 4] = [0; 4];
+    for (octet, part) in octets.iter_mut().zip(parts) {
+        if part.is_empty() {
+            return Err(ParseError::EmptyOctet);
+        }
+        if part.len() > 1 && part.starts_with('0') {
+            return Err(ParseError::LeadingZero(part.to_string()));
+        }
+        *octet = part.parse()
+            .map_err(|_| ParseError::OctetTooLarge(part.to_string()))?;
+    }
+    Ok(octets)
+}
+
+
+/*
+* Groups are up to four hex digits separated by colons. One run of zero
+* groups can be written as "::", and the last two groups can be written as
+* a dotted IPv4 address instead:
+*
+*   2001:db8::1          2001:db8:0:0:0:0:0:1
+*   ::ffff:192.0.2.1     0:0:0:0:0:ffff:c000:201
+*/
+fn parse_v6(text: &str) -> Result<[u16; 8], ParseError> {
+    if let Some(ch) = text.chars()
+        .find(|c| !(c.is_ascii_hexdigit() || *c == ':' || *c == '.'))
+    {
+        return Err(ParseError::InvalidChar(ch));
+    }
+    let (head, tail): (&str, Option<&str>) = match text.split_once("::") {
+        Some((_, rest)) if rest.contains("::") => {
+            return Err(ParseError::MultipleDoubleColons);
+        }
+        Some((head, tail)) => (head, Some(tail)),
+        None => (text, None),
+    };
+
+    let head_groups: Vec<u16> = parse_groups(head, tail.is_none())?;
+    let tail_groups: Vec<u16> = match tail {
+        Some(tail) => parse_groups(tail, true)?,
+        None => Vec::new(),
+    };
+    let used: usize = head_groups.len() + tail_groups.len();
+    let mut groups: [u16; 8] = [0; 8];
+    match tail {
+        None if used < 8 => return Err(ParseError::TooFewGroups),
+        None if used > 8 => return Err(ParseError::TooManyGroups),
+        // "::" stands for at least one zero group
+        Some(_) if used > 7 => return Err(ParseError::TooManyGroups),
+        _ => {}
+    }
+    groups[..head_groups.len()].copy_from_slice(&head_groups);
+    groups[8 - tail_groups.len()..].copy_from_slice(&tail_groups);
+    Ok(groups)
+}
+
+// Colon-separated groups on one side of a "::". An empty string is no
+// groups at all. A dotted IPv4 address is only allowed as the last piece,
+// and only when nothing follows this side.
+fn parse_groups(
+    text: &str,
+    ends_address: bool,
+) -> Result<Vec<u16>, ParseError> {
+    let mut groups: Vec<u16> = Vec::new();
+    if text.is_empty() {
+        return Ok(groups);
+    }
+    let pieces: Vec<&str> = text.split(':').collect();
+    for (i, piece) in pieces.iter().enumerate() {
+        if piece.contains('.') {
+            if !ends_address || i + 1 != pieces.len() {
+                return Err(ParseError::MisplacedIpv4);
+            }
+            let [a, b, c, d] = parse_v4(piece)?;
+            groups.push(u16::from_be_bytes([a, b]));
+            groups.push(u16::from_be_bytes([c, d]));
+            continue;
+        }
+        if piece.is_empty() {
+            return Err(ParseError::EmptyGroup);
+        }
+        if piece.len() > 4 {
+            return Err(ParseError::GroupTooLong(piece.to_string()));
+        }
+        groups.push(u16::from_str_radix(piece, 16).unwrap());
+    }
+    Ok(groups)
+}
+
+
+impl FromStr for IpAddr {
+    type Err = ParseError;
+
+    fn from_str(text: &str) -> Result<IpAddr, ParseError> {
+        IpAddr::parse(text)
+    }
+}
+
+
+/*
+* IPv6 text follows RFC 5952 so the same address always prints the same
+* way: lowercase hex without leading zeros, and the longest run of two or
+* more zero groups (the first one, on a tie) shortened to "::". IPv4-mapped
+* addresses keep their dotted tail, as in ::ffff:192.0.2.1.
+*
+* The text is built first and then padded, so {ip:<22} lines up.
+*/
+impl fmt::Display for IpAddr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut text: String = String::new();
+        self.write_text(&mut text)?;
+        f.pad(&text)
+    }
+}
+
+impl IpAddr {
+
+    fn write_text(&self, f: &mut String) -> fmt::Result {
+        let groups: &[u16; 8] = match self {
+            IpAddr::V4([a, b, c, d]) => return write!(f, "{a}.{b}.{c}.{d}"),
+            IpAddr::V6(groups) => groups,
+        };
+        if let Some([a, b, c, d]) = self.to_ipv4_mapped() {
+            return write!(f, "::ffff:{a}.{b}.{c}.{d}");
+        }
+
+        let (mut best_start, mut best_len) = (0, 0);
+        let mut i: usize = 0;
+        while i < 8 {
+            let start: usize = i;
+            while i < 8 && groups[i] == 0 {
+                i += 1;
+            }
+            if i - start > best_len {
+                (best_start, best_len) = (start, i - start);
+            }
+            i += 1;
+        }
+
+        let write_groups = |f: &mut String, part: &[u16]| {
+            for (i, group) in part.iter().enumerate() {
+                if i > 0 {
+                    write!(f, ":")?;
+                }
+                write!(f, "{group:x}")?;
+            }
+            Ok(())
+        };
+        if best_len < 2 {
+            return write_groups(f, groups);
+        }
+        write_groups(f, &groups[..best_start])?;
+        write!(f, "::")?;
+        write_groups(f, &groups[best_start + best_len..])
+    }
+}
+
+
+impl From<net::Ipv4Addr> for IpAddr {
+    fn from(ip: net::Ipv4Addr) -> IpAddr {
+        IpAddr::V4(ip.octets())
+    }
+}
+
+impl From<net::Ipv6Addr> for IpAddr {
+    fn from(ip: net::Ipv6Addr) -> IpAddr {
+        IpAddr::V6(ip.segments())
+    }
+}
+
+impl From<net::IpAddr> for IpAddr {
+    fn from(ip: net::IpAddr) -> IpAddr {
+        match ip {
+            net::IpAddr::V4(ip) => ip.into(),
+            net::IpAddr::V6(ip) => ip.into(),
+        }
+    }
+}
+
+impl From<IpAddr> for net::IpAddr {
+    fn from(ip: IpAddr) -> net::IpAddr {
+        match ip {
+            IpAddr::V4(octets) => net::IpAddr::V4(octets.into()),
+            IpAddr::V6(groups) => net::IpAddr::V6(groups.into()),
+        }
+    }
 }
 
+
 pub fn run() {
     println!("\n -- Enums and Pattern Matching --");
-    let home = IpAddr::V4(String::from("127.0.0.1"));
-    let loopback = IpAddr::V6(String::from("::1"));
+    let home: IpAddr = IpAddr::V4([127, 0, 0, 1]);
+    let loopback: IpAddr = IpAddr::V6([0, 0, 0, 0, 0, 0, 0, 1]);
+    println!("  {home} and {loopback} are both loopback: {}",
+        home.is_loopback() && loopback.is_loopback());
+
+    for text in [
+        "192.168.1.20",
+        "2001:DB8:0:0:1:0:0:1",
+        "2001:db8::0:1",
+        "fe80::1:2",
+        "::ffff:198.51.100.7",
+        "ff02::1",
+        "0:0:0:0:0:0:0:0",
+        "banana",
+        "10.0.0.256",
+        "10.01.0.1",
+        "1::2::3",
+        "1:2:3:4:5:6:7:8:9",
+        "1.2.3.4::",
+    ] {
+        match IpAddr::parse(text) {
+            Ok(ip) => {
+                let mut tags: Vec<&str> = Vec::new();
+                for (tag, yes) in [
+                    ("private", ip.is_private()),
+                    ("link-local", ip.is_link_local()),
+                    ("multicast", ip.is_multicast()),
+                    ("documentation", ip.is_documentation()),
+                    ("unspecified", ip.is_unspecified()),
+                ] {
+                    if yes {
+                        tags.push(tag);
+                    }
+                }
+                let std_ip: net::IpAddr = ip.into();
+                println!("  {text:<24} -> {ip:<22} {tags:?} std agrees: {}",
+                    std_ip.to_string() == ip.to_string());
+            }
+            Err(err) => println!("  {text:<24} !! {err}"),
+        }
+    }
 }
 
+
+pub fn register_layouts(registry: &mut Registry) {
+    registry.register("Chapter 6", layout_of!(IpAddr));
+    registry.register("Chapter 6", layout_of!(ParseError));
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    const CASES: usize = 2_000;
+
+    #[test]
+    fn shortens_the_longest_zero_run() {
+        for (groups, text) in [
+            ([0; 8], "::"),
+            ([0, 0, 0, 0, 0, 0, 0, 1], "::1"),
+            ([1, 0, 0, 0, 0, 0, 0, 0], "1::"),
+            ([0x2001, 0xdb8, 0, 0, 1, 0, 0, 1], "2001:db8::1:0:0:1"),
+            ([0x2001, 0xdb8, 0, 1, 0, 0, 0, 1], "2001:db8:0:1::1"),
+            ([0x2001, 0, 0, 1, 0, 0, 0, 1], "2001:0:0:1::1"),
+            ([1, 0, 1, 0, 1, 0, 1, 0], "1:0:1:0:1:0:1:0"),
+            ([0xfe80, 0, 0, 0, 0xabc, 0, 0, 0], "fe80::abc:0:0:0"),
+            ([0xABCD, 0xEF, 1, 2, 3, 4, 5, 6], "abcd:ef:1:2:3:4:5:6"),
+        ] {
+            let ip: IpAddr = IpAddr::V6(groups);
+            assert_eq!(ip.to_string(), text);
+            assert_eq!(IpAddr::parse(text), Ok(ip));
+        }
+    }
+
+    #[test]
+    fn embedded_ipv4() {
+        for (text, groups, shown) in [
+            (
+                "::ffff:192.0.2.1",
+                [0, 0, 0, 0, 0, 0xffff, 0xc000, 0x201],
+                "::ffff:192.0.2.1",
+            ),
+            (
+                "::FFFF:c000:0201",
+                [0, 0, 0, 0, 0, 0xffff, 0xc000, 0x201],
+                "::ffff:192.0.2.1",
+            ),
+            (
+                "64:ff9b::192.0.2.33",
+                [0x64, 0xff9b, 0, 0, 0, 0, 0xc000, 0x221],
+                "64:ff9b::c000:221",
+            ),
+            (
+                "1:2:3:4:5:6:1.2.3.4",
+                [1, 2, 3, 4, 5, 6, 0x102, 0x304],
+                "1:2:3:4:5:6:102:304",
+            ),
+        ] {
+            let ip: IpAddr = IpAddr::parse(text).unwrap();
+            assert_eq!(ip, IpAddr::V6(groups), "{text}");
+            assert_eq!(ip.to_string(), shown);
+        }
+    }
+
+    #[test]
+    fn rejects() {
+        for (text, err) in [
+            ("", ParseError::Empty),
+            (":::", ParseError::EmptyGroup),
+            ("1:::2", ParseError::EmptyGroup),
+            ("1::2::3", ParseError::MultipleDoubleColons),
+            ("::1::", ParseError::MultipleDoubleColons),
+            ("1:2:3", ParseError::TooFewGroups),
+            ("1:2:3:4:5:6:7:8:9", ParseError::TooManyGroups),
+            ("1:2:3:4::5:6:7:8", ParseError::TooManyGroups),
+            ("12345::", ParseError::GroupTooLong(String::from("12345"))),
+            ("g::", ParseError::InvalidChar('g')),
+            ("1.2.3.4::", ParseError::MisplacedIpv4),
+            ("::1.2.3.4:1", ParseError::MisplacedIpv4),
+            ("10.01.0.1", ParseError::LeadingZero(String::from("01"))),
+            ("::ffff:1.2.3.04", ParseError::LeadingZero(String::from("04"))),
+            ("00.0.0.0", ParseError::LeadingZero(String::from("00"))),
+            ("10.0.0.256", ParseError::OctetTooLarge(String::from("256"))),
+            ("10..0.1", ParseError::EmptyOctet),
+            ("10.0.1", ParseError::WrongOctetCount(3)),
+            ("10.0.0.1 ", ParseError::InvalidChar(' ')),
+        ] {
+            assert_eq!(IpAddr::parse(text), Err(err), "{text:?}");
+        }
+    }
+
+    #[test]
+    fn agrees_with_std() {
+        let mut rng: StdRng = StdRng::seed_from_u64(1);
+        for _ in 0..CASES {
+            // Mostly zeros, so there are runs to shorten
+            let groups: [u16; 8] = std::array::from_fn(|_| {
+                if rng.gen_bool(0.5) { 0 } else { rng.gen() }
+            });
+            let ip: IpAddr = IpAddr::V6(groups);
+            let std_ip: net::Ipv6Addr = groups.into();
+            assert_eq!(ip.to_string(), std_ip.to_string());
+            assert_eq!(IpAddr::parse(&std_ip.to_string()), Ok(ip));
+            assert_eq!(net::IpAddr::from(ip), net::IpAddr::V6(std_ip));
+
+            let octets: [u8; 4] = rng.gen();
+            let std_ip: net::Ipv4Addr = octets.into();
+            assert_eq!(IpAddr::V4(octets).to_string(), std_ip.to_string());
+        }
+        for text in [
+            "::", "::1", "1::", "::ffff:1.2.3.4", "1:2:3:4:5:6:7::",
+            "::2:3:4:5:6:7:8", "1:2:3:4:5:6:7:8", "0.0.0.0", "255.255.255.255",
+            ":::", "1::2::3", "1:2:3:4:5:6:7:8:9", "01.2.3.4", "1.2.3",
+            "::1.2.3.4:5", "12345::", "1.2.3.4.5", ":1::", "1::2:",
+        ] {
+            let ours: Option<net::IpAddr> = IpAddr::parse(text).ok()
+                .map(net::IpAddr::from);
+            assert_eq!(ours, text.parse().ok(), "{text}");
+        }
+    }
+
+    #[test]
+    fn display_pads() {
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+        assert_eq!(format!("[{ip:<10}]"), "[10.0.0.1  ]");
+        assert_eq!(format!("[{ip:>10}]"), "[  10.0.0.1]");
+        let ip: IpAddr = "2001:db8::1".parse().unwrap();
+        assert_eq!(format!("[{ip:^15}]"), "[  2001:db8::1  ]");
+    }
+}
@@ -1,10 +1,37 @@
-mod ip_addr;
-mod msg;
-mod coin_sorting;
+use crate::layout::Registry;
+
+pub mod ip_addr;
+pub mod cidr;
+pub mod dhcp;
+pub mod msg;
+pub mod canvas;
+pub mod codec;
+pub mod history;
+pub mod coin_sorting;
+pub mod coin_change;
 mod dice_roll;
 
 pub fn run() {
     dice_roll::dice_rolling();
+    ip_addr::run();
+    cidr::run();
+    dhcp::run();
+    msg::run();
+    canvas::run();
+    codec::run();
+    history::run();
+    coin_change::run();
 }
 
 
+pub fn register_layouts(registry: &mut Registry) {
+    coin_sorting::register_layouts(registry);
+    coin_change::register_layouts(registry);
+    ip_addr::register_layouts(registry);
+    cidr::register_layouts(registry);
+    dhcp::register_layouts(registry);
+    msg::register_layouts(registry);
+    canvas::register_layouts(registry);
+    codec::register_layouts(registry);
+    history::register_layouts(registry);
+}
@@ -1,4 +1,16 @@
-enum Message {
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process;
+use std::str::FromStr;
+
+use super::canvas::Canvas;
+use super::history;
+use crate::layout::{layout_of, Registry};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
     Quit,
     Move { x: i32, y: i32 },
     Write(String),
@@ -6,14 +18,508 @@ enum Message {
 }
 
 
+/*
+* What the messages act on: a cursor that Move shifts by (x, y), the
+* current RGB color, the text written so far, and whether a Quit has been
+* seen. Once the state stops running, further messages are ignored.
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct State {
+    pub cursor: (i32, i32),
+    pub color: (u8, u8, u8),
+    pub text: String,
+    pub running: bool,
+}
+
+impl State {
+
+    pub fn new() -> State {
+        State {
+            cursor: (0, 0),
+            color: (0, 0, 0),
+            text: String::new(),
+            running: true,
+        }
+    }
+
+    // Calls each message in turn, stopping at the first Quit
+    pub fn run(&mut self, messages: &[Message]) {
+        for message in messages {
+            if !self.running {
+                break;
+            }
+            message.call(self);
+        }
+    }
+}
+
+impl Default for State {
+    fn default() -> State {
+        State::new()
+    }
+}
+
+
+impl Message {
+    pub fn call(&self, state: &mut State) {
+        if !state.running {
+            return;
+        }
+        match self {
+            Message::Quit => state.running = false,
+            Message::Move { x, y } => {
+                state.cursor.0 = state.cursor.0.saturating_add(*x);
+                state.cursor.1 = state.cursor.1.saturating_add(*y);
+            }
+            Message::Write(text) => state.text.push_str(text),
+            // Channels outside 0..=255 are clamped
+            Message::ChangeColor(r, g, b) => {
+                state.color = (channel(*r), channel(*g), channel(*b));
+            }
+        }
+    }
+}
+
+fn channel(value: i32) -> u8 {
+    value.clamp(0, 255) as u8
+}
+
+
+/*
+* The text form, one message per line:
+*
+*   move 3 4
+*   color 255 0 0
+*   write hi there
+*   quit
+*
+* The command ends at the first whitespace, and everything after that is
+* the text of a write, spaces included. \n, \r, \t and \\ inside it stand
+* for a newline, a carriage return, a tab and a backslash. In a script,
+* blank lines and lines starting with # are skipped.
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseError {
+    Empty,
+    UnknownCommand(String),
+    WrongArgCount { command: &'static str, expected: usize, found: usize },
+    InvalidNumber(String),
+    ColorOutOfRange(i32),
+    BadEscape(String),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::Empty => write!(f, "empty message"),
+            ParseError::UnknownCommand(command) => write!(
+                f,
+                "unknown command {command:?} (expected move, color, write \
+                or quit)"
+            ),
+            ParseError::WrongArgCount { command, expected, found } => write!(
+                f,
+                "{command} takes {expected} argument(s), found {found}"
+            ),
+            ParseError::InvalidNumber(text) => {
+                write!(f, "{text:?} is not a whole number")
+            }
+            ParseError::ColorOutOfRange(value) => {
+                write!(f, "color channel {value} is outside 0..=255")
+            }
+            ParseError::BadEscape(escape) => {
+                write!(f, "unknown escape {escape:?} in write text")
+            }
+        }
+    }
+}
+
+
+#[derive(Debug)]
+pub enum ScriptError {
+    Io(io::Error),
+    Parse { line: usize, error: ParseError },
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ScriptError::Io(err) => write!(f, "{err}"),
+            ScriptError::Parse { line, error } => {
+                write!(f, "line {line}: {error}")
+            }
+        }
+    }
+}
+
+impl From<io::Error> for ScriptError {
+    fn from(err: io::Error) -> ScriptError {
+        ScriptError::Io(err)
+    }
+}
+
+
 impl Message {
-    fn call(&self) {
-        // Write code here
+
+    pub fn parse(line: &str) -> Result<Message, ParseError> {
+        let line: &str = line.trim_start();
+        let (command, rest) = match line.char_indices()
+            .find(|(_, ch)| ch.is_whitespace())
+        {
+            Some((i, ch)) => (&line[..i], &line[i + ch.len_utf8()..]),
+            None => (line, ""),
+        };
+        if command == "write" {
+            return unescape(rest).map(Message::Write);
+        }
+
+        let args: Vec<&str> = rest.split_whitespace().collect();
+        let check = |command: &'static str, expected: usize| {
+            if args.len() == expected {
+                Ok(())
+            } else {
+                Err(ParseError::WrongArgCount {
+                    command,
+                    expected,
+                    found: args.len(),
+                })
+            }
+        };
+        match command {
+            "" => Err(ParseError::Empty),
+            "quit" => {
+                check("quit", 0)?;
+                Ok(Message::Quit)
+            }
+            "move" => {
+                check("move", 2)?;
+                Ok(Message::Move {
+                    x: number(args[0])?,
+                    y: number(args[1])?,
+                })
+            }
+            "color" => {
+                check("color", 3)?;
+                let mut rgb: [i32; 3] = [0; 3];
+                for (value, arg) in rgb.iter_mut().zip(&args) {
+                    *value = number(arg)?;
+                    if !(0..=255).contains(value) {
+                        return Err(ParseError::ColorOutOfRange(*value));
+                    }
+                }
+                Ok(Message::ChangeColor(rgb[0], rgb[1], rgb[2]))
+            }
+            other => Err(ParseError::UnknownCommand(other.to_string())),
+        }
+    }
+}
+
+fn number(text: &str) -> Result<i32, ParseError> {
+    text.parse().map_err(|_| ParseError::InvalidNumber(text.to_string()))
+}
+
+fn unescape(text: &str) -> Result<String, ParseError> {
+    let mut out: String = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            out.push(ch);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('r') => out.push('\r'),
+            Some('t') => out.push('\t'),
+            Some('\\') => out.push('\\'),
+            Some(other) => {
+                return Err(ParseError::BadEscape(format!("\\{other}")));
+            }
+            None => return Err(ParseError::BadEscape(String::from("\\"))),
+        }
+    }
+    Ok(out)
+}
+
+impl FromStr for Message {
+    type Err = ParseError;
+
+    fn from_str(line: &str) -> Result<Message, ParseError> {
+        Message::parse(line)
+    }
+}
+
+// Writes a message back in the form parse reads
+impl fmt::Display for Message {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Message::Quit => write!(f, "quit"),
+            Message::Move { x, y } => write!(f, "move {x} {y}"),
+            Message::Write(text) => {
+                write!(f, "write ")?;
+                for ch in text.chars() {
+                    match ch {
+                        '\n' => write!(f, "\\n")?,
+                        '\r' => write!(f, "\\r")?,
+                        '\t' => write!(f, "\\t")?,
+                        '\\' => write!(f, "\\\\")?,
+                        _ => write!(f, "{ch}")?,
+                    }
+                }
+                Ok(())
+            }
+            Message::ChangeColor(r, g, b) => write!(f, "color {r} {g} {b}"),
+        }
+    }
+}
+
+
+pub fn parse_script(text: &str) -> Result<Vec<Message>, ScriptError> {
+    let mut messages: Vec<Message> = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let trimmed: &str = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        let message: Message = Message::parse(line.trim_end_matches('\r'))
+            .map_err(|error| ScriptError::Parse { line: i + 1, error })?;
+        messages.push(message);
+    }
+    Ok(messages)
+}
+
+pub fn run_file(path: &Path) -> Result<State, ScriptError> {
+    let messages: Vec<Message> = parse_script(&fs::read_to_string(path)?)?;
+    let mut state: State = State::new();
+    state.run(&messages);
+    Ok(state)
+}
+
+
+// `notes msg run <script>` prints the final state,
+// `notes msg draw <script> <image.ppm|image.svg> [WIDTH HEIGHT]` draws it,
+// and `notes msg shell` reads messages and undo/redo/history from stdin.
+pub fn cli(args: &[String]) {
+    let strs: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
+    let fail = |message: String| -> ! {
+        eprintln!("error: {message}");
+        process::exit(1);
+    };
+    match strs.as_slice() {
+        ["run", path] => match run_file(Path::new(path)) {
+            Ok(state) => print_state(&state),
+            Err(err) => fail(format!("{path}: {err}")),
+        },
+        ["draw", script, image, size @ ..] => {
+            let (width, height) = match size {
+                [] => (256, 256),
+                [w, h] => match (w.parse(), h.parse()) {
+                    (Ok(w), Ok(h)) => (w, h),
+                    _ => fail(format!("bad image size {w} {h}")),
+                },
+                _ => fail(String::from("give both a width and a height")),
+            };
+            let messages: Vec<Message> = fs::read_to_string(script)
+                .map_err(ScriptError::from)
+                .and_then(|text| parse_script(&text))
+                .unwrap_or_else(|err| fail(format!("{script}: {err}")));
+            let mut canvas: Canvas = Canvas::new(width, height)
+                .unwrap_or_else(|err| fail(err.to_string()));
+            canvas.run(&messages);
+            if let Err(err) = canvas.save(Path::new(image)) {
+                fail(format!("{image}: {err}"));
+            }
+        }
+        ["shell"] => {
+            let stdin = io::stdin();
+            if let Err(err) = history::shell(stdin.lock(), io::stdout(), 32) {
+                fail(err.to_string());
+            }
+        }
+        _ => fail(String::from(
+            "usage: notes msg run <script> | \
+            notes msg draw <script> <image.ppm|image.svg> [WIDTH HEIGHT] | \
+            notes msg shell"
+        )),
     }
 }
 
+fn print_state(state: &State) {
+    let (r, g, b) = state.color;
+    println!("Cursor:  {:?}", state.cursor);
+    println!("Color:   #{r:02x}{g:02x}{b:02x}");
+    println!("Text:    {:?}", state.text);
+    println!("Running: {}", state.running);
+}
+
 
 pub fn run() {
     let m = Message::Write(String::from("hello"));
-    m.call();
+    let mut state: State = State::new();
+    m.call(&mut state);
+
+    let script: &str = "# walk right, then up\n\
+        move 3 4\n\
+        color 255 0 0\n\
+        write , world\n\
+        move -1 2\n\
+        quit\n\
+        write this never happens\n";
+    let messages: Vec<Message> = parse_script(script).unwrap();
+    state.run(&messages);
+    println!("\nMessages:");
+    for message in &messages {
+        println!("  {message}");
+    }
+    print_state(&state);
+
+    for bad in ["move 3", "color 256 0 0", "jump 1 2", "move x 1"] {
+        if let Err(err) = Message::parse(bad) {
+            println!("  {bad:?}: {err}");
+        }
+    }
+}
+
+
+pub fn register_layouts(registry: &mut Registry) {
+    registry.register("Chapter 6", layout_of!(Message));
+    registry.register(
+        "Chapter 6",
+        layout_of!(State { cursor, color, text, running }),
+    );
+    registry.register("Chapter 6", layout_of!(ParseError));
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(text: &str) -> Message {
+        Message::Write(text.to_string())
+    }
+
+    #[test]
+    fn parses_each_command() {
+        assert_eq!(Message::parse("quit"), Ok(Message::Quit));
+        assert_eq!(Message::parse("  move -3 4 "), Ok(Message::Move {
+            x: -3,
+            y: 4,
+        }));
+        assert_eq!(
+            Message::parse("color 255 0 17"),
+            Ok(Message::ChangeColor(255, 0, 17))
+        );
+        assert_eq!(Message::parse("write hi there"), Ok(write("hi there")));
+        assert_eq!(Message::parse("write  hi "), Ok(write(" hi ")));
+        assert_eq!(Message::parse("write"), Ok(write("")));
+    }
+
+    #[test]
+    fn command_ends_at_any_whitespace() {
+        assert_eq!(Message::parse("move\t3 4"), Ok(Message::Move {
+            x: 3,
+            y: 4,
+        }));
+        assert_eq!(Message::parse("write\thi"), Ok(write("hi")));
+        assert_eq!(Message::parse("quit\t"), Ok(Message::Quit));
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(Message::parse("   "), Err(ParseError::Empty));
+        assert_eq!(
+            Message::parse("jump 1 2"),
+            Err(ParseError::UnknownCommand(String::from("jump")))
+        );
+        assert_eq!(
+            Message::parse("move 3"),
+            Err(ParseError::WrongArgCount {
+                command: "move",
+                expected: 2,
+                found: 1,
+            })
+        );
+        assert!(matches!(
+            Message::parse("quit now"),
+            Err(ParseError::WrongArgCount { command: "quit", .. })
+        ));
+        assert_eq!(
+            Message::parse("move x 1"),
+            Err(ParseError::InvalidNumber(String::from("x")))
+        );
+        assert_eq!(
+            Message::parse("color 256 0 0"),
+            Err(ParseError::ColorOutOfRange(256))
+        );
+        assert_eq!(
+            Message::parse("write a\\q"),
+            Err(ParseError::BadEscape(String::from("\\q")))
+        );
+    }
+
+    #[test]
+    fn unescapes() {
+        assert_eq!(
+            unescape("a\\nb\\tc\\rd\\\\e").unwrap(),
+            "a\nb\tc\rd\\e"
+        );
+        assert_eq!(unescape("plain").unwrap(), "plain");
+        assert_eq!(
+            unescape("trailing\\"),
+            Err(ParseError::BadEscape(String::from("\\")))
+        );
+    }
+
+    #[test]
+    fn display_round_trips() {
+        let messages: [Message; 6] = [
+            Message::Quit,
+            Message::Move { x: i32::MIN, y: i32::MAX },
+            Message::ChangeColor(0, 128, 255),
+            write(" lead and trail "),
+            write("line\nbreak\ttab\r\nback\\slash"),
+            write("ends with\r"),
+        ];
+        for message in &messages {
+            let text: String = message.to_string();
+            assert_eq!(Message::parse(&text).as_ref(), Ok(message), "{text}");
+        }
+        let script: String = messages.iter()
+            .map(|message| format!("{message}\r\n"))
+            .collect();
+        assert_eq!(parse_script(&script).unwrap(), messages);
+    }
+
+    #[test]
+    fn script_skips_comments_and_blank_lines() {
+        let script: &str = "# setup\n\n  move 1 1\r\n\t\nquit\n";
+        let messages: Vec<Message> = parse_script(script).unwrap();
+        assert_eq!(messages, [Message::Move { x: 1, y: 1 }, Message::Quit]);
+        assert!(matches!(
+            parse_script("quit\n\nmove 1\n"),
+            Err(ScriptError::Parse { line: 3, .. })
+        ));
+    }
+
+    #[test]
+    fn state_runs_until_quit() {
+        let mut state: State = State::new();
+        state.run(&[
+            Message::Move { x: 3, y: 4 },
+            Message::Move { x: i32::MAX, y: -1 },
+            Message::ChangeColor(-5, 300, 7),
+            write("hi"),
+            write(", there"),
+            Message::Quit,
+            write("ignored"),
+        ]);
+        assert_eq!(state, State {
+            cursor: (i32::MAX, 3),
+            color: (0, 255, 7),
+            text: String::from("hi, there"),
+            running: false,
+        });
+        write("still ignored").call(&mut state);
+        assert_eq!(state.text, "hi, there");
+    }
 }
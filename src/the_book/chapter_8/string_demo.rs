@@ -1,3 +1,5 @@
+use crate::the_book::chapter_3::char_inspector;
+
 
 pub fn run() {
     let mut name: String = String::from("");
@@ -10,7 +12,12 @@ pub fn run() {
     let num: u8 = 29;
     name.push_str(&format!("{num}")); 
 
-    // Slice a string 
+    // Slice a string. Byte ranges have to land on char boundaries or the
+    // slice panics, so check first when the text might not be ASCII.
+    if let Err(e) = char_inspector::check_slice(&name, 0..4) {
+        println!("Can't slice {name}: {e}");
+        return;
+    }
     let original: &str = &name[0..4];
 
     // Display the user name, the hard way.
@@ -1,3 +1,5 @@
+use crate::layout::Registry;
+
 pub mod chapter_1;
 pub mod chapter_2;
 pub mod chapter_3;
@@ -11,3 +13,9 @@ pub mod chapter_10;
 pub mod chapter_11;
 
 
+pub fn register_layouts(registry: &mut Registry) {
+    chapter_3::register_layouts(registry);
+    chapter_5::register_layouts(registry);
+    chapter_6::register_layouts(registry);
+    chapter_10::register_layouts(registry);
+}